@@ -0,0 +1,490 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::grid::{Grid, GridBounds, JsonGrid};
+use crate::linear_program::{maximise, LinearProgramSolution};
+use crate::market_specifiers::BackLay;
+use crate::position::{round_stake, CommissionMarkets, JsonBetSpec, JsonPositionBet, PositionBet};
+use crate::rational::Rational;
+
+// Profits within this of zero (per unit staked) count as zero
+const PROFIT_TOLERANCE: f64 = 1e-9;
+// Most times the stakes are combined again for the markets that now make a profit
+const MAX_COMMISSION_ROUNDS: usize = 10;
+
+/// A quotes file: prices on one match from any number of bookmakers (or exchanges)
+#[derive(Deserialize)]
+pub struct JsonQuotesFile {
+    pub quotes: Vec<JsonQuote>,
+}
+
+/// A quoted price: a bet without a stake, with the bookmaker offering it
+#[derive(Deserialize)]
+pub struct JsonQuote {
+    pub bookmaker: Option<String>,
+    #[serde(flatten)]
+    pub bet: JsonBetSpec,
+}
+
+#[derive(Serialize)]
+pub struct JsonQuoteBet {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bookmaker: Option<String>,
+    #[serde(flatten)]
+    pub bet: JsonPositionBet,
+}
+
+#[derive(Serialize)]
+pub struct JsonArbitrage {
+    pub kind: String,
+    pub total_stake: f64,
+    pub worst_case: f64,
+    pub bets: Vec<JsonQuoteBet>,
+    pub middle_scores: Vec<String>,
+    pub profit_grid: JsonGrid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub bookmaker: Option<String>,
+    pub bet: PositionBet,
+}
+
+impl Quote {
+    pub fn from_json(quote: &JsonQuote) -> Result<Self> {
+        Ok(Self {
+            bookmaker: quote.bookmaker.clone(),
+            bet: PositionBet::from_json(&quote.bet)?,
+        })
+    }
+
+    /// Whether the quotes are netted against each other before commission: on one market,
+    /// with the same bookmaker
+    pub fn is_same_commission_market(&self, other: &Quote) -> bool {
+        self.bookmaker == other.bookmaker && self.bet.is_same_commission_market(&other.bet)
+    }
+
+    // What a unit stake puts at risk: the stake for a back, the liability for a lay
+    fn outlay_per_unit(&self) -> f64 {
+        match self.bet.exchange_bet.back_lay {
+            BackLay::Back => 1.0,
+            BackLay::Lay => self.bet.exchange_bet.odds.to_f64() - 1.0,
+        }
+    }
+}
+
+impl fmt::Display for Quote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.bookmaker {
+            Some(bookmaker) => write!(f, "{}: {}", bookmaker, self.bet),
+            None => write!(f, "{}", self.bet),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArbitrageKind {
+    /// A profit whatever the score
+    Arbitrage,
+    /// No loss whatever the score, and a profit on some scores
+    Middle,
+    /// Every combination of the quotes loses on some score
+    None,
+}
+
+impl fmt::Display for ArbitrageKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArbitrageKind::Arbitrage => write!(f, "arbitrage"),
+            ArbitrageKind::Middle => write!(f, "middle"),
+            ArbitrageKind::None => write!(f, "none"),
+        }
+    }
+}
+
+/// The best combination of the quotes for a total stake (counting the liability of lays): the stakes that give the highest
+/// guaranteed profit, or failing that a middle that can't lose but wins on some scores.
+/// Quotes that aren't part of it have a stake of 0.
+pub struct Arbitrage {
+    pub kind: ArbitrageKind,
+    pub total_stake: Rational,
+    pub quotes: Vec<Quote>,
+}
+
+impl Arbitrage {
+    /// Searches the quotes over every final score, whatever the grid shows. A middle is
+    /// chosen to win as much as possible over the scores in `bounds`. Commission is taken
+    /// from the net winnings of each bookmaker's market, as `get_profit` does.
+    pub fn new(quotes: Vec<Quote>, total_stake: Rational, bounds: &GridBounds) -> Result<Self> {
+        if quotes.is_empty() {
+            bail!("There are no quotes to combine.");
+        }
+        let unit_gross_profits = |scores: &[(usize, usize)]| -> Result<Vec<Vec<f64>>> {
+            scores
+                .iter()
+                .map(|&(home_goals, away_goals)| {
+                    quotes
+                        .iter()
+                        .map(|quote| {
+                            Ok(quote.bet.get_gross_profit(home_goals, away_goals)?.to_f64())
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+        let all_gross_profits = unit_gross_profits(&Arbitrage::all_scores(&quotes, bounds))?;
+        let grid_gross_profits = unit_gross_profits(&bounds.scores())?;
+        let commission_markets = Arbitrage::get_commission_markets(&quotes);
+
+        // What's kept of each commission market's net profit with the stakes, for each score
+        let get_kept_fractions = |gross_profits: &[Vec<f64>], stakes: &[f64]| -> Vec<Vec<f64>> {
+            gross_profits
+                .iter()
+                .map(|unit_gross_profits| {
+                    let mut market_profits = vec![0.0; commission_markets.commissions.len()];
+                    for ((stake, unit_profit), &market) in stakes
+                        .iter()
+                        .zip(unit_gross_profits)
+                        .zip(&commission_markets.bet_markets)
+                    {
+                        market_profits[market] += stake * unit_profit;
+                    }
+                    commission_markets.get_kept_fractions(&market_profits)
+                })
+                .collect()
+        };
+        let get_unit_profits = |gross_profits: &[Vec<f64>], kept_fractions: &[Vec<f64>]| {
+            gross_profits
+                .iter()
+                .zip(kept_fractions)
+                .map(|(unit_gross_profits, kept)| {
+                    unit_gross_profits
+                        .iter()
+                        .zip(&commission_markets.bet_markets)
+                        .map(|(profit, &market)| profit * kept[market])
+                        .collect()
+                })
+                .collect::<Vec<Vec<f64>>>()
+        };
+
+        let outlay: Vec<f64> = quotes.iter().map(Quote::outlay_per_unit).collect();
+        let mut kind = ArbitrageKind::None;
+        let mut stakes = vec![0.0; quotes.len()];
+        let mut kept_fractions = (vec![], vec![]);
+        for _ in 0..MAX_COMMISSION_ROUNDS {
+            let new_kept_fractions = (
+                get_kept_fractions(&all_gross_profits, &stakes),
+                get_kept_fractions(&grid_gross_profits, &stakes),
+            );
+            if new_kept_fractions == kept_fractions {
+                break;
+            }
+            kept_fractions = new_kept_fractions;
+            (kind, stakes) = Arbitrage::combine(
+                &get_unit_profits(&all_gross_profits, &kept_fractions.0),
+                &get_unit_profits(&grid_gross_profits, &kept_fractions.1),
+                &outlay,
+                total_stake.to_f64(),
+            )?;
+        }
+
+        let quotes = quotes
+            .into_iter()
+            .zip(stakes)
+            .map(|(mut quote, stake)| {
+                quote.bet.exchange_bet.stake = round_stake(stake)?;
+                Ok(quote)
+            })
+            .collect::<Result<_>>()?;
+        let mut arbitrage = Self {
+            kind,
+            total_stake,
+            quotes,
+        };
+
+        // Rounding the stakes to the cent can take away a small profit
+        let worst_case = arbitrage.get_worst_case(bounds)?;
+        let wins_in_grid = bounds.scores().into_iter().try_fold(
+            false,
+            |wins, (home_goals, away_goals)| -> Result<bool> {
+                Ok(wins || arbitrage.get_profit(home_goals, away_goals)?.is_positive())
+            },
+        )?;
+        arbitrage.kind = match arbitrage.kind {
+            ArbitrageKind::Arbitrage if worst_case.is_positive() => ArbitrageKind::Arbitrage,
+            ArbitrageKind::Arbitrage | ArbitrageKind::Middle
+                if !worst_case.is_negative() && wins_in_grid =>
+            {
+                ArbitrageKind::Middle
+            }
+            _ => ArbitrageKind::None,
+        };
+        Ok(arbitrage)
+    }
+
+    // The stakes for the unit profits of the quotes on every final score (`profits`) and on
+    // the scores in the grid (`grid_profits`), and what kind of combination they make
+    fn combine(
+        profits: &[Vec<f64>],
+        grid_profits: &[Vec<f64>],
+        outlay: &[f64],
+        total_stake: f64,
+    ) -> Result<(ArbitrageKind, Vec<f64>)> {
+        let num_quotes = outlay.len();
+
+        // Stakes (liabilities for lays) sum to the total (as two inequalities), and every score returns at
+        // least the guaranteed profit, split into positive and negative parts
+        let mut a = vec![];
+        let mut b = vec![];
+        let add_outlay_rows = |a: &mut Vec<Vec<f64>>, b: &mut Vec<f64>, extra: usize| {
+            let mut row = outlay.to_vec();
+            row.extend(vec![0.0; extra]);
+            a.push(row.clone());
+            b.push(total_stake);
+            a.push(row.iter().map(|x| -x).collect());
+            b.push(-total_stake);
+        };
+        add_outlay_rows(&mut a, &mut b, 2);
+        for score_profits in profits {
+            let mut row: Vec<f64> = score_profits.iter().map(|profit| -profit).collect();
+            row.extend([1.0, -1.0]);
+            a.push(row);
+            b.push(0.0);
+        }
+        let mut objective = vec![0.0; num_quotes];
+        objective.extend([1.0, -1.0]);
+        let (stakes, guaranteed_profit) = match maximise(&objective, &a, &b) {
+            LinearProgramSolution::Optimal { x, value } => (x[..num_quotes].to_vec(), value),
+            _ => bail!("Couldn't combine the quotes."),
+        };
+
+        let tolerance = PROFIT_TOLERANCE * total_stake;
+        Ok(match guaranteed_profit {
+            profit if profit > tolerance => (ArbitrageKind::Arbitrage, stakes),
+            profit if profit < -tolerance => (ArbitrageKind::None, stakes),
+            // Nothing is guaranteed, but maybe nothing is lost either: make the scores in the
+            // grid pay as much as possible without losing on any score
+            _ => {
+                let (mut a, mut b) = (vec![], vec![]);
+                add_outlay_rows(&mut a, &mut b, 0);
+                for score_profits in profits {
+                    a.push(score_profits.iter().map(|profit| -profit).collect());
+                    b.push(0.0);
+                }
+                let mut objective = vec![0.0; num_quotes];
+                for score_profits in grid_profits {
+                    for (total, profit) in objective.iter_mut().zip(score_profits) {
+                        *total += profit;
+                    }
+                }
+                match maximise(&objective, &a, &b) {
+                    LinearProgramSolution::Optimal { x, value } if value > tolerance => {
+                        (ArbitrageKind::Middle, x)
+                    }
+                    _ => (ArbitrageKind::None, stakes),
+                }
+            }
+        })
+    }
+
+    // Every final score still possible, up to well past the edge of the grid: from 0-0, or
+    // from the latest score an in-play quote was taken at
+    fn all_scores(quotes: &[Quote], bounds: &GridBounds) -> Vec<(usize, usize)> {
+        let (min_home_goals, min_away_goals) = quotes
+            .iter()
+            .filter_map(|quote| quote.bet.current_score)
+            .fold((0, 0), |(home_goals, away_goals), score| {
+                (
+                    home_goals.max(score.home_goals),
+                    away_goals.max(score.away_goals),
+                )
+            });
+        GridBounds {
+            min_home_goals,
+            max_home_goals: bounds.max_home_goals.max(min_home_goals),
+            min_away_goals,
+            max_away_goals: bounds.max_away_goals.max(min_away_goals),
+            tail: true,
+        }
+        .scores()
+    }
+
+    /// Combined profit of the bets if the match finishes `home_goals`-`away_goals`. Only bets
+    /// on one market with the same bookmaker are netted against each other before commission.
+    pub fn get_profit(&self, home_goals: usize, away_goals: usize) -> Result<Rational> {
+        let gross_profits = self
+            .quotes
+            .iter()
+            .map(|quote| quote.bet.get_gross_profit(home_goals, away_goals))
+            .collect::<Result<Vec<_>>>()?;
+        Rational::checked_sum(
+            Arbitrage::get_commission_markets(&self.quotes).take_commission(&gross_profits)?,
+        )
+    }
+
+    fn get_commission_markets(quotes: &[Quote]) -> CommissionMarkets {
+        CommissionMarkets::group(quotes, Quote::is_same_commission_market, |quote| {
+            quote.bet.exchange_bet.commission
+        })
+    }
+
+    /// Lowest profit over every final score, with the stakes rounded to the cent
+    pub fn get_worst_case(&self, bounds: &GridBounds) -> Result<Rational> {
+        let profits = Arbitrage::all_scores(&self.quotes, bounds)
+            .into_iter()
+            .map(|(home_goals, away_goals)| self.get_profit(home_goals, away_goals))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(profits.into_iter().min().unwrap_or_else(Rational::zero))
+    }
+
+    pub fn get_profit_grid(&self, bounds: &GridBounds) -> Result<Grid> {
+        let mut grid = Grid::new(bounds);
+        grid.set_title("Combined profit".to_owned());
+        grid.fill_profits(|home_goals, away_goals| self.get_profit(home_goals, away_goals))?;
+        Ok(grid)
+    }
+
+    /// The cells of the grid where a middle pays out, e.g. "2-1", or "9+-0" for tail buckets
+    pub fn get_middle_scores(&self, bounds: &GridBounds) -> Result<Vec<String>> {
+        if self.kind != ArbitrageKind::Middle {
+            return Ok(vec![]);
+        }
+        let grid = self.get_profit_grid(bounds)?;
+        let mut scores = vec![];
+        for home_goals in bounds.home_goals() {
+            for away_goals in bounds.away_goals() {
+                if !grid
+                    .get_payoff(home_goals, away_goals)
+                    .is_some_and(|profit| profit.is_positive())
+                {
+                    continue;
+                }
+                let tail_mark = |is_tail| if is_tail { "+" } else { "" };
+                scores.push(format!(
+                    "{}{}-{}{}",
+                    home_goals,
+                    tail_mark(bounds.is_home_tail(home_goals)),
+                    away_goals,
+                    tail_mark(bounds.is_away_tail(away_goals))
+                ));
+            }
+        }
+        Ok(scores)
+    }
+
+    pub fn to_json(&self, bounds: &GridBounds) -> Result<JsonArbitrage> {
+        Ok(JsonArbitrage {
+            kind: self.kind.to_string(),
+            total_stake: self.total_stake.to_f64(),
+            worst_case: self.get_worst_case(bounds)?.to_f64(),
+            bets: self
+                .quotes
+                .iter()
+                .filter(|quote| quote.bet.exchange_bet.stake.is_positive())
+                .map(|quote| JsonQuoteBet {
+                    bookmaker: quote.bookmaker.clone(),
+                    bet: quote.bet.to_json(),
+                })
+                .collect(),
+            middle_scores: self.get_middle_scores(bounds)?,
+            profit_grid: self.get_profit_grid(bounds)?.to_json(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(json: &str) -> Quote {
+        Quote::from_json(&serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    fn arbitrage(quotes: Vec<Quote>) -> Arbitrage {
+        Arbitrage::new(quotes, Rational::from_integer(100), &GridBounds::square(6)).unwrap()
+    }
+
+    #[test]
+    fn test_finds_arbitrage_across_bookmakers() {
+        // 1/3 + 1/3.6 + 1/4.5 < 1: back all three outcomes
+        let arbitrage = arbitrage(vec![
+            quote(r#"{"bookmaker": "A", "bet_type": "win-draw-win", "side": "h", "odds": 3}"#),
+            quote(r#"{"bookmaker": "B", "bet_type": "win-draw-win", "side": "d", "odds": 3.6}"#),
+            quote(r#"{"bookmaker": "B", "bet_type": "win-draw-win", "side": "a", "odds": 4.5}"#),
+        ]);
+        assert_eq!(arbitrage.kind, ArbitrageKind::Arbitrage);
+        let bounds = GridBounds::square(6);
+        // 100 / (1/3 + 1/3.6 + 1/4.5) returned, less rounding to the cent
+        let worst_case = arbitrage.get_worst_case(&bounds).unwrap();
+        assert!(
+            worst_case > Rational::new(1998, 100).unwrap()
+                && worst_case <= Rational::from_integer(20)
+        );
+    }
+
+    #[test]
+    fn test_finds_middle_between_lines() {
+        // Over 2.5 and under 3.5 at evens: both win on exactly 3 goals
+        let arbitrage = arbitrage(vec![
+            quote(r#"{"bet_type": "over-under", "side": "over", "goals": 2.5, "odds": 2}"#),
+            quote(r#"{"bet_type": "over-under", "side": "under", "goals": 3.5, "odds": 2}"#),
+        ]);
+        assert_eq!(arbitrage.kind, ArbitrageKind::Middle);
+        let bounds = GridBounds::square(6);
+        assert_eq!(arbitrage.get_worst_case(&bounds).unwrap(), Rational::zero());
+        assert_eq!(
+            arbitrage.get_middle_scores(&bounds).unwrap(),
+            vec!["0-3", "1-2", "2-1", "3-0"]
+        );
+    }
+
+    #[test]
+    fn test_no_arbitrage_with_a_margin() {
+        let arbitrage = arbitrage(vec![
+            quote(r#"{"bet_type": "asian-handicap", "side": "home", "handicap": 0, "odds": 1.9}"#),
+            quote(r#"{"bet_type": "asian-handicap", "side": "away", "handicap": 0, "odds": 1.95}"#),
+        ]);
+        assert_eq!(arbitrage.kind, ArbitrageKind::None);
+    }
+
+    #[test]
+    fn test_nets_commission_on_one_exchange_market() {
+        // 1/2.04 + 1/2 < 1, but only if the 5% is taken from the net winnings of the market:
+        // taken from each winning bet, over pays 1.988 and under 1.95
+        let arbitrage = arbitrage(vec![
+            quote(
+                r#"{"bookmaker": "Exchange", "bet_type": "over-under", "side": "over", "goals": 2.5, "odds": 2.04, "commission": 0.05}"#,
+            ),
+            quote(
+                r#"{"bookmaker": "Exchange", "bet_type": "over-under", "side": "under", "goals": 2.5, "odds": 2, "commission": 0.05}"#,
+            ),
+        ]);
+        assert_eq!(arbitrage.kind, ArbitrageKind::Arbitrage);
+        let worst_case = arbitrage.get_worst_case(&GridBounds::square(6)).unwrap();
+        assert!(
+            worst_case > Rational::new(9, 10).unwrap() && worst_case < Rational::from_integer(1)
+        );
+    }
+
+    #[test]
+    fn test_rounding_stakes_can_lose_an_arbitrage() {
+        // A 0.003% margin on a stake of 1 doesn't survive rounding the stakes to the cent
+        let arbitrage = Arbitrage::new(
+            vec![
+                quote(r#"{"bet_type": "over-under", "side": "over", "goals": 2.5, "odds": 1.5}"#),
+                quote(
+                    r#"{"bet_type": "over-under", "side": "under", "goals": 2.5, "odds": 3.0003}"#,
+                ),
+            ],
+            Rational::from_integer(1),
+            &GridBounds::square(6),
+        )
+        .unwrap();
+        assert!(arbitrage
+            .get_worst_case(&GridBounds::square(6))
+            .unwrap()
+            .is_negative());
+        assert_eq!(arbitrage.kind, ArbitrageKind::None);
+    }
+}
@@ -0,0 +1,861 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bet_types::Market;
+use crate::exchange::ExchangeBet;
+use crate::market_specifiers::{
+    BackLay, HomeAwayDrawSide, HomeAwaySide, Line, OverUnderSide, Period,
+};
+use crate::probability::PoissonModel;
+use crate::rational::Rational;
+use crate::results::{
+    print_skipped_rows, HistoricalMatch, HistoricalMatches, JsonSkippedRow, SkippedRow,
+};
+use crate::settlement::{Outcome, SettleBet, Settlement, SettlementMarket};
+
+#[derive(Serialize)]
+pub struct JsonBacktestTotals {
+    pub name: String,
+    pub bets: usize,
+    pub staked: f64,
+    pub profit: f64,
+    pub roi: Option<f64>,
+    pub hit_rate: Option<f64>,
+    pub max_drawdown: f64,
+    pub closing_line_value: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct JsonBacktestBet {
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    pub event: String,
+    pub strategy: String,
+    pub market: String,
+    pub odds: f64,
+    pub stake: f64,
+    pub expected_value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closing_line_value: Option<f64>,
+    pub score: String,
+    pub outcome: String,
+    pub profit: f64,
+}
+
+#[derive(Serialize)]
+pub struct JsonBacktest {
+    pub matches: usize,
+    pub matches_without_model: usize,
+    pub skipped_rows: Vec<JsonSkippedRow>,
+    pub odds: String,
+    pub min_ev: f64,
+    pub stake: f64,
+    pub strategies: Vec<JsonBacktestTotals>,
+    pub overall: JsonBacktestTotals,
+    pub bets: Vec<JsonBacktestBet>,
+}
+
+/// Whose odds columns of a football-data file are bet at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OddsSource {
+    Pinnacle,
+    Bet365,
+    Max,
+    Avg,
+}
+
+impl FromStr for OddsSource {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<OddsSource, ()> {
+        match input {
+            "pinnacle" => Ok(OddsSource::Pinnacle),
+            "ps" => Ok(OddsSource::Pinnacle),
+            "bet365" => Ok(OddsSource::Bet365),
+            "b365" => Ok(OddsSource::Bet365),
+            "max" => Ok(OddsSource::Max),
+            "avg" => Ok(OddsSource::Avg),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for OddsSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OddsSource::Pinnacle => write!(f, "Pinnacle"),
+            OddsSource::Bet365 => write!(f, "Bet365"),
+            OddsSource::Max => write!(f, "Max"),
+            OddsSource::Avg => write!(f, "Avg"),
+        }
+    }
+}
+
+impl OddsSource {
+    // Column prefix of the 1X2 odds, e.g. PSH and PSCH (closing)
+    fn result_prefix(&self) -> &'static str {
+        match self {
+            OddsSource::Pinnacle => "PS",
+            OddsSource::Bet365 => "B365",
+            OddsSource::Max => "Max",
+            OddsSource::Avg => "Avg",
+        }
+    }
+
+    // Column prefix of the Asian handicap and over/under odds, e.g. PAHH and P>2.5. Only
+    // Pinnacle's differs from its 1X2 prefix.
+    fn line_prefix(&self) -> &'static str {
+        match self {
+            OddsSource::Pinnacle => "P",
+            source => source.result_prefix(),
+        }
+    }
+}
+
+/// Markets a football-data file has odds for: 1X2, the Asian handicap line of the match
+/// (AHh, a home handicap) and over/under 2.5 goals
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BacktestMarket {
+    WinDrawWin,
+    AsianHandicap,
+    OverUnder,
+}
+
+impl FromStr for BacktestMarket {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<BacktestMarket, ()> {
+        match input {
+            "win-draw-win" => Ok(BacktestMarket::WinDrawWin),
+            "1x2" => Ok(BacktestMarket::WinDrawWin),
+            "asian-handicap" => Ok(BacktestMarket::AsianHandicap),
+            "ah" => Ok(BacktestMarket::AsianHandicap),
+            "over-under" => Ok(BacktestMarket::OverUnder),
+            "ou" => Ok(BacktestMarket::OverUnder),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for BacktestMarket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BacktestMarket::WinDrawWin => write!(f, "Win-Draw-Win"),
+            BacktestMarket::AsianHandicap => write!(f, "Asian Handicap"),
+            BacktestMarket::OverUnder => write!(f, "Over/Under"),
+        }
+    }
+}
+
+/// What to bet on: a market, optionally only one side of it, e.g. "asian-handicap:home".
+/// On each match the side with the best expected value is backed, if it's good enough.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strategy {
+    pub market: BacktestMarket,
+    pub side: Option<String>,
+}
+
+impl FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Strategy> {
+        let (market, side) = match input.split_once(':') {
+            Some((market, side)) => (market, Some(side)),
+            None => (input, None),
+        };
+        let market = BacktestMarket::from_str(market).map_err(|_| {
+            anyhow!(
+                "Unsupported market '{}' in strategy '{}'. Must be one of 'win-draw-win', 'asian-handicap' or 'over-under' ('1x2', 'ah', 'ou').",
+                market,
+                input
+            )
+        })?;
+        // Sides are kept as they are shown, to compare with the sides of the quotes
+        let side = match side {
+            None => None,
+            Some(side) => Some(
+                match market {
+                    BacktestMarket::WinDrawWin => {
+                        HomeAwayDrawSide::from_str(side).map(|side| side.to_string())
+                    }
+                    BacktestMarket::AsianHandicap => {
+                        HomeAwaySide::from_str(side).map(|side| side.to_string())
+                    }
+                    BacktestMarket::OverUnder => {
+                        OverUnderSide::from_str(side).map(|side| side.to_string())
+                    }
+                }
+                .map_err(|_| {
+                    anyhow!(
+                        "Invalid side '{}' in strategy '{}' for the {} market.",
+                        side,
+                        input,
+                        market
+                    )
+                })?,
+            ),
+        };
+        Ok(Strategy { market, side })
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.side {
+            Some(side) => write!(f, "{}: {}", self.market, side),
+            None => write!(f, "{}", self.market),
+        }
+    }
+}
+
+// A side of a market with the odds it could be backed at before the match, and its fair
+// closing odds (the closing odds of the market with the margin taken out) if the file has
+// them for the same line
+struct Quote {
+    side: String,
+    market: Market,
+    odds: Rational,
+    fair_closing_odds: Option<f64>,
+}
+
+/// A bet the backtest placed, and how it settled
+pub struct BacktestBet {
+    pub line: usize,
+    pub date: Option<String>,
+    pub event: String,
+    pub strategy: usize,
+    pub bet: SettleBet,
+    pub expected_value: f64,
+    pub closing_line_value: Option<f64>,
+    pub score: String,
+    pub settlement: Settlement,
+}
+
+/// Profit and loss of a run of bets, in the order they were placed
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestTotals {
+    pub bets: usize,
+    pub staked: Rational,
+    pub profit: Rational,
+    // Stakes settled as a win or a loss, and how much of them won, in bets: a push counts for
+    // neither, and a half-win for half a bet of each
+    pub decided: f64,
+    pub won: f64,
+    pub max_drawdown: Rational,
+    peak: Rational,
+    closing_line_values: Vec<f64>,
+}
+
+impl BacktestTotals {
+    fn new() -> Self {
+        Self {
+            bets: 0,
+            staked: Rational::zero(),
+            profit: Rational::zero(),
+            decided: 0.0,
+            won: 0.0,
+            max_drawdown: Rational::zero(),
+            peak: Rational::zero(),
+            closing_line_values: vec![],
+        }
+    }
+
+    fn add(&mut self, bet: &BacktestBet) -> Result<()> {
+        self.bets += 1;
+        self.staked = self.staked.checked_add(bet.bet.exchange_bet.stake)?;
+        self.profit = self.profit.checked_add(bet.settlement.profit)?;
+        let (decided, won) = match bet.settlement.outcome {
+            Outcome::Win => (1.0, 1.0),
+            Outcome::HalfWin => (0.5, 0.5),
+            Outcome::Push | Outcome::Void => (0.0, 0.0),
+            Outcome::HalfLoss => (0.5, 0.0),
+            Outcome::Loss => (1.0, 0.0),
+        };
+        self.decided += decided;
+        self.won += won;
+        self.peak = self.peak.max(self.profit);
+        self.max_drawdown = self.max_drawdown.max(self.peak.checked_sub(self.profit)?);
+        self.closing_line_values.extend(bet.closing_line_value);
+        Ok(())
+    }
+
+    /// Profit as a fraction of the stakes
+    pub fn get_roi(&self) -> Option<f64> {
+        match self.staked.is_positive() {
+            true => Some(self.profit.to_f64() / self.staked.to_f64()),
+            false => None,
+        }
+    }
+
+    /// Fraction of the stakes settled as a win or a loss that won. Pushes don't count, and
+    /// half-wins and half-losses count for the half of the stake that wasn't pushed.
+    pub fn get_hit_rate(&self) -> Option<f64> {
+        match self.decided > 0.0 {
+            true => Some(self.won / self.decided),
+            false => None,
+        }
+    }
+
+    /// Average closing-line value of the bets that have one
+    pub fn get_closing_line_value(&self) -> Option<f64> {
+        match self.closing_line_values.len() {
+            0 => None,
+            count => Some(self.closing_line_values.iter().sum::<f64>() / count as f64),
+        }
+    }
+
+    fn to_json(&self, name: String) -> JsonBacktestTotals {
+        JsonBacktestTotals {
+            name,
+            bets: self.bets,
+            staked: self.staked.to_f64(),
+            profit: self.profit.to_f64(),
+            roi: self.get_roi(),
+            hit_rate: self.get_hit_rate(),
+            max_drawdown: self.max_drawdown.to_f64(),
+            closing_line_value: self.get_closing_line_value(),
+        }
+    }
+}
+
+/// Runs staking strategies over historical matches: on each match with a model, each
+/// strategy backs the side of its market with the best expected value under the model at the
+/// pre-closing odds, if that is above `min_ev` per unit staked. Bets are settled on the
+/// result, and compared with the closing odds for their closing-line value (how much better
+/// the odds taken were than the fair closing odds). Rows with a malformed score, model or odds
+/// are skipped.
+pub struct Backtest {
+    pub strategies: Vec<Strategy>,
+    pub odds_source: OddsSource,
+    pub min_ev: f64,
+    pub stake: Rational,
+    pub matches: usize,
+    pub matches_without_model: usize,
+    pub skipped: Vec<SkippedRow>,
+    pub bets: Vec<BacktestBet>,
+    // Totals for each strategy, and for all the bets together
+    totals: (Vec<BacktestTotals>, BacktestTotals),
+}
+
+impl Backtest {
+    /// `get_model` gives the model for a match, or `None` if there isn't one
+    pub fn new<F>(
+        historical_matches: &HistoricalMatches,
+        get_model: F,
+        strategies: Vec<Strategy>,
+        odds_source: OddsSource,
+        min_ev: f64,
+        stake: Rational,
+    ) -> Result<Self>
+    where
+        F: Fn(&HistoricalMatch) -> Result<Option<PoissonModel>>,
+    {
+        let mut backtest = Self {
+            strategies,
+            odds_source,
+            min_ev,
+            stake,
+            matches: 0,
+            matches_without_model: 0,
+            skipped: historical_matches.skipped.clone(),
+            bets: vec![],
+            totals: (vec![], BacktestTotals::new()),
+        };
+        for historical_match in &historical_matches.matches {
+            let skip = |e: anyhow::Error| SkippedRow {
+                line: historical_match.line,
+                reason: e.to_string(),
+            };
+            let model = match get_model(historical_match) {
+                Ok(Some(model)) => model,
+                Ok(None) => {
+                    backtest.matches += 1;
+                    backtest.matches_without_model += 1;
+                    continue;
+                }
+                Err(e) => {
+                    backtest.skipped.push(skip(e));
+                    continue;
+                }
+            };
+            // Every strategy's odds are read before any bet is placed, so a row with
+            // malformed odds is skipped as a whole
+            let quotes = match backtest
+                .strategies
+                .iter()
+                .map(|strategy| {
+                    Backtest::get_quotes(historical_match, strategy.market, backtest.odds_source)
+                })
+                .collect::<Result<Vec<Vec<Quote>>>>()
+            {
+                Ok(quotes) => quotes,
+                Err(e) => {
+                    backtest.skipped.push(skip(e));
+                    continue;
+                }
+            };
+            backtest.matches += 1;
+            for (i, quotes) in quotes.into_iter().enumerate() {
+                let strategy = &backtest.strategies[i];
+                if let Some(bet) =
+                    backtest.place_bet(historical_match, &model, i, strategy, quotes)?
+                {
+                    backtest.bets.push(bet);
+                }
+            }
+        }
+        backtest.skipped.sort_by_key(|skipped| skipped.line);
+
+        let mut by_strategy = vec![BacktestTotals::new(); backtest.strategies.len()];
+        let mut overall = BacktestTotals::new();
+        for bet in &backtest.bets {
+            by_strategy[bet.strategy].add(bet)?;
+            overall.add(bet)?;
+        }
+        backtest.totals = (by_strategy, overall);
+        Ok(backtest)
+    }
+
+    // The bet `strategy` places on a match with `quotes` for its market, if any
+    fn place_bet(
+        &self,
+        historical_match: &HistoricalMatch,
+        model: &PoissonModel,
+        strategy_index: usize,
+        strategy: &Strategy,
+        quotes: Vec<Quote>,
+    ) -> Result<Option<BacktestBet>> {
+        let mut best: Option<(Quote, f64)> = None;
+        for quote in quotes {
+            if strategy
+                .side
+                .as_ref()
+                .is_some_and(|side| *side != quote.side)
+            {
+                continue;
+            }
+            // Expected profit per unit staked
+            let unit_bet =
+                ExchangeBet::new(BackLay::Back, quote.odds, Rational::one(), Rational::zero())?;
+            let expected_value = model.get_expected_payoff(None, |home_goals, away_goals| {
+                unit_bet.get_profit(quote.market.get_payoff(None, home_goals, away_goals)?)
+            })?;
+            if expected_value > self.min_ev
+                && best
+                    .as_ref()
+                    .is_none_or(|(_, best_value)| expected_value > *best_value)
+            {
+                best = Some((quote, expected_value));
+            }
+        }
+        let (quote, expected_value) = match best {
+            Some(best) => best,
+            None => return Ok(None),
+        };
+
+        let bet = SettleBet {
+            market: SettlementMarket::Score(quote.market, Period::FullTime),
+            exchange_bet: ExchangeBet::new(
+                BackLay::Back,
+                quote.odds,
+                self.stake,
+                Rational::zero(),
+            )?,
+            current_score: None,
+            void: false,
+        };
+        let settlement = bet.settle(historical_match.full_time, historical_match.half_time)?;
+        Ok(Some(BacktestBet {
+            line: historical_match.line,
+            date: historical_match.date.clone(),
+            event: historical_match.get_name(),
+            strategy: strategy_index,
+            expected_value,
+            closing_line_value: quote
+                .fair_closing_odds
+                .map(|fair_closing_odds| quote.odds.to_f64() / fair_closing_odds - 1.0),
+            score: historical_match.full_time.to_string(),
+            bet,
+            settlement,
+        }))
+    }
+
+    // The sides of `market` the match has pre-closing odds for. Blank odds are left out, but
+    // odds that aren't a number are an error.
+    fn get_quotes(
+        historical_match: &HistoricalMatch,
+        market: BacktestMarket,
+        odds_source: OddsSource,
+    ) -> Result<Vec<Quote>> {
+        let in_row = |e: anyhow::Error| anyhow!("{} (line {})", e, historical_match.line);
+        let result_prefix = odds_source.result_prefix();
+        let line_prefix = odds_source.line_prefix();
+        // Sides with their odds columns (pre-closing, closing) and the line of the closing
+        // odds if it can differ from the line bet on
+        let (sides, closing_line_matches): (Vec<(Market, String, String)>, bool) = match market {
+            BacktestMarket::WinDrawWin => (
+                [
+                    (HomeAwayDrawSide::Home, "H"),
+                    (HomeAwayDrawSide::Draw, "D"),
+                    (HomeAwayDrawSide::Away, "A"),
+                ]
+                .into_iter()
+                .map(|(side, column)| {
+                    (
+                        Market::WinDrawWin(side),
+                        format!("{}{}", result_prefix, column),
+                        format!("{}C{}", result_prefix, column),
+                    )
+                })
+                .collect(),
+                true,
+            ),
+            BacktestMarket::AsianHandicap => {
+                let handicap = match historical_match.get("AHh") {
+                    Some(handicap) => Line::from_str(handicap).map_err(in_row)?,
+                    None => return Ok(vec![]),
+                };
+                let closing_handicap = match historical_match.get("AHCh") {
+                    Some(closing_handicap) => {
+                        Some(Line::from_str(closing_handicap).map_err(in_row)?)
+                    }
+                    None => None,
+                };
+                (
+                    vec![
+                        (
+                            Market::AsianHandicap(HomeAwaySide::Home, handicap),
+                            format!("{}AHH", line_prefix),
+                            format!("{}CAHH", line_prefix),
+                        ),
+                        (
+                            Market::AsianHandicap(
+                                HomeAwaySide::Away,
+                                Line::from_quarters(-handicap.quarters()),
+                            ),
+                            format!("{}AHA", line_prefix),
+                            format!("{}CAHA", line_prefix),
+                        ),
+                    ],
+                    closing_handicap == Some(handicap),
+                )
+            }
+            BacktestMarket::OverUnder => {
+                let goals = Line::from_quarters(10);
+                (
+                    vec![
+                        (
+                            Market::OverUnder(OverUnderSide::Over, goals),
+                            format!("{}>2.5", line_prefix),
+                            format!("{}C>2.5", line_prefix),
+                        ),
+                        (
+                            Market::OverUnder(OverUnderSide::Under, goals),
+                            format!("{}<2.5", line_prefix),
+                            format!("{}C<2.5", line_prefix),
+                        ),
+                    ],
+                    true,
+                )
+            }
+        };
+
+        let mut odds = vec![];
+        let mut closing_odds = vec![];
+        for (_, column, closing_column) in &sides {
+            odds.push(historical_match.get_number(column)?);
+            closing_odds.push(historical_match.get_number(closing_column)?);
+        }
+        // Fair closing probabilities, scaling the implied probabilities to add up to 1
+        let fair_closing_probabilities: Option<Vec<f64>> = match closing_line_matches {
+            true => closing_odds
+                .iter()
+                .map(|odds| odds.filter(|odds| *odds > Rational::one()))
+                .map(|odds| odds.map(|odds| 1.0 / odds.to_f64()))
+                .collect(),
+            false => None,
+        };
+        let overround: Option<f64> = fair_closing_probabilities
+            .as_ref()
+            .map(|probabilities| probabilities.iter().sum());
+
+        Ok(sides
+            .into_iter()
+            .zip(odds)
+            .enumerate()
+            .filter_map(|(i, ((market, _, _), odds))| {
+                let odds = odds.filter(|odds| *odds > Rational::one())?;
+                let fair_closing_odds = match (&fair_closing_probabilities, overround) {
+                    (Some(probabilities), Some(overround)) => Some(overround / probabilities[i]),
+                    _ => None,
+                };
+                Some(Quote {
+                    side: Backtest::get_side(&market),
+                    market,
+                    odds,
+                    fair_closing_odds,
+                })
+            })
+            .collect())
+    }
+
+    fn get_side(market: &Market) -> String {
+        match market {
+            Market::WinDrawWin(side) => side.to_string(),
+            Market::AsianHandicap(side, _) => side.to_string(),
+            Market::OverUnder(side, _) => side.to_string(),
+        }
+    }
+
+    /// Totals for each strategy, and for all the bets together
+    pub fn get_totals(&self) -> (Vec<BacktestTotals>, BacktestTotals) {
+        self.totals.clone()
+    }
+
+    pub fn print_text(&self) {
+        println!(
+            "{} matches, {} without a model. Backing {} at {} pre-closing odds when the expected value is above {:.2}% of the stake.",
+            self.matches,
+            self.matches_without_model,
+            self.stake,
+            self.odds_source,
+            self.min_ev * 100.0
+        );
+        print_skipped_rows(&self.skipped);
+        let as_percentage = |fraction: Option<f64>| match fraction {
+            Some(fraction) => format!("{:.2}%", fraction * 100.0),
+            None => "-".to_owned(),
+        };
+        let (by_strategy, overall) = self.get_totals();
+        let mut rows: Vec<(String, &BacktestTotals)> = self
+            .strategies
+            .iter()
+            .map(Strategy::to_string)
+            .zip(&by_strategy)
+            .collect();
+        rows.push(("All bets".to_owned(), &overall));
+        let name_width = rows
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Strategy".len());
+        println!();
+        println!(
+            "{:<name_width$} {:>5} {:>10} {:>10} {:>9} {:>9} {:>12} {:>9}",
+            "Strategy", "Bets", "Staked", "Profit", "ROI", "Hit rate", "Max drawdown", "CLV"
+        );
+        for (name, totals) in rows {
+            println!(
+                "{:<name_width$} {:>5} {:>10} {:>10} {:>9} {:>9} {:>12} {:>9}",
+                name,
+                totals.bets,
+                totals.staked.to_string(),
+                totals.profit.to_string(),
+                as_percentage(totals.get_roi()),
+                as_percentage(totals.get_hit_rate()),
+                totals.max_drawdown.to_string(),
+                as_percentage(totals.get_closing_line_value()),
+            );
+        }
+    }
+
+    pub fn to_json(&self) -> JsonBacktest {
+        let (by_strategy, overall) = self.get_totals();
+        JsonBacktest {
+            matches: self.matches,
+            matches_without_model: self.matches_without_model,
+            skipped_rows: self.skipped.iter().map(SkippedRow::to_json).collect(),
+            odds: self.odds_source.to_string(),
+            min_ev: self.min_ev,
+            stake: self.stake.to_f64(),
+            strategies: self
+                .strategies
+                .iter()
+                .zip(&by_strategy)
+                .map(|(strategy, totals)| totals.to_json(strategy.to_string()))
+                .collect(),
+            overall: overall.to_json("All bets".to_owned()),
+            bets: self
+                .bets
+                .iter()
+                .map(|bet| JsonBacktestBet {
+                    line: bet.line,
+                    date: bet.date.clone(),
+                    event: bet.event.clone(),
+                    strategy: self.strategies[bet.strategy].to_string(),
+                    market: bet.bet.market.to_string(),
+                    odds: bet.bet.exchange_bet.odds.to_f64(),
+                    stake: bet.bet.exchange_bet.stake.to_f64(),
+                    expected_value: bet.expected_value,
+                    closing_line_value: bet.closing_line_value,
+                    score: bet.score.clone(),
+                    outcome: bet.settlement.outcome.to_string(),
+                    profit: bet.settlement.profit.to_f64(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{parse_historical_matches, CsvTable};
+
+    #[test]
+    fn test_backs_value_and_settles() {
+        // Evenly matched: home +0 at 2.2 is value, and the closing line moved against it
+        let table = CsvTable::parse(
+            "HomeTeam,AwayTeam,FTHG,FTAG,HXG,AXG,AHh,PAHH,PAHA,AHCh,PCAHH,PCAHA,P>2.5,P<2.5\n\
+             A,B,1,0,1.3,1.3,0,2.2,1.75,0,2.0,2.0,1.9,2.0\n\
+             C,D,0,0,1.3,1.3,0,2.2,1.75,-0.25,2.2,1.7,1.9,2.0\n\
+             E,F,0,1,1.3,1.3,0,2.2,1.75,0,1.95,1.95,1.9,2.0\n",
+        )
+        .unwrap();
+        let matches = parse_historical_matches(&table).unwrap();
+        let backtest = Backtest::new(
+            &matches,
+            |historical_match| historical_match.get_model(0.0),
+            vec![Strategy::from_str("ah:h").unwrap()],
+            OddsSource::Pinnacle,
+            0.03,
+            Rational::from_integer(10),
+        )
+        .unwrap();
+        let outcomes: Vec<Outcome> = backtest
+            .bets
+            .iter()
+            .map(|bet| bet.settlement.outcome)
+            .collect();
+        assert_eq!(outcomes, vec![Outcome::Win, Outcome::Push, Outcome::Loss]);
+
+        let (_, overall) = backtest.get_totals();
+        assert_eq!(overall.profit, Rational::from_integer(2));
+        assert_eq!(overall.max_drawdown, Rational::from_integer(10));
+        // The push is neither a hit nor a miss
+        assert_eq!(overall.get_hit_rate(), Some(0.5));
+        // 2.2 against fair closing odds of 2, not counting the match where the line moved
+        assert!((overall.get_closing_line_value().unwrap() - 0.1).abs() < 1e-9);
+
+        // Over 2.5 at 1.9 isn't value at these expected goals, and only the home side is backed
+        assert!(Strategy::from_str("ah:over").is_err());
+        assert!(backtest.bets.iter().all(|bet| bet
+            .bet
+            .market
+            .to_string()
+            .starts_with("Asian Handicap: Home")));
+    }
+
+    fn run(csv: &str, strategies: &[&str]) -> Backtest {
+        let table = CsvTable::parse(csv).unwrap();
+        Backtest::new(
+            &parse_historical_matches(&table).unwrap(),
+            |historical_match| historical_match.get_model(0.0),
+            strategies
+                .iter()
+                .map(|strategy| Strategy::from_str(strategy).unwrap())
+                .collect(),
+            OddsSource::Pinnacle,
+            0.03,
+            Rational::from_integer(10),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_strategies_bet_separately() {
+        // A high scoring match: home at 2.9 and over 2.5 at 1.6 are value, under 2.5 isn't
+        let backtest = run(
+            "HomeTeam,AwayTeam,FTHG,FTAG,HXG,AXG,PSH,PSD,PSA,P>2.5,P<2.5\n\
+             A,B,3,1,2.0,2.0,2.9,4.5,2.8,1.6,2.6\n",
+            &["1x2", "ou:over", "ou:under"],
+        );
+        assert_eq!(
+            backtest
+                .bets
+                .iter()
+                .map(|bet| (bet.strategy, bet.bet.market.to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, "Win-Draw-Win: Home".to_owned()),
+                (1, "Over/Under: Over 2.5".to_owned()),
+            ]
+        );
+        let (by_strategy, overall) = backtest.get_totals();
+        assert_eq!(by_strategy[0].profit, Rational::from_integer(19));
+        assert_eq!(by_strategy[1].profit, Rational::from_integer(6));
+        assert_eq!(by_strategy[2].bets, 0);
+        assert_eq!(by_strategy[2].get_roi(), None);
+        assert_eq!(by_strategy[2].get_hit_rate(), None);
+        assert_eq!(overall.staked, Rational::from_integer(20));
+        assert_eq!(overall.profit, Rational::from_integer(25));
+    }
+
+    #[test]
+    fn test_pushes_and_half_results_in_hit_rate_and_roi() {
+        let backtest = run(
+            "HomeTeam,AwayTeam,FTHG,FTAG,HXG,AXG,AHh,PAHH,PAHA\n\
+             A,B,1,1,1.3,1.3,0.25,2.2,1.5\n\
+             C,D,0,0,1.3,1.3,0,2.2,1.5\n\
+             E,F,0,0,1.3,1.3,-0.25,2.6,1.5\n\
+             G,H,2,0,1.3,1.3,0,2.2,1.5\n",
+            &["ah:home"],
+        );
+        let outcomes: Vec<Outcome> = backtest
+            .bets
+            .iter()
+            .map(|bet| bet.settlement.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::HalfWin,
+                Outcome::Push,
+                Outcome::HalfLoss,
+                Outcome::Win
+            ]
+        );
+        let (_, overall) = backtest.get_totals();
+        // Pushed stakes are still staked: 6 + 0 - 5 + 12 over 40
+        assert_eq!(overall.staked, Rational::from_integer(40));
+        assert_eq!(overall.profit, Rational::from_integer(13));
+        assert_eq!(overall.get_roi(), Some(13.0 / 40.0));
+        // 1.5 of the 2 stakes that weren't pushed won
+        assert_eq!(overall.get_hit_rate(), Some(0.75));
+    }
+
+    #[test]
+    fn test_skips_rows_with_missing_or_malformed_values() {
+        let backtest = run(
+            "HomeTeam,AwayTeam,FTHG,FTAG,HXG,AXG,AHh,PAHH,PAHA\n\
+             A,B,1,0,1.3,1.3,0,2.2,1.75\n\
+             C,D,1,0,1.3,1.3,0,,\n\
+             E,F,1,0,1.3,1.3,0,n/a,1.75\n\
+             G,H,1,0,1.3,1.3,level,2.2,1.75\n\
+             I,J,,,1.3,1.3,0,2.2,1.75\n\
+             K,L,1,0,,,0,2.2,1.75\n",
+            &["ah"],
+        );
+        // Blank odds only mean there's nothing to bet on
+        assert_eq!(backtest.bets.len(), 1);
+        assert_eq!(backtest.matches, 3);
+        assert_eq!(backtest.matches_without_model, 1);
+        assert_eq!(
+            backtest
+                .skipped
+                .iter()
+                .map(|skipped| skipped.line)
+                .collect::<Vec<_>>(),
+            vec![4, 5, 6]
+        );
+        assert_eq!(
+            backtest.skipped[0].reason,
+            "Invalid 'PAHH' value 'n/a' on line 4. Expected a decimal."
+        );
+        assert!(backtest.skipped[1].reason.ends_with("(line 5)"));
+    }
+}
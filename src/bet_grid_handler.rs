@@ -1,12 +1,39 @@
-use crate::bet_types::asian_handicap::AsianHandicap;
-use crate::bet_types::over_under::OverUnder;
-use crate::bet_types::win_draw_win::WinDrawWin;
-use crate::config::DEFAULT_FB_GRID_SIZE;
+use crate::arbitrage::{Arbitrage, ArbitrageKind, JsonQuotesFile, Quote};
+use crate::backtest::{Backtest, BacktestMarket, OddsSource, Strategy};
+use crate::bet_types::aggregate::{AggregateMarket, TwoLeggedTie};
+use crate::bet_types::goal_sequence::{
+    GoalSequenceMarket, JsonGoalSequenceModel, JsonGoalSequenceSettlement,
+};
+use crate::bet_types::half_time_full_time::{
+    HalfTimeFullTime, JsonHalfTimeFullTime, JsonHalfTimeFullTimePanel,
+};
+use crate::bet_types::to_qualify::{ExtraTime, ToQualify};
+use crate::bet_types::Market;
+use crate::calibration::Calibration;
+use crate::cash_out::CashOut;
+use crate::config::{DEFAULT_FB_GRID_SIZE, EXTRA_TIME_MINUTES};
 use crate::error::Error;
-use crate::market_specifiers::{HomeAwayDrawSide, HomeAwaySide, OverUnderSide};
+use crate::exchange::ExchangeBet;
+use crate::goal_sequence::GoalSequence;
+use crate::grid::{Grid, GridBounds};
+use crate::hedge::{Hedge, HedgeObjective, JsonPositionFile};
+use crate::kelly::Kelly;
+use crate::ledger::{parse_date, today, JsonLedgerEntry, Ledger, LedgerEntry, LedgerSummary};
+use crate::liability::Book;
+use crate::market_specifiers::{
+    BackLay, HalfTimeFullTimeSide, HomeAwayDrawSide, HomeAwaySide, Line, OverUnderSide, Period,
+};
+use crate::position::{read_bets_file, read_json_file, PositionBet};
+use crate::probability::{JsonModelGrid, PoissonModel};
+use crate::ratings::{JsonFixture, JsonRatingsOutput, Ratings};
+use crate::rational::Rational;
+use crate::results::{print_skipped_rows, read_historical_matches, HistoricalMatches, SkippedRow};
+use crate::score::Score;
+use crate::settlement::{read_settle_bets_file, JsonSettleBetsFile, Settlements};
+use crate::simulation::{BankrollSimulation, Simulation, StakingPlan};
 use anyhow::{bail, Result};
-use serde_json;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct BetGrid {}
 impl BetGrid {
@@ -15,7 +42,86 @@ impl BetGrid {
     }
 
     pub fn run(&self, matches: clap::ArgMatches) -> Result<()> {
-        self.payoff_grid(matches)?;
+        match matches.subcommand() {
+            Some(("hedge", hedge_matches)) => self.hedge(hedge_matches.clone())?,
+            Some(("arbitrage", arbitrage_matches)) => self.arbitrage(arbitrage_matches.clone())?,
+            Some(("kelly", kelly_matches)) => self.kelly(kelly_matches.clone())?,
+            Some(("cash-out", cash_out_matches)) => self.cash_out(cash_out_matches.clone())?,
+            Some(("liability", liability_matches)) => self.liability(liability_matches.clone())?,
+            Some(("settle", settle_matches)) => self.settle(settle_matches.clone())?,
+            Some(("ledger", ledger_matches)) => self.ledger(ledger_matches.clone())?,
+            Some(("backtest", backtest_matches)) => self.backtest(backtest_matches.clone())?,
+            Some(("calibrate", calibrate_matches)) => self.calibrate(calibrate_matches.clone())?,
+            Some(("ratings", ratings_matches)) => self.ratings(ratings_matches.clone())?,
+            Some(("simulate", simulate_matches)) => self.simulate(simulate_matches.clone())?,
+            _ => self.payoff_grid(matches)?,
+        }
+        Ok(())
+    }
+
+    fn hedge(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.hedge(matches)?;
+        Ok(())
+    }
+
+    fn arbitrage(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.arbitrage(matches)?;
+        Ok(())
+    }
+
+    fn kelly(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.kelly(matches)?;
+        Ok(())
+    }
+
+    fn cash_out(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.cash_out(matches)?;
+        Ok(())
+    }
+
+    fn liability(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.liability(matches)?;
+        Ok(())
+    }
+
+    fn settle(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.settle(matches)?;
+        Ok(())
+    }
+
+    fn ledger(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.ledger(matches)?;
+        Ok(())
+    }
+
+    fn backtest(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.backtest(matches)?;
+        Ok(())
+    }
+
+    fn calibrate(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.calibrate(matches)?;
+        Ok(())
+    }
+
+    fn ratings(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.ratings(matches)?;
+        Ok(())
+    }
+
+    fn simulate(&self, matches: clap::ArgMatches) -> Result<()> {
+        let cout_handler = BetGridCliHandler::new();
+        cout_handler.simulate(matches)?;
         Ok(())
     }
 
@@ -30,6 +136,15 @@ enum BetType {
     WinDrawWin,
     AsianHandicap,
     OverUnder,
+    HalfTimeFullTime,
+    FirstToScore,
+    LastToScore,
+    RaceTo,
+    ScoreInBothHalves,
+    ToQualify,
+    AggregateWinner,
+    AggregateHandicap,
+    AggregateTotal,
 }
 
 pub enum OutputMode {
@@ -46,21 +161,47 @@ impl BetGridCliHandler {
     fn payoff_grid(&self, matches: clap::ArgMatches) -> Result<()> {
         // Figure out if we're outputting text of json
         let output_mode = self.get_output_mode(matches.clone());
-        let grid_size: usize = self.get_grid_size(matches.clone())?;
+        let current_score = self.get_current_score(matches.clone(), &output_mode)?;
+        let period = self.get_period(matches.clone(), &output_mode)?;
+        let half_time_score = self.get_score(matches.clone(), "half_time_score", &output_mode)?;
+        if half_time_score.is_some() && period != Period::SecondHalf {
+            self.output_error(&output_mode, "The 'half-time-score' argument is for second-half bets. Pass 'period' second-half too.".to_owned())?;
+        }
+        // A second-half grid from the half-time score is over final scores, so it starts at the
+        // match score in play
+        let in_play_score = match half_time_score {
+            Some(half_time_score) => Some(match current_score {
+                Some(current_score) => Score::new(
+                    half_time_score.home_goals + current_score.home_goals,
+                    half_time_score.away_goals + current_score.away_goals,
+                ),
+                None => half_time_score,
+            }),
+            None => current_score,
+        };
+        let grid_bounds =
+            self.get_grid_bounds_from(matches.clone(), in_play_score, &output_mode)?;
+        let exchange_bet = self.get_exchange_bet(matches.clone(), &output_mode)?;
 
         // get a BetTypes enum from the given bet type argument
         let bet_type = self.get_bet_type(matches.clone(), &output_mode)?;
+        let bet_type_name = matches
+            .get_one::<String>("bet_type")
+            .cloned()
+            .unwrap_or_default();
 
-        // For each bet type, extract any extra params and call the appropriate grid method
-        match bet_type {
+        // For each bet type, extract any extra params to get the market that was bet on
+        let market = match bet_type {
             BetType::WinDrawWin => {
                 let side_str = matches.get_one::<String>("side");
                 match side_str {
-                    Some(matched_side) => {
-                        let side = HomeAwayDrawSide::from_str(matched_side);
-                        let grid = WinDrawWin::get_payoff_grid(side.unwrap(), grid_size)?;
-                        grid.print(&output_mode)?;
-                    }
+                    Some(matched_side) => match HomeAwayDrawSide::from_str(matched_side) {
+                        Ok(side) => Market::WinDrawWin(side),
+                        Err(_) => {
+                            self.output_error(&output_mode, format!("Invalid side '{}' for win-draw-win bet type. Should be one of: 'home', 'away', 'draw' (or 'h', 'a', 'd').", matched_side))?;
+                            return Ok(());
+                        }
+                    },
                     None => {
                         self.output_error(&output_mode, "Couldn't find required argument 'side' for win-draw-win bet type. Should be one of: 'home', 'away', 'draw' (or 'h', 'a', 'd').".to_owned())?;
                         return Ok(());
@@ -68,97 +209,2175 @@ impl BetGridCliHandler {
                 }
             }
 
-            BetType::AsianHandicap => {
+            BetType::AsianHandicap | BetType::AggregateHandicap => {
+                let side = match matches.get_one::<String>("side") {
+                    Some(matched_side) => match HomeAwaySide::from_str(matched_side) {
+                        Ok(side) => side,
+                        Err(_) => {
+                            self.output_error(&output_mode, format!("Invalid side '{}' for {} bet type. Should be one of: 'home' or 'away' ('h' or 'a').", matched_side, bet_type_name))?;
+                            return Ok(());
+                        }
+                    },
+                    None => {
+                        self.output_error(&output_mode, format!("Couldn't find required argument 'side' for {} bet type. Should be one of: 'home' or 'away' ('h' or 'a').", bet_type_name))?;
+                        return Ok(());
+                    }
+                };
+                let handicap = match matches.get_one::<String>("handicap") {
+                    Some(matched_handicap) => match Line::from_str(matched_handicap) {
+                        Ok(line) => line,
+                        Err(e) => {
+                            self.output_error(&output_mode, e.to_string())?;
+                            return Ok(());
+                        }
+                    },
+                    None => {
+                        self.output_error(&output_mode, format!("Couldn't find required argument 'handicap' for {} bet type. For example, -0.25, 1, 1.5.", bet_type_name))?;
+                        return Ok(());
+                    }
+                };
+                Market::AsianHandicap(side, handicap)
+            }
+
+            BetType::OverUnder | BetType::AggregateTotal => {
+                let side = match matches.get_one::<String>("side") {
+                    Some(matched_side) => match OverUnderSide::from_str(matched_side) {
+                        Ok(side) => side,
+                        Err(_) => {
+                            self.output_error(&output_mode, format!("Invalid side '{}' for {} bet type. Should be one of: 'over' or 'under' ('o' or 'u').", matched_side, bet_type_name))?;
+                            return Ok(());
+                        }
+                    },
+                    None => {
+                        self.output_error(&output_mode, format!("Couldn't find required argument 'side' for {} bet type. Should be one of: 'over' or 'under' ('o' or 'u').", bet_type_name))?;
+                        return Ok(());
+                    }
+                };
+                let goals = match matches.get_one::<String>("goals") {
+                    Some(matched_goals) => {
+                        match Line::from_str(matched_goals) {
+                            Ok(line) if line.is_negative() => {
+                                self.output_error(&output_mode, format!("Invalid goals line '{}'. Over/under lines can't be negative.", matched_goals))?;
+                                return Ok(());
+                            }
+                            Ok(line) => line,
+                            Err(e) => {
+                                self.output_error(&output_mode, e.to_string())?;
+                                return Ok(());
+                            }
+                        }
+                    }
+                    None => {
+                        self.output_error(&output_mode, format!("Couldn't find required argument 'goals' for {} bet type. For example, 1, 1.5, 2.", bet_type_name))?;
+                        return Ok(());
+                    }
+                };
+                Market::OverUnder(side, goals)
+            }
+
+            BetType::HalfTimeFullTime => {
                 let side = match matches.get_one::<String>("side") {
-                    Some(matched_side) => HomeAwaySide::from_str(matched_side),
+                    Some(matched_side) => match HalfTimeFullTimeSide::from_str(matched_side) {
+                        Ok(side) => side,
+                        Err(_) => {
+                            self.output_error(&output_mode, format!("Invalid side '{}' for ht-ft bet type. Should be the half-time then full-time result, for example 'draw/home' or 'd/h'.", matched_side))?;
+                            return Ok(());
+                        }
+                    },
                     None => {
-                        self.output_error(&output_mode, "Couldn't find required argument 'side' for asian-handicap bet type. Should be one of: 'home' or 'away' ('h' or 'a').".to_owned())?;
+                        self.output_error(&output_mode, "Couldn't find required argument 'side' for ht-ft bet type. Should be the half-time then full-time result, for example 'draw/home' or 'd/h'.".to_owned())?;
                         return Ok(());
                     }
                 };
-                let handicap: f32 = match matches.get_one::<String>("handicap") {
-                    Some(matched_handicap) => matched_handicap.parse()?,
+                if period != Period::FullTime
+                    || current_score.is_some()
+                    || matches.contains_id("minutes_remaining")
+                {
+                    self.output_error(&output_mode, "Half-time/full-time bets settle on the whole match from kick-off, so 'period', 'current-score' and 'minutes-remaining' aren't supported.".to_owned())?;
+                }
+                return self.half_time_full_time_grids(
+                    matches,
+                    side,
+                    &grid_bounds,
+                    exchange_bet.as_ref(),
+                    &output_mode,
+                );
+            }
+
+            BetType::FirstToScore
+            | BetType::LastToScore
+            | BetType::RaceTo
+            | BetType::ScoreInBothHalves => {
+                let side = match matches.get_one::<String>("side") {
+                    Some(matched_side) => match HomeAwaySide::from_str(matched_side) {
+                        Ok(side) => side,
+                        Err(_) => {
+                            self.output_error(&output_mode, format!("Invalid side '{}' for {} bet type. Should be one of: 'home' or 'away' ('h' or 'a').", matched_side, bet_type_name))?;
+                            return Ok(());
+                        }
+                    },
                     None => {
-                        self.output_error(&output_mode, "Couldn't find required argument 'handicap' for asian-handicap bet type. For example, -0.25, 1, 1.5.".to_owned())?;
+                        self.output_error(&output_mode, "Couldn't find required argument 'side' for goal sequence bet types. Should be one of: 'home' or 'away' ('h' or 'a').".to_owned())?;
                         return Ok(());
                     }
                 };
-                let grid = AsianHandicap::get_payoff_grid(side.unwrap(), handicap, grid_size)?;
-                grid.print(&output_mode)?;
+                let market = match bet_type {
+                    BetType::FirstToScore => GoalSequenceMarket::FirstToScore(side),
+                    BetType::LastToScore => GoalSequenceMarket::LastToScore(side),
+                    BetType::ScoreInBothHalves => GoalSequenceMarket::ScoreInBothHalves(side),
+                    _ => {
+                        let goals = match matches.get_one::<String>("goals") {
+                            Some(matched_goals) => match matched_goals.parse::<usize>() {
+                                Ok(goals) if goals > 0 => goals,
+                                _ => {
+                                    self.output_error(&output_mode, format!("Invalid goals '{}' for race-to bet type. Must be a whole number of goals, at least 1.", matched_goals))?;
+                                    return Ok(());
+                                }
+                            },
+                            None => {
+                                self.output_error(&output_mode, "Couldn't find required argument 'goals' for race-to bet type. For example, 2 for the first team to 2 goals.".to_owned())?;
+                                return Ok(());
+                            }
+                        };
+                        GoalSequenceMarket::RaceTo(side, goals)
+                    }
+                };
+                if period != Period::FullTime
+                    || current_score.is_some()
+                    || matches.contains_id("minutes_remaining")
+                {
+                    self.output_error(&output_mode, "Goal sequence bets settle on the whole match from kick-off, so 'period', 'current-score' and 'minutes-remaining' aren't supported.".to_owned())?;
+                }
+                return self.goal_sequence_market(
+                    matches,
+                    market,
+                    &grid_bounds,
+                    exchange_bet.as_ref(),
+                    &output_mode,
+                );
             }
 
-            BetType::OverUnder => {
+            BetType::AggregateWinner => {
                 let side = match matches.get_one::<String>("side") {
-                    Some(matched_side) => OverUnderSide::from_str(matched_side),
+                    Some(matched_side) => match HomeAwaySide::from_str(matched_side) {
+                        Ok(side) => side,
+                        Err(_) => {
+                            self.output_error(&output_mode, format!("Invalid side '{}' for aggregate-winner bet type. Should be one of: 'home' or 'away' ('h' or 'a'), the teams' roles in the second leg.", matched_side))?;
+                            return Ok(());
+                        }
+                    },
                     None => {
-                        self.output_error(&output_mode, "Couldn't find required argument 'side' for over-under bet type. Should be one of: 'over' or 'under' ('o' or 'u').".to_owned())?;
+                        self.output_error(&output_mode, "Couldn't find required argument 'side' for aggregate-winner bet type. Should be one of: 'home' or 'away' ('h' or 'a'), the teams' roles in the second leg.".to_owned())?;
                         return Ok(());
                     }
                 };
-                let goals: f32 = match matches.get_one::<String>("goals") {
-                    Some(matched_goals) => matched_goals.parse()?,
+                let market = AggregateMarket::Winner(side);
+                return self.aggregate_market(
+                    matches,
+                    market,
+                    &grid_bounds,
+                    period,
+                    current_score,
+                    exchange_bet.as_ref(),
+                    &output_mode,
+                );
+            }
+
+            BetType::ToQualify => {
+                let side = match matches.get_one::<String>("side") {
+                    Some(matched_side) => match HomeAwaySide::from_str(matched_side) {
+                        Ok(side) => side,
+                        Err(_) => {
+                            self.output_error(&output_mode, format!("Invalid side '{}' for {} bet type. Should be one of: 'home' or 'away' ('h' or 'a').", matched_side, bet_type_name))?;
+                            return Ok(());
+                        }
+                    },
                     None => {
-                        self.output_error(&output_mode, "Couldn't find required argument 'goals' for over-under bet type. For example, 1, 1.5, 2.".to_owned())?;
+                        self.output_error(&output_mode, format!("Couldn't find required argument 'side' for {} bet type. Should be one of: 'home' or 'away' ('h' or 'a').", bet_type_name))?;
+                        return Ok(());
+                    }
+                };
+                if period != Period::FullTime
+                    || current_score.is_some()
+                    || matches.contains_id("minutes_remaining")
+                {
+                    self.output_error(&output_mode, "Qualification bets settle on the whole tie from kick-off, so 'period', 'current-score' and 'minutes-remaining' aren't supported.".to_owned())?;
+                }
+                let extra_time = self.get_extra_time(matches.clone(), &output_mode)?;
+                return self.qualification_grid(
+                    matches,
+                    side,
+                    extra_time,
+                    &grid_bounds,
+                    exchange_bet.as_ref(),
+                    &output_mode,
+                );
+            }
+        };
+
+        // Aggregate handicaps and totals are the usual lines, settled on the aggregate score
+        let aggregate_market = match (&bet_type, &market) {
+            (BetType::AggregateHandicap, Market::AsianHandicap(side, handicap)) => {
+                Some(AggregateMarket::AsianHandicap(side.clone(), *handicap))
+            }
+            (BetType::AggregateTotal, Market::OverUnder(side, goals)) => {
+                Some(AggregateMarket::OverUnder(side.clone(), *goals))
+            }
+            _ => None,
+        };
+        if let Some(aggregate_market) = aggregate_market {
+            return self.aggregate_market(
+                matches,
+                aggregate_market,
+                &grid_bounds,
+                period,
+                current_score,
+                exchange_bet.as_ref(),
+                &output_mode,
+            );
+        }
+
+        let mut grid = match half_time_score {
+            Some(half_time_score) => {
+                market.get_second_half_payoff_grid(&grid_bounds, half_time_score, current_score)?
+            }
+            None => market.get_payoff_grid(&grid_bounds, current_score)?,
+        };
+        if self.shows_outcomes(&matches) {
+            grid.set_show_outcomes();
+        }
+        if period != Period::FullTime {
+            grid.set_period(period);
+        }
+        if let Some(exchange_bet) = &exchange_bet {
+            if let Err(e) = grid.set_exchange_bet(exchange_bet) {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        }
+        let pricing = self.get_pricing(exchange_bet.as_ref());
+        match self.get_model(matches.clone(), current_score, period, &output_mode)? {
+            None => grid.print(&output_mode)?,
+            Some(model) => {
+                let probability_grid = model.get_probability_grid(&grid_bounds, in_play_score);
+                let payoff = |home_goals, away_goals| match half_time_score {
+                    Some(half_time_score) => market.get_second_half_payoff(
+                        half_time_score,
+                        current_score,
+                        home_goals,
+                        away_goals,
+                    ),
+                    None => market.get_payoff(current_score, home_goals, away_goals),
+                };
+                let expected_value = match model
+                    .get_expected_payoff(in_play_score, |home_goals, away_goals| {
+                        pricing.get_profit(payoff(home_goals, away_goals)?)
+                    }) {
+                    Ok(expected_value) => expected_value,
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
                         return Ok(());
                     }
                 };
-                let grid = OverUnder::get_payoff_grid(side.unwrap(), goals, grid_size)?;
-                grid.print(&output_mode)?;
+                match output_mode {
+                    OutputMode::Text => {
+                        grid.print_text()?;
+                        println!();
+                        probability_grid.print_text();
+                        println!();
+                        self.print_expected_value(expected_value, exchange_bet.as_ref());
+                    }
+                    OutputMode::Json => {
+                        let json_grid = JsonModelGrid {
+                            payoff: grid.to_json(),
+                            probability_grid: probability_grid.to_json(),
+                            expected_value,
+                        };
+                        println!("{}", serde_json::to_string_pretty(&json_grid)?);
+                    }
+                }
             }
         }
 
         Ok(())
     }
 
-    fn output_error(&self, output_mode: &OutputMode, error_message: String) -> Result<()> {
+    // One payoff grid (and probability grid) per half-time result
+    fn half_time_full_time_grids(
+        &self,
+        matches: clap::ArgMatches,
+        side: HalfTimeFullTimeSide,
+        grid_bounds: &GridBounds,
+        exchange_bet: Option<&ExchangeBet>,
+        output_mode: &OutputMode,
+    ) -> Result<()> {
+        let mut grids = HalfTimeFullTime::get_payoff_grids(&side, grid_bounds)?;
+        for (_, grid) in grids.iter_mut() {
+            if let Some(exchange_bet) = exchange_bet {
+                if let Err(e) = grid.set_exchange_bet(exchange_bet) {
+                    self.output_error(output_mode, e.to_string())?;
+                    return Ok(());
+                }
+            }
+            if self.shows_outcomes(&matches) {
+                grid.set_show_outcomes();
+            }
+        }
+        let pricing = self.get_pricing(exchange_bet);
+        let model = self.get_model(matches, None, Period::FullTime, output_mode)?;
+        let probability_grids = model
+            .as_ref()
+            .map(|model| model.get_half_time_full_time_probability_grids(grid_bounds));
+        let expected_value = match model
+            .as_ref()
+            .map(|model| {
+                model.get_half_time_full_time_expected_payoff(|half_time, full_time| {
+                    pricing.get_profit(HalfTimeFullTime::get_payoff(&side, half_time, full_time))
+                })
+            })
+            .transpose()
+        {
+            Ok(expected_value) => expected_value,
+            Err(e) => {
+                self.output_error(output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+
         match output_mode {
-            OutputMode::Text => bail!(error_message),
+            OutputMode::Text => {
+                println!("Greyed out full-time scores can't follow the half-time result");
+                for (i, (_, grid)) in grids.iter().enumerate() {
+                    println!();
+                    grid.print_text()?;
+                    if let Some(probability_grids) = &probability_grids {
+                        println!();
+                        probability_grids[i].1.print_text();
+                    }
+                }
+                if let Some(expected_value) = expected_value {
+                    println!();
+                    self.print_expected_value(expected_value, exchange_bet);
+                }
+            }
             OutputMode::Json => {
-                let error = Error {
-                    error: error_message,
+                let panels = grids
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (half_time_result, grid))| JsonHalfTimeFullTimePanel {
+                        half_time_result: half_time_result.to_string(),
+                        payoff: grid.to_json(),
+                        probability_grid: probability_grids
+                            .as_ref()
+                            .map(|probability_grids| probability_grids[i].1.to_json()),
+                    })
+                    .collect();
+                let json_grids = JsonHalfTimeFullTime {
+                    panels,
+                    expected_value,
                 };
-                let json_error = serde_json::to_string_pretty(&error);
-                match json_error {
-                    Ok(serialized_error) => bail!(serialized_error),
-                    _ => bail!("{{\"error\": \"unexpected_error\"}}"),
+                println!("{}", serde_json::to_string_pretty(&json_grids)?);
+            }
+        }
+        Ok(())
+    }
+
+    // Settles a goal sequence market on the given goals, or projects it onto the final score
+    // grid with the probability model
+    fn goal_sequence_market(
+        &self,
+        matches: clap::ArgMatches,
+        market: GoalSequenceMarket,
+        grid_bounds: &GridBounds,
+        exchange_bet: Option<&ExchangeBet>,
+        output_mode: &OutputMode,
+    ) -> Result<()> {
+        let pricing = self.get_pricing(exchange_bet);
+        if let Some(goal_sequence) = matches.get_one::<String>("goal_sequence") {
+            let sequence = match GoalSequence::from_str(goal_sequence) {
+                Ok(sequence) => sequence,
+                Err(e) => {
+                    self.output_error(output_mode, e.to_string())?;
+                    return Ok(());
+                }
+            };
+            let payoff = match market
+                .get_payoff(&sequence)
+                .and_then(|payoff| pricing.get_profit(payoff))
+            {
+                Ok(payoff) => payoff,
+                Err(e) => {
+                    self.output_error(output_mode, e.to_string())?;
+                    return Ok(());
                 }
+            };
+            match output_mode {
+                OutputMode::Text => {
+                    println!("{}", market);
+                    println!(
+                        "Goals: {} (final score {})",
+                        sequence,
+                        sequence.final_score()
+                    );
+                    if let Some(exchange_bet) = exchange_bet {
+                        println!("{}", exchange_bet);
+                    }
+                    println!("Payoff: {}", payoff);
+                }
+                OutputMode::Json => {
+                    let settlement = JsonGoalSequenceSettlement {
+                        market: market.to_string(),
+                        goal_sequence: sequence.to_string(),
+                        final_score: sequence.final_score().to_string(),
+                        payoff: payoff.to_f64(),
+                        exchange: exchange_bet.map(ExchangeBet::to_json),
+                    };
+                    println!("{}", serde_json::to_string_pretty(&settlement)?);
+                }
+            }
+            return Ok(());
+        }
+
+        let model = match self.get_model(matches, None, Period::FullTime, output_mode)? {
+            Some(model) => model,
+            None => {
+                self.output_error(output_mode, "Goal sequence bets depend on the order of the goals, not just the final score. Pass the goals with 'goal-sequence' to settle the bet, or 'home-xg' and 'away-xg' to price it.".to_owned())?;
+                return Ok(());
+            }
+        };
+        let win_probability_grid = model
+            .get_conditional_probability_grid(grid_bounds, |home_goals, away_goals| {
+                market.get_win_probability(home_goals, away_goals)
+            });
+        let win_probability = model.get_expectation(None, |home_goals, away_goals| {
+            market.get_win_probability(home_goals, away_goals)
+        });
+        let expected_value = match pricing.get_expected_profit(win_probability) {
+            Ok(expected_value) => expected_value,
+            Err(e) => {
+                self.output_error(output_mode, e.to_string())?;
+                return Ok(());
             }
         };
+
+        match output_mode {
+            OutputMode::Text => {
+                println!("{}", market);
+                if let Some(exchange_bet) = exchange_bet {
+                    println!("{}", exchange_bet);
+                }
+                win_probability_grid.print_table("Win probability given the final score (%)");
+                println!();
+                println!("Win probability: {:.2}%", win_probability * 100.0);
+                self.print_expected_value(expected_value, exchange_bet);
+            }
+            OutputMode::Json => {
+                let json_model = JsonGoalSequenceModel {
+                    market: market.to_string(),
+                    bounds: grid_bounds.clone(),
+                    win_probability_grid: win_probability_grid.to_json(),
+                    win_probability,
+                    expected_value,
+                    exchange: exchange_bet.map(ExchangeBet::to_json),
+                };
+                println!("{}", serde_json::to_string_pretty(&json_model)?);
+            }
+        }
+        Ok(())
     }
 
-    fn get_output_mode(&self, matches: clap::ArgMatches) -> OutputMode {
-        match matches.get_one::<String>("output").map(String::as_str) {
-            Some("json") | Some("j") => OutputMode::Json,
-            _ => OutputMode::Text,
+    // Regulation score grid for a knockout tie, with draws going to extra time and penalties
+    fn qualification_grid(
+        &self,
+        matches: clap::ArgMatches,
+        side: HomeAwaySide,
+        extra_time: Option<ExtraTime>,
+        grid_bounds: &GridBounds,
+        exchange_bet: Option<&ExchangeBet>,
+        output_mode: &OutputMode,
+    ) -> Result<()> {
+        let show_outcomes = self.shows_outcomes(&matches);
+        let model = self.get_model(matches, None, Period::FullTime, output_mode)?;
+        let extra_time_probability = model.as_ref().map(|model| {
+            ToQualify::get_extra_time_probability(
+                &side,
+                model.get_result_probabilities(EXTRA_TIME_MINUTES),
+            )
+        });
+        let mut grid = ToQualify::get_payoff_grid(
+            side.clone(),
+            grid_bounds,
+            extra_time.as_ref(),
+            extra_time_probability,
+        )?;
+
+        if show_outcomes {
+            grid.set_show_outcomes();
         }
+        let extra_time_note = extra_time
+            .is_none()
+            .then(|| self.extra_time_note("level after 90 minutes", &side, extra_time_probability));
+        self.print_knockout_grid(
+            grid,
+            model,
+            extra_time_note,
+            extra_time_probability,
+            grid_bounds,
+            exchange_bet,
+            output_mode,
+            |home_goals, away_goals| {
+                ToQualify::get_payoff(&side, home_goals, away_goals, extra_time.as_ref())
+            },
+        )
     }
 
-    fn get_grid_size(&self, matches: clap::ArgMatches) -> Result<usize> {
-        let size: usize = match matches.get_one::<String>("grid_size").map(String::as_str) {
-            Some(grid_size) => grid_size.parse()?,
-            _ => DEFAULT_FB_GRID_SIZE,
+    // The two-legged tie from the first-leg arguments, then the second-leg grid
+    #[allow(clippy::too_many_arguments)]
+    fn aggregate_market(
+        &self,
+        matches: clap::ArgMatches,
+        market: AggregateMarket,
+        grid_bounds: &GridBounds,
+        period: Period,
+        current_score: Option<Score>,
+        exchange_bet: Option<&ExchangeBet>,
+        output_mode: &OutputMode,
+    ) -> Result<()> {
+        let first_leg = match matches.get_one::<String>("first_leg_score") {
+            Some(first_leg) => match Score::from_str(first_leg) {
+                Ok(score) => score,
+                Err(e) => {
+                    self.output_error(output_mode, e.to_string())?;
+                    return Ok(());
+                }
+            },
+            None => {
+                self.output_error(output_mode, "Couldn't find required argument 'first-leg-score' for aggregate bet types. For example, 2-1 (the first-leg home team, who play away in the second leg, first).".to_owned())?;
+                return Ok(());
+            }
         };
-        Ok(size)
+        if period != Period::FullTime
+            || current_score.is_some()
+            || matches.contains_id("minutes_remaining")
+        {
+            self.output_error(output_mode, "Aggregate bets are priced on the whole second leg from kick-off, so 'period', 'current-score' and 'minutes-remaining' aren't supported.".to_owned())?;
+        }
+        let tie = TwoLeggedTie {
+            first_leg,
+            away_goals_rule: matches.get_flag("away_goals_rule"),
+        };
+        let extra_time = self.get_extra_time(matches.clone(), output_mode)?;
+        self.aggregate_grid(
+            matches,
+            market,
+            tie,
+            extra_time,
+            grid_bounds,
+            exchange_bet,
+            output_mode,
+        )
+    }
+
+    // Second-leg grid for a two-legged tie, settled on the aggregate score
+    #[allow(clippy::too_many_arguments)]
+    fn aggregate_grid(
+        &self,
+        matches: clap::ArgMatches,
+        market: AggregateMarket,
+        tie: TwoLeggedTie,
+        extra_time: Option<ExtraTime>,
+        grid_bounds: &GridBounds,
+        exchange_bet: Option<&ExchangeBet>,
+        output_mode: &OutputMode,
+    ) -> Result<()> {
+        let show_outcomes = self.shows_outcomes(&matches);
+        let model = self.get_model(matches, None, Period::FullTime, output_mode)?;
+        // Only the aggregate winner can go to extra time: handicaps and totals settle on the
+        // second leg
+        let extra_time_probability = match (&market, &model) {
+            (AggregateMarket::Winner(side), Some(model)) => Some(tie.get_extra_time_probability(
+                side,
+                model.get_result_probabilities(EXTRA_TIME_MINUTES),
+                model.get_goalless_probability(EXTRA_TIME_MINUTES),
+            )),
+            _ => None,
+        };
+        let mut grid = market.get_payoff_grid(
+            &tie,
+            grid_bounds,
+            extra_time.as_ref(),
+            extra_time_probability,
+        )?;
+        if show_outcomes {
+            grid.set_show_outcomes();
+        }
+        let extra_time_note = match (&market, &extra_time) {
+            (AggregateMarket::Winner(side), None) => {
+                Some(self.extra_time_note("level on aggregate", side, extra_time_probability))
+            }
+            _ => None,
+        };
+
+        self.print_knockout_grid(
+            grid,
+            model,
+            extra_time_note,
+            extra_time_probability,
+            grid_bounds,
+            exchange_bet,
+            output_mode,
+            |home_goals, away_goals| {
+                market.get_payoff(&tie, home_goals, away_goals, extra_time.as_ref())
+            },
+        )
+    }
+
+    // Explains the "ET" cells of a knockout grid, with the model's chance of `side` going
+    // through from there
+    fn extra_time_note(
+        &self,
+        level: &str,
+        side: &HomeAwaySide,
+        extra_time_probability: Option<f64>,
+    ) -> String {
+        match extra_time_probability {
+            Some(probability) => format!(
+                "ET: {}, {} goes through {:.2}% of the time after extra time and penalties",
+                level,
+                side.to_string().to_lowercase(),
+                probability * 100.0
+            ),
+            None => format!("ET: {}, settled on extra time and penalties (pass 'extra-time-score' and 'penalty-winner' to settle these)", level),
+        }
+    }
+
+    // Prints a knockout grid with the model's view of it if there is one. `payoff` is `None`
+    // for scores that go to extra time with its result unknown: with the model those are
+    // worth the chance of going through from there.
+    #[allow(clippy::too_many_arguments)]
+    fn print_knockout_grid<F>(
+        &self,
+        mut grid: Grid,
+        model: Option<PoissonModel>,
+        extra_time_note: Option<String>,
+        extra_time_probability: Option<f64>,
+        grid_bounds: &GridBounds,
+        exchange_bet: Option<&ExchangeBet>,
+        output_mode: &OutputMode,
+        payoff: F,
+    ) -> Result<()>
+    where
+        F: Fn(usize, usize) -> Option<Rational>,
+    {
+        if let Some(exchange_bet) = exchange_bet {
+            if let Err(e) = grid.set_exchange_bet(exchange_bet) {
+                self.output_error(output_mode, e.to_string())?;
+                return Ok(());
+            }
+        }
+        let pricing = self.get_pricing(exchange_bet);
+        let print_grid_text = || -> Result<()> {
+            grid.print_text()?;
+            if let Some(extra_time_note) = &extra_time_note {
+                println!("{}", extra_time_note);
+            }
+            Ok(())
+        };
+        let model = match (model, output_mode) {
+            (Some(model), _) => model,
+            (None, OutputMode::Text) => return print_grid_text(),
+            (None, OutputMode::Json) => return grid.print(output_mode),
+        };
+
+        let probability_grid = model.get_probability_grid(grid_bounds, None);
+        let get_expected_value = || -> Result<f64> {
+            let extra_time_payoff = match extra_time_probability {
+                Some(probability) => pricing.get_expected_profit(probability)?,
+                None => 0.0,
+            };
+            model
+                .get_score_probabilities(None)
+                .into_iter()
+                .map(|(score, probability)| {
+                    let profit = match payoff(score.home_goals, score.away_goals) {
+                        Some(payoff) => pricing.get_profit(payoff)?.to_f64(),
+                        None => extra_time_payoff,
+                    };
+                    Ok(probability * profit)
+                })
+                .sum()
+        };
+        let expected_value = match get_expected_value() {
+            Ok(expected_value) => expected_value,
+            Err(e) => {
+                self.output_error(output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+        match output_mode {
+            OutputMode::Text => {
+                print_grid_text()?;
+                println!();
+                probability_grid.print_text();
+                println!();
+                self.print_expected_value(expected_value, exchange_bet);
+            }
+            OutputMode::Json => {
+                let json_grid = JsonModelGrid {
+                    payoff: grid.to_json(),
+                    probability_grid: probability_grid.to_json(),
+                    expected_value,
+                };
+                println!("{}", serde_json::to_string_pretty(&json_grid)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn hedge(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let grid_bounds = self.get_grid_bounds(matches.clone(), &output_mode)?;
+        let objective = match matches.get_one::<String>("objective") {
+            None => HedgeObjective::WorstCase,
+            Some(objective) => match HedgeObjective::from_str(objective) {
+                Ok(objective) => objective,
+                Err(_) => {
+                    self.output_error(&output_mode, format!("Unsupported hedge objective '{}'. Must be one of 'worst-case' or 'equalise' ('w' or 'e').", objective))?;
+                    return Ok(());
+                }
+            },
+        };
+
+        let path = matches.get_one::<String>("position").unwrap();
+        let hedge = match self.read_hedge(path, objective, &grid_bounds) {
+            Ok(hedge) => hedge,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+
+        match output_mode {
+            OutputMode::Text => {
+                println!("Position:");
+                for bet in &hedge.position {
+                    println!("  {}", bet);
+                }
+                println!("Hedges ({}):", hedge.objective.to_string().to_lowercase());
+                for bet in &hedge.hedges {
+                    println!("  {}", bet);
+                }
+                println!();
+                hedge.get_net_grid(&grid_bounds)?.print_text()?;
+                println!();
+                let (worst_case, best_case) = hedge.get_range(&grid_bounds, true)?;
+                let (unhedged_worst_case, unhedged_best_case) =
+                    hedge.get_range(&grid_bounds, false)?;
+                println!(
+                    "Worst case: {} (unhedged {})",
+                    worst_case, unhedged_worst_case
+                );
+                println!("Best case: {} (unhedged {})", best_case, unhedged_best_case);
+            }
+            OutputMode::Json => {
+                let json_hedge = match hedge.to_json(&grid_bounds) {
+                    Ok(json_hedge) => json_hedge,
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                };
+                println!("{}", serde_json::to_string_pretty(&json_hedge)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_hedge(
+        &self,
+        path: &str,
+        objective: HedgeObjective,
+        grid_bounds: &GridBounds,
+    ) -> Result<Hedge> {
+        let position_file: JsonPositionFile = read_json_file(path)?;
+        let position = position_file
+            .position
+            .iter()
+            .map(PositionBet::from_json)
+            .collect::<Result<Vec<PositionBet>>>()?;
+        let markets = Hedge::get_markets(&position_file.hedges)?;
+        if markets.is_empty() {
+            bail!(
+                "The position file '{}' has no 'hedges' to hedge with.",
+                path
+            );
+        }
+        Hedge::new(objective, position, markets, grid_bounds)
+    }
+
+    fn arbitrage(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let grid_bounds = self.get_grid_bounds(matches.clone(), &output_mode)?;
+        let total_stake = match matches.get_one::<String>("total_stake") {
+            None => Rational::from_integer(100),
+            Some(total_stake) => match Rational::from_str(total_stake) {
+                Ok(total_stake) if total_stake.is_positive() => total_stake,
+                _ => {
+                    self.output_error(
+                        &output_mode,
+                        format!(
+                            "Invalid total stake '{}'. Must be a positive number.",
+                            total_stake
+                        ),
+                    )?;
+                    return Ok(());
+                }
+            },
+        };
+
+        let path = matches.get_one::<String>("quotes").unwrap();
+        let arbitrage = match self.read_arbitrage(path, total_stake, &grid_bounds) {
+            Ok(arbitrage) => arbitrage,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+
+        match output_mode {
+            OutputMode::Text => {
+                let worst_case = arbitrage.get_worst_case(&grid_bounds)?;
+                match arbitrage.kind {
+                    ArbitrageKind::Arbitrage => println!(
+                        "Arbitrage: a profit of at least {} on every score for {} staked",
+                        worst_case, total_stake
+                    ),
+                    ArbitrageKind::Middle => println!(
+                        "Middle: no loss on any score for {} staked (worst case {}), a profit on {}",
+                        total_stake,
+                        worst_case,
+                        arbitrage.get_middle_scores(&grid_bounds)?.join(", ")
+                    ),
+                    ArbitrageKind::None => {
+                        println!(
+                            "No arbitrage or middle: the best combination for {} staked still loses {} on some scores",
+                            total_stake, -worst_case
+                        );
+                        return Ok(());
+                    }
+                }
+                for quote in &arbitrage.quotes {
+                    if quote.bet.exchange_bet.stake.is_positive() {
+                        println!("  {}", quote);
+                    }
+                }
+                println!();
+                arbitrage.get_profit_grid(&grid_bounds)?.print_text()?;
+            }
+            OutputMode::Json => {
+                let json_arbitrage = match arbitrage.to_json(&grid_bounds) {
+                    Ok(json_arbitrage) => json_arbitrage,
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                };
+                println!("{}", serde_json::to_string_pretty(&json_arbitrage)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_arbitrage(
+        &self,
+        path: &str,
+        total_stake: Rational,
+        grid_bounds: &GridBounds,
+    ) -> Result<Arbitrage> {
+        let quotes_file: JsonQuotesFile = read_json_file(path)?;
+        let quotes = quotes_file
+            .quotes
+            .iter()
+            .map(Quote::from_json)
+            .collect::<Result<Vec<Quote>>>()?;
+        Arbitrage::new(quotes, total_stake, grid_bounds)
+    }
+
+    fn kelly(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let grid_bounds = self.get_grid_bounds(matches.clone(), &output_mode)?;
+        if matches.contains_id("current_score") || matches.contains_id("minutes_remaining") {
+            self.output_error(&output_mode, "Kelly stakes are for bets before kick-off, so 'current-score' and 'minutes-remaining' aren't supported.".to_owned())?;
+        }
+        let bankroll = match matches.get_one::<String>("bankroll") {
+            None => Rational::from_integer(100),
+            Some(bankroll) => match Rational::from_str(bankroll) {
+                Ok(bankroll) if bankroll.is_positive() => bankroll,
+                _ => {
+                    self.output_error(
+                        &output_mode,
+                        format!(
+                            "Invalid bankroll '{}'. Must be a positive number.",
+                            bankroll
+                        ),
+                    )?;
+                    return Ok(());
+                }
+            },
+        };
+        let kelly_fraction = match matches.get_one::<String>("kelly_fraction") {
+            None => 1.0,
+            Some(kelly_fraction) => match kelly_fraction.parse::<f64>() {
+                Ok(kelly_fraction) if kelly_fraction > 0.0 && kelly_fraction <= 1.0 => {
+                    kelly_fraction
+                }
+                _ => {
+                    self.output_error(&output_mode, format!("Invalid Kelly fraction '{}'. Must be above 0 and at most 1 (for example, 0.5 for half Kelly).", kelly_fraction))?;
+                    return Ok(());
+                }
+            },
+        };
+        let model = match self.get_model(matches.clone(), None, Period::FullTime, &output_mode)? {
+            Some(model) => model,
+            None => {
+                self.output_error(
+                    &output_mode,
+                    "Kelly stakes need a probability model. Pass 'home-xg' and 'away-xg' (or 'ratings')."
+                        .to_owned(),
+                )?;
+                return Ok(());
+            }
+        };
+
+        let path = matches.get_one::<String>("bets").unwrap();
+        let kelly = match self.read_kelly(path, &model, bankroll, kelly_fraction) {
+            Ok(kelly) => kelly,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+
+        match output_mode {
+            OutputMode::Text => {
+                println!(
+                    "Kelly stakes ({}x Kelly) from a bankroll of {}:",
+                    kelly_fraction, bankroll
+                );
+                for (bet, single_bet_stake) in kelly.bets.iter().zip(&kelly.single_bet_stakes) {
+                    println!("  {} [{} as the only bet]", bet, single_bet_stake);
+                }
+                println!();
+                kelly.get_profit_grid(&grid_bounds)?.print_text()?;
+                println!();
+                println!("Expected log growth: {:.6}", kelly.expected_log_growth);
+                println!("Expected profit: {:.4}", kelly.expected_profit);
+            }
+            OutputMode::Json => {
+                let json_kelly = match kelly.to_json(&grid_bounds) {
+                    Ok(json_kelly) => json_kelly,
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                };
+                println!("{}", serde_json::to_string_pretty(&json_kelly)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_kelly(
+        &self,
+        path: &str,
+        model: &PoissonModel,
+        bankroll: Rational,
+        kelly_fraction: f64,
+    ) -> Result<Kelly> {
+        let bets = read_bets_file(path)?;
+        let score_probabilities = model.get_score_probabilities(None);
+        Kelly::new(bets, &score_probabilities, bankroll, kelly_fraction)
+    }
+
+    fn cash_out(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let grid_bounds = self.get_grid_bounds(matches.clone(), &output_mode)?;
+        let current_score = self.get_current_score(matches.clone(), &output_mode)?;
+        let offer = match matches.get_one::<String>("offer") {
+            None => None,
+            Some(offer) => match Rational::from_str(offer) {
+                Ok(offer) if !offer.is_negative() => Some(offer),
+                _ => {
+                    self.output_error(
+                        &output_mode,
+                        format!("Invalid offer '{}'. Must be a non-negative number.", offer),
+                    )?;
+                    return Ok(());
+                }
+            },
+        };
+        let model = match self.get_model(
+            matches.clone(),
+            current_score,
+            Period::FullTime,
+            &output_mode,
+        )? {
+            Some(model) => model,
+            None => {
+                self.output_error(
+                    &output_mode,
+                    "Cash-out values need a probability model. Pass 'home-xg' and 'away-xg' (or 'ratings')."
+                        .to_owned(),
+                )?;
+                return Ok(());
+            }
+        };
+
+        let path = matches.get_one::<String>("bets").unwrap();
+        let current_score = current_score.unwrap_or(Score::new(0, 0));
+        let cash_out = match self.read_cash_out(path, &model, current_score, offer) {
+            Ok(cash_out) => cash_out,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+
+        match output_mode {
+            OutputMode::Text => {
+                println!("Open bets at {}:", current_score);
+                for bet in &cash_out.bets {
+                    println!("  {}", bet);
+                }
+                println!();
+                cash_out.get_profit_grid(&grid_bounds)?.print_text()?;
+                println!();
+                model
+                    .get_probability_grid(&grid_bounds, Some(current_score))
+                    .print_text();
+                println!();
+                println!(
+                    "Fair cash-out value: {:.2} (expected profit {:.2} on {} at risk)",
+                    cash_out.fair_value(),
+                    cash_out.expected_profit,
+                    cash_out.at_risk
+                );
+                if let Some(offer) = cash_out.offer {
+                    let profit = offer.checked_sub(cash_out.at_risk)?;
+                    match cash_out.get_offer_margin() {
+                        Some(margin) if margin >= 0.0 => println!(
+                            "Offered cash-out: {} (profit {}), {:.2}% below fair value",
+                            offer,
+                            profit,
+                            margin * 100.0
+                        ),
+                        Some(margin) => println!(
+                            "Offered cash-out: {} (profit {}), {:.2}% above fair value",
+                            offer,
+                            profit,
+                            -margin * 100.0
+                        ),
+                        None => println!("Offered cash-out: {} (profit {})", offer, profit),
+                    }
+                }
+            }
+            OutputMode::Json => {
+                let json_cash_out = match cash_out.to_json(&grid_bounds) {
+                    Ok(json_cash_out) => json_cash_out,
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                };
+                println!("{}", serde_json::to_string_pretty(&json_cash_out)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_cash_out(
+        &self,
+        path: &str,
+        model: &PoissonModel,
+        current_score: Score,
+        offer: Option<Rational>,
+    ) -> Result<CashOut> {
+        let bets = read_bets_file(path)?;
+        let score_probabilities = model.get_score_probabilities(Some(current_score));
+        CashOut::new(bets, current_score, &score_probabilities, offer)
+    }
+
+    fn liability(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let grid_bounds = self.get_grid_bounds(matches.clone(), &output_mode)?;
+
+        let path = matches.get_one::<String>("bets").unwrap();
+        let book = match read_bets_file(path) {
+            Ok(bets) => Book::new(bets),
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+
+        match output_mode {
+            OutputMode::Text => {
+                println!("Turnover by market:");
+                for (market, bets, turnover) in book.get_turnover_by_market()? {
+                    let plural = if bets == 1 { "" } else { "s" };
+                    println!("  {}: {} bet{}, {} staked", market, bets, plural, turnover);
+                }
+                println!();
+                book.get_liability_grid(&grid_bounds)?.print_text()?;
+                println!();
+                let (worst_score, worst_profit) = book.get_worst_case(&grid_bounds)?;
+                println!("Total turnover: {}", book.get_turnover()?);
+                println!("Worst case: {} at {}", worst_profit, worst_score);
+            }
+            OutputMode::Json => {
+                let json_book = match book.to_json(&grid_bounds) {
+                    Ok(json_book) => json_book,
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                };
+                println!("{}", serde_json::to_string_pretty(&json_book)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn settle(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let (full_time, half_time) = self.get_result(matches.clone(), &output_mode)?;
+
+        let path = matches.get_one::<String>("bets").unwrap();
+        let settlements = match read_settle_bets_file(path)
+            .and_then(|bets| Settlements::new(bets, full_time, half_time))
+        {
+            Ok(settlements) => settlements,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+
+        match output_mode {
+            OutputMode::Text => settlements.print_text(),
+            OutputMode::Json => {
+                println!("{}", serde_json::to_string_pretty(&settlements.to_json())?);
+            }
+        }
+        Ok(())
+    }
+
+    fn ledger(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let (command, command_matches) = match matches.subcommand() {
+            Some((command, command_matches)) => (command, command_matches.clone()),
+            None => {
+                self.output_error(&output_mode, "Missing ledger command. Should be one of: 'record', 'list', 'settle' or 'summary'.".to_owned())?;
+                return Ok(());
+            }
+        };
+        let path = command_matches.get_one::<String>("ledger").unwrap();
+        let mut ledger = match Ledger::read(path, command == "record") {
+            Ok(ledger) => ledger,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+
+        match command {
+            "record" => {
+                let date = match command_matches.get_one::<String>("date") {
+                    Some(date) => date.clone(),
+                    None => today(),
+                };
+                let event = command_matches.get_one::<String>("event").unwrap();
+                let bookmaker = command_matches.get_one::<String>("bookmaker").unwrap();
+                let bets_path = command_matches.get_one::<String>("bets").unwrap();
+                let recorded = match read_json_file::<JsonSettleBetsFile>(bets_path)
+                    .and_then(|bets_file| ledger.record(&date, event, bookmaker, bets_file.bets))
+                    .and_then(|recorded| ledger.write(path).map(|_| recorded))
+                {
+                    Ok(recorded) => recorded,
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                };
+                self.print_ledger_entries(&output_mode, &ledger, &recorded)?;
+            }
+            "list" => {
+                let event = command_matches.get_one::<String>("event");
+                let open = command_matches.get_flag("open");
+                let entries: Vec<LedgerEntry> = ledger
+                    .get_entries()?
+                    .into_iter()
+                    .filter(|entry| event.is_none_or(|event| entry.event == *event))
+                    .filter(|entry| !open || entry.settlement.is_none())
+                    .collect();
+                self.print_ledger_entries(&output_mode, &ledger, &entries)?;
+            }
+            "settle" => {
+                let (full_time, half_time) =
+                    self.get_result(command_matches.clone(), &output_mode)?;
+                let event = command_matches.get_one::<String>("event").unwrap();
+                let settled = match ledger
+                    .settle(event, full_time, half_time)
+                    .and_then(|settled| ledger.write(path).map(|_| settled))
+                {
+                    Ok(settled) => settled,
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                };
+                self.print_ledger_entries(&output_mode, &ledger, &settled)?;
+            }
+            _ => {
+                let summary = match ledger
+                    .get_entries()
+                    .and_then(|entries| LedgerSummary::new(&entries))
+                {
+                    Ok(summary) => summary,
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                };
+                match output_mode {
+                    OutputMode::Text => summary.print_text(),
+                    OutputMode::Json => {
+                        println!("{}", serde_json::to_string_pretty(&summary.to_json())?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn print_ledger_entries(
+        &self,
+        output_mode: &OutputMode,
+        ledger: &Ledger,
+        entries: &[LedgerEntry],
+    ) -> Result<()> {
+        match output_mode {
+            OutputMode::Text => {
+                for entry in entries {
+                    let status = match &entry.settlement {
+                        Some(settlement) => {
+                            format!("{}, profit {}", settlement.outcome, settlement.profit)
+                        }
+                        None => "Open".to_owned(),
+                    };
+                    println!(
+                        "#{} {} {} ({}): {}: {}",
+                        entry.id, entry.date, entry.event, entry.bookmaker, entry.bet, status
+                    );
+                }
+            }
+            OutputMode::Json => {
+                let json_entries: Vec<&JsonLedgerEntry> = ledger
+                    .get_json_entries()
+                    .iter()
+                    .filter(|json_entry| entries.iter().any(|entry| entry.id == json_entry.id))
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&json_entries)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn backtest(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let strategies = match matches.get_many::<String>("strategy") {
+            None => [
+                BacktestMarket::WinDrawWin,
+                BacktestMarket::AsianHandicap,
+                BacktestMarket::OverUnder,
+            ]
+            .into_iter()
+            .map(|market| Strategy { market, side: None })
+            .collect(),
+            Some(strategies) => match strategies
+                .map(|strategy| Strategy::from_str(strategy))
+                .collect::<Result<Vec<Strategy>>>()
+            {
+                Ok(strategies) => strategies,
+                Err(e) => {
+                    self.output_error(&output_mode, e.to_string())?;
+                    return Ok(());
+                }
+            },
+        };
+        let odds_source = match matches.get_one::<String>("odds") {
+            None => OddsSource::Pinnacle,
+            Some(odds_source) => match OddsSource::from_str(odds_source) {
+                Ok(odds_source) => odds_source,
+                Err(_) => {
+                    self.output_error(&output_mode, format!("Unsupported odds '{}'. Must be one of 'pinnacle', 'bet365', 'max' or 'avg'.", odds_source))?;
+                    return Ok(());
+                }
+            },
+        };
+        let min_ev = match matches.get_one::<String>("min_ev") {
+            None => 0.03,
+            Some(min_ev) => match min_ev.parse::<f64>() {
+                Ok(min_ev) if min_ev.is_finite() => min_ev,
+                _ => {
+                    self.output_error(
+                        &output_mode,
+                        format!("Invalid minimum expected value '{}'. Must be a number (for example, 0.03 for 3% of the stake).", min_ev),
+                    )?;
+                    return Ok(());
+                }
+            },
+        };
+        let stake = match matches.get_one::<String>("stake") {
+            None => Rational::one(),
+            Some(stake) => match Rational::from_str(stake) {
+                Ok(stake) if stake.is_positive() => stake,
+                _ => {
+                    self.output_error(
+                        &output_mode,
+                        format!("Invalid stake '{}'. Must be a positive number.", stake),
+                    )?;
+                    return Ok(());
+                }
+            },
+        };
+
+        let rho = self.get_rho(&matches, &output_mode)?;
+
+        let path = matches.get_one::<String>("results").unwrap();
+        let historical_matches = match read_historical_matches(path) {
+            Ok(historical_matches) => historical_matches,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+        self.check_expected_goals_columns(&historical_matches, path, "backtest", &output_mode)?;
+        let backtest = match Backtest::new(
+            &historical_matches,
+            |historical_match| historical_match.get_model(rho),
+            strategies,
+            odds_source,
+            min_ev,
+            stake,
+        ) {
+            Ok(backtest) => backtest,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+        if backtest.matches_without_model == backtest.matches {
+            self.output_error(&output_mode, format!("No match in '{}' has a model. The backtest needs each match's pre-match expected goals in HXG and AXG columns.", path))?;
+        }
+
+        match output_mode {
+            OutputMode::Text => backtest.print_text(),
+            OutputMode::Json => {
+                println!("{}", serde_json::to_string_pretty(&backtest.to_json())?);
+            }
+        }
+        Ok(())
+    }
+
+    fn calibrate(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let mut lines = vec![];
+        for name in ["handicaps", "goals"] {
+            lines.push(match matches.get_many::<String>(name) {
+                None => None,
+                Some(values) => match values
+                    .map(|value| Line::from_str(value))
+                    .collect::<Result<Vec<Line>>>()
+                {
+                    Ok(lines) => Some(lines),
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                },
+            });
+        }
+        let goals = lines
+            .pop()
+            .unwrap()
+            .unwrap_or([6, 10, 14].into_iter().map(Line::from_quarters).collect());
+        let handicaps = lines.pop().unwrap();
+        let rho = self.get_rho(&matches, &output_mode)?;
+
+        let path = matches.get_one::<String>("results").unwrap();
+        let historical_matches = match read_historical_matches(path) {
+            Ok(historical_matches) => historical_matches,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+        self.check_expected_goals_columns(&historical_matches, path, "calibration", &output_mode)?;
+        let calibration = match Calibration::new(
+            &historical_matches,
+            |historical_match| historical_match.get_model(rho),
+            rho,
+            handicaps,
+            goals,
+        ) {
+            Ok(calibration) => calibration,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+        if calibration.matches_without_model == calibration.matches {
+            self.output_error(&output_mode, format!("No match in '{}' has a model. The calibration needs each match's pre-match expected goals in HXG and AXG columns.", path))?;
+        }
+
+        match output_mode {
+            OutputMode::Text => calibration.print_text(),
+            OutputMode::Json => {
+                println!("{}", serde_json::to_string_pretty(&calibration.to_json())?);
+            }
+        }
+        Ok(())
+    }
+
+    fn ratings(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        let half_life = match matches.get_one::<String>("half_life") {
+            None => None,
+            Some(half_life) => match half_life.parse::<f64>() {
+                Ok(half_life) if half_life.is_finite() && half_life > 0.0 => Some(half_life),
+                _ => {
+                    self.output_error(
+                        &output_mode,
+                        format!(
+                            "Invalid half-life '{}'. Must be a positive number of days.",
+                            half_life
+                        ),
+                    )?;
+                    return Ok(());
+                }
+            },
+        };
+        let date = match matches
+            .get_one::<String>("date")
+            .map(|date| parse_date(date))
+        {
+            None => None,
+            Some(Ok(date)) => Some(date),
+            Some(Err(e)) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+
+        let path = matches.get_one::<String>("results").unwrap();
+        let (ratings, skipped) =
+            match read_historical_matches(path).and_then(|historical_matches| {
+                Ok((
+                    Ratings::fit(
+                        &historical_matches.matches,
+                        date,
+                        half_life,
+                        matches.get_flag("dixon_coles"),
+                    )?,
+                    historical_matches.skipped,
+                ))
+            }) {
+                Ok(ratings) => ratings,
+                Err(e) => {
+                    self.output_error(&output_mode, e.to_string())?;
+                    return Ok(());
+                }
+            };
+        // A fixture to predict, if the teams are given
+        let fixture = match (
+            matches.get_one::<String>("home_team"),
+            matches.get_one::<String>("away_team"),
+        ) {
+            (Some(home_team), Some(away_team)) => {
+                match ratings
+                    .get_expected_goals(home_team, away_team)
+                    .and_then(|expected_goals| {
+                        Ok((expected_goals, ratings.get_model(home_team, away_team)?))
+                    }) {
+                    Ok((expected_goals, model)) => {
+                        Some((home_team, away_team, expected_goals, model))
+                    }
+                    Err(e) => {
+                        self.output_error(&output_mode, e.to_string())?;
+                        return Ok(());
+                    }
+                }
+            }
+            (None, None) => None,
+            _ => {
+                self.output_error(
+                    &output_mode,
+                    "A fixture needs both 'home-team' and 'away-team'.".to_owned(),
+                )?;
+                return Ok(());
+            }
+        };
+        if let Some(save) = matches.get_one::<String>("save") {
+            if let Err(e) = ratings.write(save) {
+                self.output_error(&output_mode, e.to_string())?;
+            }
+        }
+
+        match output_mode {
+            OutputMode::Text => {
+                print_skipped_rows(&skipped);
+                ratings.print_text();
+                if let Some((home_team, away_team, (home_xg, away_xg), model)) = fixture {
+                    let (home, draw, away) =
+                        model.get_result_probabilities(Period::FullTime.minutes());
+                    println!();
+                    println!(
+                        "{} v {}: expected goals {:.2}-{:.2}. Home {:.1}%, draw {:.1}%, away {:.1}%.",
+                        home_team,
+                        away_team,
+                        home_xg,
+                        away_xg,
+                        home * 100.0,
+                        draw * 100.0,
+                        away * 100.0
+                    );
+                }
+            }
+            OutputMode::Json => {
+                let fixture = fixture.map(|(home_team, away_team, (home_xg, away_xg), model)| {
+                    let (home_win, draw, away_win) =
+                        model.get_result_probabilities(Period::FullTime.minutes());
+                    JsonFixture {
+                        home_team: home_team.clone(),
+                        away_team: away_team.clone(),
+                        home_xg,
+                        away_xg,
+                        home_win,
+                        draw,
+                        away_win,
+                    }
+                });
+                let output = JsonRatingsOutput {
+                    ratings: ratings.to_json(),
+                    skipped_rows: skipped.iter().map(SkippedRow::to_json).collect(),
+                    fixture,
+                };
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn simulate(&self, matches: clap::ArgMatches) -> Result<()> {
+        let output_mode = self.get_output_mode(matches.clone());
+        if matches.contains_id("current_score") || matches.contains_id("minutes_remaining") {
+            self.output_error(&output_mode, "Simulations are of bets before kick-off, so 'current-score' and 'minutes-remaining' aren't supported.".to_owned())?;
+        }
+        let mut counts = vec![];
+        for (name, default) in [
+            ("samples", Some(10_000)),
+            ("matches", None),
+            ("paths", Some(1000)),
+        ] {
+            counts.push(match matches.get_one::<String>(name) {
+                None => default,
+                Some(count) => match count.parse::<usize>() {
+                    Ok(count) if count > 0 => Some(count),
+                    _ => {
+                        self.output_error(
+                            &output_mode,
+                            format!(
+                                "Invalid {} '{}'. Must be a positive whole number.",
+                                name, count
+                            ),
+                        )?;
+                        return Ok(());
+                    }
+                },
+            });
+        }
+        let (samples, bankroll_matches, paths) =
+            (counts[0].unwrap(), counts[1], counts[2].unwrap());
+        let seed = match matches.get_one::<String>("seed") {
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_nanos() as u64)
+                .unwrap_or(0),
+            Some(seed) => match seed.parse::<u64>() {
+                Ok(seed) => seed,
+                Err(_) => {
+                    self.output_error(
+                        &output_mode,
+                        format!("Invalid seed '{}'. Must be a whole number.", seed),
+                    )?;
+                    return Ok(());
+                }
+            },
+        };
+        let bankroll = match matches.get_one::<String>("bankroll") {
+            None => Rational::from_integer(100),
+            Some(bankroll) => match Rational::from_str(bankroll) {
+                Ok(bankroll) if bankroll.is_positive() => bankroll,
+                _ => {
+                    self.output_error(
+                        &output_mode,
+                        format!(
+                            "Invalid bankroll '{}'. Must be a positive number.",
+                            bankroll
+                        ),
+                    )?;
+                    return Ok(());
+                }
+            },
+        };
+        let staking = match matches.get_one::<String>("staking") {
+            None => StakingPlan::Flat,
+            Some(staking) => match StakingPlan::from_str(staking) {
+                Ok(staking) => staking,
+                Err(_) => {
+                    self.output_error(&output_mode, format!("Unsupported staking plan '{}'. Must be one of 'flat', 'proportional' or 'kelly' ('f', 'p' or 'k').", staking))?;
+                    return Ok(());
+                }
+            },
+        };
+        let kelly_fraction = match matches.get_one::<String>("kelly_fraction") {
+            None => 1.0,
+            Some(kelly_fraction) => match kelly_fraction.parse::<f64>() {
+                Ok(kelly_fraction) if kelly_fraction > 0.0 && kelly_fraction <= 1.0 => {
+                    kelly_fraction
+                }
+                _ => {
+                    self.output_error(&output_mode, format!("Invalid Kelly fraction '{}'. Must be above 0 and at most 1 (for example, 0.5 for half Kelly).", kelly_fraction))?;
+                    return Ok(());
+                }
+            },
+        };
+        let model = match self.get_model(matches.clone(), None, Period::FullTime, &output_mode)? {
+            Some(model) => model,
+            None => {
+                self.output_error(
+                    &output_mode,
+                    "Simulations need a probability model. Pass 'home-xg' and 'away-xg' (or 'ratings')."
+                        .to_owned(),
+                )?;
+                return Ok(());
+            }
+        };
+
+        let path = matches.get_one::<String>("bets").unwrap();
+        let bets = match read_bets_file(path) {
+            Ok(bets) => bets,
+            Err(e) => {
+                self.output_error(&output_mode, e.to_string())?;
+                return Ok(());
+            }
+        };
+        let score_probabilities = model.get_score_probabilities(None);
+        let mut simulation =
+            match Simulation::new(bets.clone(), &score_probabilities, samples, seed) {
+                Ok(simulation) => simulation,
+                Err(e) => {
+                    self.output_error(&output_mode, e.to_string())?;
+                    return Ok(());
+                }
+            };
+        if let Some(bankroll_matches) = bankroll_matches {
+            match BankrollSimulation::new(
+                bets,
+                &score_probabilities,
+                staking,
+                kelly_fraction,
+                bankroll,
+                bankroll_matches,
+                paths,
+                seed,
+            ) {
+                Ok(bankroll_simulation) => {
+                    simulation = simulation.with_bankroll(bankroll_simulation);
+                }
+                Err(e) => {
+                    self.output_error(&output_mode, e.to_string())?;
+                    return Ok(());
+                }
+            }
+        }
+
+        match output_mode {
+            OutputMode::Text => simulation.print_text(),
+            OutputMode::Json => {
+                println!("{}", serde_json::to_string_pretty(&simulation.to_json())?);
+            }
+        }
+        Ok(())
+    }
+
+    // The final score and, if given, the half-time score
+    fn get_result(
+        &self,
+        matches: clap::ArgMatches,
+        output_mode: &OutputMode,
+    ) -> Result<(Score, Option<Score>)> {
+        let mut scores = vec![];
+        for name in ["score", "half_time_score"] {
+            scores.push(match matches.get_one::<String>(name) {
+                None => None,
+                Some(score) => match Score::from_str(score) {
+                    Ok(score) => Some(score),
+                    Err(e) => {
+                        self.output_error(output_mode, e.to_string())?;
+                        None
+                    }
+                },
+            });
+        }
+        Ok((scores[0].unwrap(), scores[1]))
+    }
+
+    // Even money payoffs per unit staked turn into what `exchange_bet` receives; without one,
+    // they stay as they are
+    fn get_pricing(&self, exchange_bet: Option<&ExchangeBet>) -> ExchangeBet {
+        exchange_bet
+            .cloned()
+            .unwrap_or_else(ExchangeBet::even_money)
+    }
+
+    fn print_expected_value(&self, expected_value: f64, exchange_bet: Option<&ExchangeBet>) {
+        match exchange_bet {
+            Some(_) => println!("Expected profit: {:.4}", expected_value),
+            None => println!("Expected value: {:.4} per unit staked", expected_value),
+        }
+    }
+
+    fn output_error(&self, output_mode: &OutputMode, error_message: String) -> Result<()> {
+        match output_mode {
+            OutputMode::Text => bail!(error_message),
+            OutputMode::Json => {
+                let error = Error {
+                    error: error_message,
+                };
+                let json_error = serde_json::to_string_pretty(&error);
+                match json_error {
+                    Ok(serialized_error) => bail!(serialized_error),
+                    _ => bail!("{{\"error\": \"unexpected_error\"}}"),
+                }
+            }
+        };
+    }
+
+    fn get_output_mode(&self, matches: clap::ArgMatches) -> OutputMode {
+        match matches.get_one::<String>("output").map(String::as_str) {
+            Some("json") | Some("j") => OutputMode::Json,
+            _ => OutputMode::Text,
+        }
+    }
+
+    // The outcomes output is text output, with market grids showing the outcome of the bet
+    // on each score instead of its payoff
+    fn shows_outcomes(&self, matches: &clap::ArgMatches) -> bool {
+        matches!(
+            matches.get_one::<String>("output").map(String::as_str),
+            Some("outcomes") | Some("o")
+        )
+    }
+
+    fn get_grid_size(&self, matches: clap::ArgMatches) -> Result<usize> {
+        let size: usize = match matches.get_one::<String>("grid_size").map(String::as_str) {
+            Some(grid_size) => grid_size.parse()?,
+            _ => DEFAULT_FB_GRID_SIZE,
+        };
+        Ok(size)
+    }
+
+    fn get_grid_bounds(
+        &self,
+        matches: clap::ArgMatches,
+        output_mode: &OutputMode,
+    ) -> Result<GridBounds> {
+        let current_score = self.get_current_score(matches.clone(), output_mode)?;
+        self.get_grid_bounds_from(matches, current_score, output_mode)
+    }
+
+    // Grid bounds whose default square starts at `in_play_score`
+    fn get_grid_bounds_from(
+        &self,
+        matches: clap::ArgMatches,
+        in_play_score: Option<Score>,
+        output_mode: &OutputMode,
+    ) -> Result<GridBounds> {
+        let grid_size = self.get_grid_size(matches.clone())?;
+        if grid_size == 0 {
+            self.output_error(output_mode, "Grid size must be at least 1.".to_owned())?;
+        }
+
+        // Each bound defaults to the square grid, starting at the current score in play, and
+        // can be overridden individually
+        let mut bounds = GridBounds::square(grid_size);
+        if let Some(in_play_score) = in_play_score {
+            for (bound, goals) in [
+                (&mut bounds.min_home_goals, in_play_score.home_goals),
+                (&mut bounds.max_home_goals, in_play_score.home_goals),
+                (&mut bounds.min_away_goals, in_play_score.away_goals),
+                (&mut bounds.max_away_goals, in_play_score.away_goals),
+            ] {
+                match bound.checked_add(goals) {
+                    Some(goals) => *bound = goals,
+                    None => self.output_error(
+                        output_mode,
+                        format!(
+                            "The grid can't start at the score {}. There are too many goals to show.",
+                            in_play_score
+                        ),
+                    )?,
+                }
+            }
+        }
+        for (arg, bound) in [
+            ("min-home-goals", &mut bounds.min_home_goals),
+            ("max-home-goals", &mut bounds.max_home_goals),
+            ("min-away-goals", &mut bounds.min_away_goals),
+            ("max-away-goals", &mut bounds.max_away_goals),
+        ] {
+            if let Some(value) = matches.get_one::<String>(&arg.replace('-', "_")) {
+                match value.parse() {
+                    Ok(value) => *bound = value,
+                    Err(_) => self.output_error(
+                        output_mode,
+                        format!(
+                            "Invalid {} '{}'. Must be a whole number of goals.",
+                            arg, value
+                        ),
+                    )?,
+                }
+            }
+        }
+
+        bounds.tail = matches.get_flag("tail");
+
+        if bounds.min_home_goals > bounds.max_home_goals
+            || bounds.min_away_goals > bounds.max_away_goals
+        {
+            self.output_error(
+                output_mode,
+                format!(
+                    "Minimum goals can't be above maximum goals. Got home {}-{} and away {}-{}.",
+                    bounds.min_home_goals,
+                    bounds.max_home_goals,
+                    bounds.min_away_goals,
+                    bounds.max_away_goals
+                ),
+            )?;
+        }
+        Ok(bounds)
+    }
+
+    fn get_current_score(
+        &self,
+        matches: clap::ArgMatches,
+        output_mode: &OutputMode,
+    ) -> Result<Option<Score>> {
+        self.get_score(matches, "current_score", output_mode)
+    }
+
+    // An optional score argument, e.g. the current score
+    fn get_score(
+        &self,
+        matches: clap::ArgMatches,
+        name: &str,
+        output_mode: &OutputMode,
+    ) -> Result<Option<Score>> {
+        match matches.get_one::<String>(name) {
+            None => Ok(None),
+            Some(score) => match Score::from_str(score) {
+                Ok(score) => Ok(Some(score)),
+                Err(e) => {
+                    self.output_error(output_mode, e.to_string())?;
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    // The result of extra time (and penalties) after a draw in regulation, if given
+    fn get_extra_time(
+        &self,
+        matches: clap::ArgMatches,
+        output_mode: &OutputMode,
+    ) -> Result<Option<ExtraTime>> {
+        let score = match matches.get_one::<String>("extra_time_score") {
+            None => {
+                if matches.contains_id("penalty_winner") {
+                    self.output_error(
+                        output_mode,
+                        "The 'penalty-winner' argument needs the 'extra-time-score' too."
+                            .to_owned(),
+                    )?;
+                }
+                return Ok(None);
+            }
+            Some(score) => match Score::from_str(score) {
+                Ok(score) => score,
+                Err(e) => {
+                    self.output_error(output_mode, e.to_string())?;
+                    return Ok(None);
+                }
+            },
+        };
+        let penalty_winner = match matches.get_one::<String>("penalty_winner") {
+            None => None,
+            Some(_) if score.home_goals != score.away_goals => {
+                self.output_error(
+                    output_mode,
+                    format!(
+                        "Extra time finished {}, so there was no penalty shootout.",
+                        score
+                    ),
+                )?;
+                return Ok(None);
+            }
+            Some(penalty_winner) => match HomeAwaySide::from_str(penalty_winner) {
+                Ok(side) => Some(side),
+                Err(_) => {
+                    self.output_error(output_mode, format!("Invalid penalty winner '{}'. Should be one of: 'home' or 'away' ('h' or 'a').", penalty_winner))?;
+                    return Ok(None);
+                }
+            },
+        };
+        Ok(Some(ExtraTime {
+            score,
+            penalty_winner,
+        }))
+    }
+
+    // The exchange bet from the lay, odds, stake and commission arguments, if any were given
+    fn get_exchange_bet(
+        &self,
+        matches: clap::ArgMatches,
+        output_mode: &OutputMode,
+    ) -> Result<Option<ExchangeBet>> {
+        let args = ["odds", "stake", "commission"];
+        if !matches.get_flag("lay") && !args.iter().any(|arg| matches.contains_id(arg)) {
+            return Ok(None);
+        }
+
+        let even_money = ExchangeBet::even_money();
+        let mut values = [even_money.odds, even_money.stake, even_money.commission];
+        for (arg, value) in args.iter().zip(values.iter_mut()) {
+            if let Some(input) = matches.get_one::<String>(arg) {
+                match Rational::from_str(input) {
+                    Ok(parsed) => *value = parsed,
+                    Err(e) => {
+                        self.output_error(output_mode, format!("Invalid {}: {}", arg, e))?;
+                    }
+                }
+            }
+        }
+        let [odds, stake, commission] = values;
+        let back_lay = match matches.get_flag("lay") {
+            true => BackLay::Lay,
+            false => BackLay::Back,
+        };
+        match ExchangeBet::new(back_lay, odds, stake, commission) {
+            Ok(exchange_bet) => Ok(Some(exchange_bet)),
+            Err(e) => {
+                self.output_error(output_mode, e.to_string())?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn get_period(&self, matches: clap::ArgMatches, output_mode: &OutputMode) -> Result<Period> {
+        match matches.get_one::<String>("period") {
+            None => Ok(Period::FullTime),
+            Some(period) => match Period::from_str(period) {
+                Ok(period) => Ok(period),
+                Err(_) => {
+                    self.output_error(output_mode, format!("Unsupported period '{}'. Must be one of 'full-time', 'first-half' or 'second-half' ('ft', '1h' or '2h').", period))?;
+                    Ok(Period::FullTime)
+                }
+            },
+        }
+    }
+
+    // Goal model from the expected goals arguments, covering the goals still to come in the period
+    fn get_model(
+        &self,
+        matches: clap::ArgMatches,
+        current_score: Option<Score>,
+        period: Period,
+        output_mode: &OutputMode,
+    ) -> Result<Option<PoissonModel>> {
+        let home_xg = matches.get_one::<String>("home_xg");
+        let away_xg = matches.get_one::<String>("away_xg");
+        let ratings = matches.get_one::<String>("ratings");
+        let minutes_remaining = matches.get_one::<String>("minutes_remaining");
+
+        let model = match (ratings, home_xg, away_xg) {
+            (None, None, None) => {
+                if minutes_remaining.is_some() {
+                    self.output_error(output_mode, "The 'minutes-remaining' argument needs a probability model. Pass 'home-xg' and 'away-xg' (or 'ratings') too.".to_owned())?;
+                }
+                return Ok(None);
+            }
+            (None, Some(home_xg), Some(away_xg)) => {
+                let mut expected_goals = vec![];
+                for (arg, value) in [("home-xg", home_xg), ("away-xg", away_xg)] {
+                    match value.parse::<f64>() {
+                        Ok(value) => expected_goals.push(value),
+                        Err(_) => self.output_error(
+                            output_mode,
+                            format!(
+                                "Invalid {} '{}'. Must be a number of goals, for example 1.4.",
+                                arg, value
+                            ),
+                        )?,
+                    }
+                }
+                PoissonModel::new(expected_goals[0], expected_goals[1])
+            }
+            (Some(path), None, None) => {
+                let home_team = matches.get_one::<String>("home_team");
+                let away_team = matches.get_one::<String>("away_team");
+                match (home_team, away_team) {
+                    (Some(home_team), Some(away_team)) => Ratings::read(path)
+                        .and_then(|ratings| ratings.get_model(home_team, away_team)),
+                    _ => {
+                        self.output_error(output_mode, "A model from 'ratings' needs the teams. Pass 'home-team' and 'away-team'.".to_owned())?;
+                        return Ok(None);
+                    }
+                }
+            }
+            (Some(_), _, _) => {
+                self.output_error(output_mode, "Pass either 'ratings' or 'home-xg' and 'away-xg' for the probability model, not both.".to_owned())?;
+                return Ok(None);
+            }
+            _ => {
+                self.output_error(output_mode, "The probability model needs both 'home-xg' and 'away-xg' (expected goals over a full match).".to_owned())?;
+                return Ok(None);
+            }
+        };
+        // Ratings come with their own rho, which 'rho' overrides
+        let model = match matches.contains_id("rho") {
+            true => {
+                let rho = self.get_rho(&matches, output_mode)?;
+                model.and_then(|model| model.with_rho(rho))
+            }
+            false => model,
+        };
+        let model = match model {
+            Ok(model) => model,
+            Err(e) => {
+                self.output_error(output_mode, e.to_string())?;
+                return Ok(None);
+            }
+        };
+
+        let minutes_remaining: f64 = match (minutes_remaining, current_score) {
+            (Some(minutes_remaining), _) => match minutes_remaining.parse() {
+                Ok(minutes_remaining) => minutes_remaining,
+                Err(_) => {
+                    self.output_error(
+                        output_mode,
+                        format!(
+                            "Invalid minutes remaining '{}'. Must be a number of minutes.",
+                            minutes_remaining
+                        ),
+                    )?;
+                    return Ok(None);
+                }
+            },
+            (None, None) => period.minutes(),
+            (None, Some(_)) => {
+                self.output_error(output_mode, "Couldn't find required argument 'minutes-remaining' for an in-play probability model.".to_owned())?;
+                return Ok(None);
+            }
+        };
+        if !(0.0..=period.minutes()).contains(&minutes_remaining) {
+            self.output_error(
+                output_mode,
+                format!(
+                    "Invalid minutes remaining {}. Must be between 0 and {} for the {} period.",
+                    minutes_remaining,
+                    period.minutes(),
+                    period.to_string().to_lowercase()
+                ),
+            )?;
+        }
+        Ok(Some(model.for_minutes_remaining(minutes_remaining)))
+    }
+
+    // The Dixon-Coles correction for the probability model, 0 (independent goals) by default
+    fn get_rho(&self, matches: &clap::ArgMatches, output_mode: &OutputMode) -> Result<f64> {
+        match matches.get_one::<String>("rho") {
+            None => Ok(0.0),
+            Some(rho) => match rho.parse::<f64>() {
+                Ok(rho) if rho.is_finite() => Ok(rho),
+                _ => {
+                    self.output_error(
+                        output_mode,
+                        format!("Invalid rho '{}'. Must be a number, for example -0.1.", rho),
+                    )?;
+                    Ok(0.0)
+                }
+            },
+        }
+    }
+
+    // The backtest and calibration take each match's model from the expected goals added to
+    // the results file, so a file without those columns can't be used at all
+    fn check_expected_goals_columns(
+        &self,
+        historical_matches: &HistoricalMatches,
+        path: &str,
+        purpose: &str,
+        output_mode: &OutputMode,
+    ) -> Result<()> {
+        for column in ["HXG", "AXG"] {
+            if !historical_matches.has_column(column) {
+                self.output_error(output_mode, format!("The results file '{}' has no '{}' column. The {} needs each match's pre-match expected goals from your model, the home team's in an HXG column and the away team's in an AXG column.", path, column, purpose))?;
+            }
+        }
+        Ok(())
     }
 
     fn get_bet_type(&self, matches: clap::ArgMatches, output_mode: &OutputMode) -> Result<BetType> {
         let bet_type_input = matches.get_one::<String>("bet_type");
         match bet_type_input {
             None => {
-                self.output_error(&output_mode, "Couldn't find required argument 'bet_type'. Must be one of 'win-draw-win', 'asian-handicap' or 'over-under'.".to_owned())?;
+                self.output_error(output_mode, "Couldn't find required argument 'bet_type'. Must be one of 'win-draw-win', 'asian-handicap', 'over-under', 'ht-ft', 'first-to-score', 'last-to-score', 'race-to', 'score-both-halves', 'to-qualify', 'to-lift-trophy', 'aggregate-winner', 'aggregate-handicap' or 'aggregate-total'.".to_owned())?;
                 // dummy return, the above always errors
-                return Ok(BetType::WinDrawWin);
+                Ok(BetType::WinDrawWin)
             }
             Some(matched_bet_type) => {
                 match matched_bet_type.as_str() {
                     "win-draw-win" => Ok(BetType::WinDrawWin),
                     "asian-handicap" => Ok(BetType::AsianHandicap),
                     "over-under" => Ok(BetType::OverUnder),
+                    "ht-ft" => Ok(BetType::HalfTimeFullTime),
+                    "first-to-score" => Ok(BetType::FirstToScore),
+                    "last-to-score" => Ok(BetType::LastToScore),
+                    "race-to" => Ok(BetType::RaceTo),
+                    "score-both-halves" => Ok(BetType::ScoreInBothHalves),
+                    // A final is settled like any other knockout tie
+                    "to-qualify" | "to-lift-trophy" => Ok(BetType::ToQualify),
+                    "aggregate-winner" => Ok(BetType::AggregateWinner),
+                    "aggregate-handicap" => Ok(BetType::AggregateHandicap),
+                    "aggregate-total" => Ok(BetType::AggregateTotal),
                     other => {
                         let error_message = format!("Unsupported bet type '{}'", other);
-                        self.output_error(&output_mode, error_message)?;
+                        self.output_error(output_mode, error_message)?;
                         // dummy return, the above always errors
-                        return Ok(BetType::WinDrawWin);
+                        Ok(BetType::WinDrawWin)
                     }
                 }
             }
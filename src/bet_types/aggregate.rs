@@ -0,0 +1,309 @@
+use crate::bet_types::asian_handicap::AsianHandicap;
+use crate::bet_types::over_under::OverUnder;
+use crate::bet_types::to_qualify::{ExtraTime, ToQualify};
+use crate::grid::{Grid, GridBounds};
+use crate::market_specifiers::{HomeAwaySide, Line, OverUnderSide};
+use crate::rational::Rational;
+use crate::score::Score;
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A two-legged tie going into its second leg. Home and away are the teams' roles in the
+/// second leg, so the second-leg away team hosted the first leg.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoLeggedTie {
+    /// First-leg score as played: the first-leg home team (second-leg away team) first
+    pub first_leg: Score,
+    pub away_goals_rule: bool,
+}
+
+impl TwoLeggedTie {
+    /// Aggregate score for the second-leg home and away teams after a second leg of
+    /// `home_goals`-`away_goals`
+    pub fn aggregate(&self, home_goals: usize, away_goals: usize) -> Score {
+        Score::new(
+            self.first_leg.away_goals + home_goals,
+            self.first_leg.home_goals + away_goals,
+        )
+    }
+
+    /// The team that goes through after the second leg, or `None` if the tie goes to extra time
+    pub fn winner(&self, home_goals: usize, away_goals: usize) -> Option<HomeAwaySide> {
+        let aggregate = self.aggregate(home_goals, away_goals);
+        match aggregate.home_goals.cmp(&aggregate.away_goals) {
+            Ordering::Greater => return Some(HomeAwaySide::Home),
+            Ordering::Less => return Some(HomeAwaySide::Away),
+            Ordering::Equal => {}
+        }
+        // Each team's away goals: the second-leg home team played away in the first leg
+        match (
+            self.away_goals_rule,
+            self.first_leg.away_goals.cmp(&away_goals),
+        ) {
+            (true, Ordering::Greater) => Some(HomeAwaySide::Home),
+            (true, Ordering::Less) => Some(HomeAwaySide::Away),
+            _ => None,
+        }
+    }
+
+    /// The team that goes through after extra time in the second leg. With the away goals
+    /// rule, a score draw in extra time sends the visitors through.
+    pub fn extra_time_winner(&self, extra_time: &ExtraTime) -> Option<HomeAwaySide> {
+        let score = extra_time.score;
+        if self.away_goals_rule && score.home_goals == score.away_goals && score.away_goals > 0 {
+            return Some(HomeAwaySide::Away);
+        }
+        extra_time.winner()
+    }
+
+    /// Chance of `side` going through after extra time, from the extra time result
+    /// probabilities (home win, draw, away win) and the chance of no goals in extra time
+    pub fn get_extra_time_probability(
+        &self,
+        side: &HomeAwaySide,
+        extra_time_results: (f64, f64, f64),
+        goalless_probability: f64,
+    ) -> f64 {
+        let (home_win, draw, away_win) = extra_time_results;
+        let extra_time_results = match self.away_goals_rule {
+            true => (
+                home_win,
+                goalless_probability,
+                away_win + draw - goalless_probability,
+            ),
+            false => extra_time_results,
+        };
+        ToQualify::get_extra_time_probability(side, extra_time_results)
+    }
+}
+
+impl fmt::Display for TwoLeggedTie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let aggregate = self.aggregate(0, 0);
+        write!(
+            f,
+            "Home {}-{} Away on aggregate before the second leg",
+            aggregate.home_goals, aggregate.away_goals
+        )?;
+        if self.away_goals_rule {
+            write!(f, ", away goals rule")?;
+        }
+        Ok(())
+    }
+}
+
+/// Second-leg markets settled on the aggregate score over both legs
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregateMarket {
+    Winner(HomeAwaySide),
+    AsianHandicap(HomeAwaySide, Line),
+    OverUnder(OverUnderSide, Line),
+}
+
+impl fmt::Display for AggregateMarket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AggregateMarket::Winner(side) => write!(f, "Aggregate winner: {}", side),
+            AggregateMarket::AsianHandicap(side, handicap) => {
+                write!(
+                    f,
+                    "Aggregate Asian Handicap: {} {}",
+                    side,
+                    handicap.describe()
+                )
+            }
+            AggregateMarket::OverUnder(side, goals) => {
+                write!(f, "Aggregate Over/Under: {} {}", side, goals.describe())
+            }
+        }
+    }
+}
+
+impl AggregateMarket {
+    /// Grid over second-leg scores. For the aggregate winner, ties that go to extra time are
+    /// settled on `extra_time` if it's known, otherwise they are labelled "ET", together with
+    /// the chance of `side` going through if the model gave one. Tail cells holding such a tie
+    /// are ambiguous.
+    pub fn get_payoff_grid(
+        &self,
+        tie: &TwoLeggedTie,
+        bounds: &GridBounds,
+        extra_time: Option<&ExtraTime>,
+        extra_time_probability: Option<f64>,
+    ) -> Result<Grid> {
+        let mut payoff_grid = Grid::new(bounds);
+        payoff_grid.set_title(format!("{} ({})", self, tie));
+        payoff_grid.fill_possible_payoffs(|home_goals, away_goals| {
+            self.get_payoff(tie, home_goals, away_goals, extra_time)
+        })?;
+
+        for home_goals in bounds.home_goals() {
+            for away_goals in bounds.away_goals() {
+                let is_tail = bounds.is_home_tail(home_goals) || bounds.is_away_tail(away_goals);
+                let holds_extra_time = bounds.home_goals_in_row(home_goals).any(|home| {
+                    bounds
+                        .away_goals_in_col(away_goals)
+                        .any(|away| self.get_payoff(tie, home, away, extra_time).is_none())
+                });
+                if is_tail && holds_extra_time {
+                    payoff_grid.set_ambiguous(home_goals, away_goals)?;
+                }
+                if is_tail
+                    || tie.winner(home_goals, away_goals).is_some()
+                    || payoff_grid.get_payoff(home_goals, away_goals).is_some()
+                {
+                    continue;
+                }
+                let label = match extra_time_probability {
+                    Some(probability) => format!("ET {:.0}%", probability * 100.0),
+                    None => "ET".to_owned(),
+                };
+                payoff_grid.set_label(home_goals, away_goals, label)?;
+            }
+        }
+        Ok(payoff_grid)
+    }
+
+    /// Payoff (per unit staked at even money) after a second leg of `home_goals`-`away_goals`.
+    /// `None` for an aggregate winner bet when the tie goes to extra time and its result
+    /// isn't known.
+    pub fn get_payoff(
+        &self,
+        tie: &TwoLeggedTie,
+        home_goals: usize,
+        away_goals: usize,
+        extra_time: Option<&ExtraTime>,
+    ) -> Option<Rational> {
+        let aggregate = tie.aggregate(home_goals, away_goals);
+        match self {
+            AggregateMarket::Winner(side) => {
+                let winner = match tie.winner(home_goals, away_goals) {
+                    Some(winner) => winner,
+                    None => tie.extra_time_winner(extra_time?)?,
+                };
+                match winner == *side {
+                    true => Some(Rational::one()),
+                    false => Some(-Rational::one()),
+                }
+            }
+            AggregateMarket::AsianHandicap(side, handicap) => Some(AsianHandicap::get_payoff(
+                side,
+                *handicap,
+                aggregate.home_goals,
+                aggregate.away_goals,
+            )),
+            AggregateMarket::OverUnder(side, goals) => Some(OverUnder::get_payoff(
+                side,
+                *goals,
+                aggregate.home_goals,
+                aggregate.away_goals,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // First leg 2-1 to the second-leg away team
+    fn tie(away_goals_rule: bool) -> TwoLeggedTie {
+        TwoLeggedTie {
+            first_leg: Score::new(2, 1),
+            away_goals_rule,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_winner() {
+        let market = AggregateMarket::Winner(HomeAwaySide::Home);
+        let payoff = |tie: &TwoLeggedTie, home_goals, away_goals| {
+            market.get_payoff(tie, home_goals, away_goals, None)
+        };
+
+        assert_eq!(payoff(&tie(false), 2, 0), Some(Rational::one()));
+        assert_eq!(payoff(&tie(false), 0, 0), Some(-Rational::one()));
+        // 3-3 on aggregate: extra time without the away goals rule
+        assert_eq!(payoff(&tie(false), 2, 1), None);
+        assert_eq!(payoff(&tie(false), 1, 0), None);
+        // With it, 1-0 sends the hosts through (one away goal to none) and 3-2 the visitors,
+        // while 2-1 is still level on away goals
+        assert_eq!(payoff(&tie(true), 1, 0), Some(Rational::one()));
+        assert_eq!(payoff(&tie(true), 3, 2), Some(-Rational::one()));
+        assert_eq!(payoff(&tie(true), 2, 1), None);
+
+        let extra_time = ExtraTime {
+            score: Score::new(1, 1),
+            penalty_winner: Some(HomeAwaySide::Home),
+        };
+        assert_eq!(
+            market.get_payoff(&tie(false), 1, 0, Some(&extra_time)),
+            Some(Rational::one())
+        );
+        // A score draw in extra time counts the away goals too
+        assert_eq!(
+            market.get_payoff(&tie(true), 2, 1, Some(&extra_time)),
+            Some(-Rational::one())
+        );
+    }
+
+    #[test]
+    fn test_tail_ties_are_ambiguous() {
+        let market = AggregateMarket::Winner(HomeAwaySide::Home);
+        let mut bounds = GridBounds::square(4);
+        bounds.tail = true;
+        let json_grid = market
+            .get_payoff_grid(&tie(false), &bounds, None, None)
+            .unwrap()
+            .to_json();
+        let is_ambiguous = |home_goals, away_goals| {
+            json_grid
+                .ambiguous_cells
+                .iter()
+                .any(|cell| cell.home_goals == home_goals && cell.away_goals == away_goals)
+        };
+        // 3+ against 2 holds 3-2, level on aggregate, though every other score sends the
+        // hosts through
+        assert_eq!(json_grid.payoff_grid[&3][&2], 1.0);
+        assert!(is_ambiguous(3, 2));
+        assert!(!is_ambiguous(3, 1));
+    }
+
+    #[test]
+    fn test_aggregate_lines() {
+        let handicap =
+            AggregateMarket::AsianHandicap(HomeAwaySide::Home, Line::from_str("-0.5").unwrap());
+        // 1-0 in the second leg: 2-2 on aggregate
+        assert_eq!(
+            handicap.get_payoff(&tie(true), 1, 0, None),
+            Some(-Rational::one())
+        );
+        assert_eq!(
+            handicap.get_payoff(&tie(true), 2, 0, None),
+            Some(Rational::one())
+        );
+
+        let total = AggregateMarket::OverUnder(OverUnderSide::Over, Line::from_str("3.5").unwrap());
+        assert_eq!(
+            total.get_payoff(&tie(false), 0, 0, None),
+            Some(-Rational::one())
+        );
+        assert_eq!(
+            total.get_payoff(&tie(false), 1, 0, None),
+            Some(Rational::one())
+        );
+    }
+
+    #[test]
+    fn test_extra_time_cells_are_labelled() {
+        let grid = AggregateMarket::Winner(HomeAwaySide::Away)
+            .get_payoff_grid(&tie(false), &GridBounds::square(4), None, Some(0.4))
+            .unwrap();
+        // Level on aggregate whenever the home team wins the second leg by one
+        assert_eq!(grid.to_json().labelled_cells.len(), 3);
+        assert_eq!(grid.to_json().labelled_cells[0].label, "ET 40%");
+        assert_eq!(grid.get_payoff(2, 0), Some(-Rational::one()));
+    }
+}
@@ -1,85 +1,99 @@
-use crate::grid::Grid;
-use crate::market_specifiers::HomeAwaySide;
+use crate::grid::{Grid, GridBounds};
+use crate::market_specifiers::{HomeAwaySide, Line};
+use crate::rational::Rational;
+use crate::score::Score;
 use anyhow::Result;
 
 pub struct AsianHandicap {}
 impl AsianHandicap {
-    pub fn get_payoff_grid(side: HomeAwaySide, handicap: f32, grid_size: usize) -> Result<Grid> {
-        let mut payoff_grid = Grid::new(grid_size);
+    /// Payoff grid over final scores. With a `current_score` the bet is settled by in-running
+    /// rules: only goals scored after the bet was placed count towards the handicap.
+    pub fn get_payoff_grid(
+        side: HomeAwaySide,
+        handicap: Line,
+        bounds: &GridBounds,
+        current_score: Option<Score>,
+    ) -> Result<Grid> {
+        let mut payoff_grid = Grid::new(bounds);
+        payoff_grid.set_title(format!("Asian Handicap: {} {}", side, handicap.describe()));
 
-        match side {
-            HomeAwaySide::Home => {
-                AsianHandicap::set_payoff(&mut payoff_grid, handicap, grid_size, true)?
-            }
-            HomeAwaySide::Away => {
-                AsianHandicap::set_payoff(&mut payoff_grid, handicap, grid_size, false)?
+        match current_score {
+            None => payoff_grid.fill_payoffs(|home_goals, away_goals| {
+                AsianHandicap::get_payoff(&side, handicap, home_goals, away_goals)
+            })?,
+            Some(current_score) => {
+                payoff_grid.set_current_score(current_score);
+                payoff_grid.fill_possible_payoffs(|home_goals, away_goals| {
+                    AsianHandicap::get_in_play_payoff(
+                        &side,
+                        handicap,
+                        current_score,
+                        home_goals,
+                        away_goals,
+                    )
+                })?
             }
         };
         Ok(payoff_grid)
     }
 
-    fn set_payoff(
-        payoff_grid: &mut Grid,
-        handicap: f32,
-        grid_size: usize,
-        is_home: bool,
-    ) -> Result<()> {
-        for home_goals in 0..grid_size {
-            for away_goals in 0..grid_size {
-                let adjusted_score: f32 = if is_home {
-                    home_goals as f32 - away_goals as f32 + handicap
-                } else {
-                    away_goals as f32 - home_goals as f32 + handicap
-                };
+    /// Payoff of a bet placed in play at `current_score`, settled on the final score. `None`
+    /// if the final score can't be reached from the current one.
+    pub fn get_in_play_payoff(
+        side: &HomeAwaySide,
+        handicap: Line,
+        current_score: Score,
+        home_goals: usize,
+        away_goals: usize,
+    ) -> Option<Rational> {
+        Some(AsianHandicap::get_payoff(
+            side,
+            handicap,
+            home_goals.checked_sub(current_score.home_goals)?,
+            away_goals.checked_sub(current_score.away_goals)?,
+        ))
+    }
 
-                if handicap == handicap.floor() {
-                    // round handicap
-                    payoff_grid.set_payoff(
-                        home_goals,
-                        away_goals,
-                        AsianHandicap::get_handicap_payoff(adjusted_score),
-                    )?;
-                } else if handicap.abs() % 0.5 == 0.0 {
-                    // .5 handicap
-                    payoff_grid.set_payoff(
-                        home_goals,
-                        away_goals,
-                        AsianHandicap::get_handicap_payoff(adjusted_score),
-                    )?;
-                } else {
-                    // quarter handicap
-
-                    let lower_half_handicap = handicap - 0.25;
-                    let upper_half_handicap = handicap + 0.25;
-
-                    let lower_adjusted_score = match is_home {
-                        true => home_goals as f32 - away_goals as f32 + lower_half_handicap,
-                        false => away_goals as f32 - home_goals as f32 + lower_half_handicap,
-                    };
-                    let upper_adjusted_score = match is_home {
-                        true => home_goals as f32 - away_goals as f32 + upper_half_handicap,
-                        false => away_goals as f32 - home_goals as f32 + upper_half_handicap,
-                    };
-
-                    let payoff = AsianHandicap::get_handicap_payoff(lower_adjusted_score) * 0.5
-                        + AsianHandicap::get_handicap_payoff(upper_adjusted_score) * 0.5;
-                    payoff_grid.set_payoff(home_goals, away_goals, payoff)?;
-                }
-            }
-        }
+    /// Payoff (per unit staked at even money) of a bet on `side` with the given handicap
+    pub fn get_payoff(
+        side: &HomeAwaySide,
+        handicap: Line,
+        home_goals: usize,
+        away_goals: usize,
+    ) -> Rational {
+        let (home_goals, away_goals) = (
+            i64::try_from(home_goals).unwrap_or(i64::MAX),
+            i64::try_from(away_goals).unwrap_or(i64::MAX),
+        );
+        let goal_difference = match side {
+            HomeAwaySide::Home => home_goals - away_goals,
+            HomeAwaySide::Away => away_goals - home_goals,
+        };
+        // Everything is kept in quarter goals so the comparison with the line is exact. Only
+        // the sign matters, which saturating keeps for any number of goals.
+        let adjusted_quarters = |line: Line| {
+            goal_difference
+                .saturating_mul(4)
+                .saturating_add(line.quarters() as i64)
+        };
 
-        Ok(())
+        match handicap.halves() {
+            // round or .5 handicap
+            None => Rational::from_integer(AsianHandicap::get_handicap_payoff(adjusted_quarters(
+                handicap,
+            ))),
+            // quarter handicap: half the stake goes on each neighbouring line, so the payoff is
+            // the sum of the two in halves (two quarters each)
+            Some((first_half, second_half)) => Rational::from_quarters(
+                2 * (AsianHandicap::get_handicap_payoff(adjusted_quarters(first_half))
+                    + AsianHandicap::get_handicap_payoff(adjusted_quarters(second_half))),
+            ),
+        }
     }
 
-    fn get_handicap_payoff(adjusted_score: f32) -> f64 {
+    fn get_handicap_payoff(adjusted_quarters: i64) -> i64 {
         // handles round and .5 handicaps. quarter handicaps are a combination of these
-        if adjusted_score > 0.0 {
-            return 1.0;
-        } else if adjusted_score == 0.0 {
-            return 0.0;
-        } else {
-            return -1.0;
-        }
+        adjusted_quarters.signum()
     }
 }
 
@@ -91,7 +105,13 @@ mod tests {
     where
         F: Fn(f64) -> f64,
     {
-        let ah = AsianHandicap::get_payoff_grid(side.clone(), handicap, 10).unwrap();
+        let ah = AsianHandicap::get_payoff_grid(
+            side.clone(),
+            handicap.to_string().parse().unwrap(),
+            &GridBounds::square(10),
+            None,
+        )
+        .unwrap();
         let payoff_grid = ah.print_json().unwrap().payoff_grid;
 
         for home_goals in 0..10 {
@@ -199,4 +219,47 @@ mod tests {
             _ => 1.0,
         });
     }
+
+    #[test]
+    fn test_in_play_payoff_ignores_goals_before_bet() {
+        // Home -0.5 backed at 1-0: a 1-0 or 2-1 final score loses, 2-0 wins
+        let current_score = Score::new(1, 0);
+        let ah = AsianHandicap::get_payoff_grid(
+            HomeAwaySide::Home,
+            Line::from_quarters(-2),
+            &GridBounds::square(4),
+            Some(current_score),
+        )
+        .unwrap();
+        let payoff_grid = ah.print_json().unwrap().payoff_grid;
+
+        assert_eq!(payoff_grid[&1][&0], -1.0);
+        assert_eq!(payoff_grid[&2][&1], -1.0);
+        assert_eq!(payoff_grid[&2][&0], 1.0);
+        // Final scores below the current score can't happen and are left out
+        assert!(payoff_grid[&0].is_empty());
+        assert_eq!(
+            AsianHandicap::get_in_play_payoff(
+                &HomeAwaySide::Home,
+                Line::from_quarters(-2),
+                current_score,
+                0,
+                3
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_payoff_with_extreme_goals() {
+        let line = |input: &str| input.parse::<Line>().unwrap();
+        assert_eq!(
+            AsianHandicap::get_payoff(&HomeAwaySide::Home, line("-1000"), usize::MAX, 0),
+            Rational::one()
+        );
+        assert_eq!(
+            AsianHandicap::get_payoff(&HomeAwaySide::Away, line("999.75"), usize::MAX, 0),
+            Rational::from_integer(-1)
+        );
+    }
 }
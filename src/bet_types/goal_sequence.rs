@@ -0,0 +1,290 @@
+use crate::exchange::JsonExchangeBet;
+use crate::goal_sequence::GoalSequence;
+use crate::grid::GridBounds;
+use crate::market_specifiers::{HomeAwaySide, Period};
+use crate::rational::Rational;
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Markets that depend on the order (or timing) of the goals rather than just the final score.
+/// A bet on a team loses if nobody scores, or if neither team gets to the race target.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GoalSequenceMarket {
+    FirstToScore(HomeAwaySide),
+    LastToScore(HomeAwaySide),
+    RaceTo(HomeAwaySide, usize),
+    ScoreInBothHalves(HomeAwaySide),
+}
+
+#[derive(Serialize)]
+pub struct JsonGoalSequenceSettlement {
+    pub market: String,
+    pub goal_sequence: String,
+    pub final_score: String,
+    pub payoff: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exchange: Option<JsonExchangeBet>,
+}
+
+/// The model's view of a goal sequence market, projected onto the final score grid
+#[derive(Serialize)]
+pub struct JsonGoalSequenceModel {
+    pub market: String,
+    #[serde(flatten)]
+    pub bounds: GridBounds,
+    pub win_probability_grid: HashMap<usize, HashMap<usize, f64>>,
+    pub win_probability: f64,
+    pub expected_value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exchange: Option<JsonExchangeBet>,
+}
+
+impl GoalSequenceMarket {
+    fn side(&self) -> &HomeAwaySide {
+        match self {
+            GoalSequenceMarket::FirstToScore(side)
+            | GoalSequenceMarket::LastToScore(side)
+            | GoalSequenceMarket::RaceTo(side, _)
+            | GoalSequenceMarket::ScoreInBothHalves(side) => side,
+        }
+    }
+
+    /// Payoff (per unit staked at even money) for the goals that were scored. Scoring in both
+    /// halves needs the minute of each of the team's goals.
+    pub fn get_payoff(&self, sequence: &GoalSequence) -> Result<Rational> {
+        let side = self.side();
+        let team_goals = sequence.goals.iter().filter(|goal| goal.team == *side);
+        let wins = match self {
+            GoalSequenceMarket::FirstToScore(_) => {
+                sequence.goals.first().map(|goal| &goal.team) == Some(side)
+            }
+            GoalSequenceMarket::LastToScore(_) => {
+                sequence.goals.last().map(|goal| &goal.team) == Some(side)
+            }
+            GoalSequenceMarket::RaceTo(_, goals) => {
+                // Whoever scores their `goals`-th goal first
+                let (mut home, mut away) = (0, 0);
+                let mut winner = None;
+                for goal in &sequence.goals {
+                    match goal.team {
+                        HomeAwaySide::Home => home += 1,
+                        HomeAwaySide::Away => away += 1,
+                    }
+                    if home == *goals || away == *goals {
+                        winner = Some(goal.team.clone());
+                        break;
+                    }
+                }
+                winner.as_ref() == Some(side)
+            }
+            GoalSequenceMarket::ScoreInBothHalves(_) => {
+                let mut halves = vec![];
+                for goal in team_goals {
+                    match goal.half() {
+                        Some(half) => halves.push(half),
+                        None => bail!(
+                            "Can't settle '{}' without the minute of every {} goal (for example H23).",
+                            self,
+                            side.to_string().to_lowercase()
+                        ),
+                    }
+                }
+                halves.contains(&Period::FirstHalf) && halves.contains(&Period::SecondHalf)
+            }
+        };
+
+        match wins {
+            true => Ok(Rational::one()),
+            false => Ok(-Rational::one()),
+        }
+    }
+
+    /// Probability of the bet winning given only the final score, assuming goals come at a
+    /// constant rate (as in the Poisson model): every order of the goals is then equally
+    /// likely, and each goal is equally likely to come at any minute.
+    pub fn get_win_probability(&self, home_goals: usize, away_goals: usize) -> f64 {
+        let (team_goals, other_goals) = match self.side() {
+            HomeAwaySide::Home => (home_goals, away_goals),
+            HomeAwaySide::Away => (away_goals, home_goals),
+        };
+        let total_goals = team_goals + other_goals;
+
+        match self {
+            // The first (or last) goal is equally likely to be any of them
+            GoalSequenceMarket::FirstToScore(_) | GoalSequenceMarket::LastToScore(_) => {
+                match total_goals {
+                    0 => 0.0,
+                    _ => team_goals as f64 / total_goals as f64,
+                }
+            }
+            GoalSequenceMarket::RaceTo(_, goals) => {
+                GoalSequenceMarket::race_win_probability(*goals, team_goals, other_goals)
+            }
+            // Loses only if all the team's goals land in the same half
+            GoalSequenceMarket::ScoreInBothHalves(_) => match team_goals {
+                0 => 0.0,
+                _ => 1.0 - 0.5f64.powi(team_goals as i32 - 1),
+            },
+        }
+    }
+
+    // Probability that the team gets to `goals` first, with the goals drawn in a random order.
+    // `reach[i][j]` is the probability of the team having i and the other team j at some point.
+    fn race_win_probability(goals: usize, team_goals: usize, other_goals: usize) -> f64 {
+        if team_goals < goals {
+            return 0.0;
+        }
+        if other_goals < goals {
+            return 1.0;
+        }
+
+        let mut reach = vec![vec![0.0; goals + 1]; goals + 1];
+        reach[0][0] = 1.0;
+        let mut win_probability = 0.0;
+        for i in 0..=goals {
+            for j in 0..=goals {
+                if i == goals {
+                    win_probability += reach[i][j];
+                    continue;
+                }
+                if j == goals {
+                    continue;
+                }
+                let remaining = (team_goals + other_goals - i - j) as f64;
+                reach[i + 1][j] += reach[i][j] * (team_goals - i) as f64 / remaining;
+                reach[i][j + 1] += reach[i][j] * (other_goals - j) as f64 / remaining;
+            }
+        }
+        win_probability
+    }
+}
+
+impl fmt::Display for GoalSequenceMarket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoalSequenceMarket::FirstToScore(side) => write!(f, "First team to score: {}", side),
+            GoalSequenceMarket::LastToScore(side) => write!(f, "Last team to score: {}", side),
+            GoalSequenceMarket::RaceTo(side, goals) => {
+                write!(f, "Race to {} goals: {}", goals, side)
+            }
+            GoalSequenceMarket::ScoreInBothHalves(side) => {
+                write!(f, "To score in both halves: {}", side)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn payoff(market: GoalSequenceMarket, sequence: &str) -> Rational {
+        market
+            .get_payoff(&GoalSequence::from_str(sequence).unwrap())
+            .unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "Expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_settles_on_goal_order() {
+        let home = HomeAwaySide::Home;
+        let win = Rational::one();
+        let lose = -Rational::one();
+
+        assert_eq!(
+            payoff(GoalSequenceMarket::FirstToScore(home.clone()), "H, A, A"),
+            win
+        );
+        assert_eq!(
+            payoff(GoalSequenceMarket::LastToScore(home.clone()), "H, A, A"),
+            lose
+        );
+        assert_eq!(
+            payoff(GoalSequenceMarket::FirstToScore(home.clone()), "none"),
+            lose
+        );
+        assert_eq!(
+            payoff(GoalSequenceMarket::RaceTo(home.clone(), 2), "A, H, H, A, A"),
+            win
+        );
+        assert_eq!(
+            payoff(GoalSequenceMarket::RaceTo(home.clone(), 3), "A, H, H, A, A"),
+            lose
+        );
+        assert_eq!(
+            payoff(
+                GoalSequenceMarket::ScoreInBothHalves(home.clone()),
+                "H10, A30, H40"
+            ),
+            lose
+        );
+        assert_eq!(
+            payoff(
+                GoalSequenceMarket::ScoreInBothHalves(home),
+                "H45+1, A50, H80"
+            ),
+            win
+        );
+    }
+
+    #[test]
+    fn test_both_halves_needs_goal_times() {
+        let market = GoalSequenceMarket::ScoreInBothHalves(HomeAwaySide::Away);
+        assert!(market
+            .get_payoff(&GoalSequence::from_str("A10, A").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_race_win_probability_matches_every_goal_order() {
+        // Every order of the goals in a 3-2 is equally likely
+        let (home_goals, away_goals) = (3, 2);
+        let market = GoalSequenceMarket::RaceTo(HomeAwaySide::Home, 2);
+        let mut wins = 0;
+        let mut orders = 0;
+        for mask in 0u32..(1 << (home_goals + away_goals)) {
+            if mask.count_ones() as usize != home_goals {
+                continue;
+            }
+            let sequence: Vec<&str> = (0..home_goals + away_goals)
+                .map(|i| if mask & (1 << i) != 0 { "H" } else { "A" })
+                .collect();
+            orders += 1;
+            if payoff(market.clone(), &sequence.join(",")) == Rational::one() {
+                wins += 1;
+            }
+        }
+        assert_close(
+            market.get_win_probability(home_goals, away_goals),
+            wins as f64 / orders as f64,
+        );
+    }
+
+    #[test]
+    fn test_win_probability_given_final_score() {
+        let home = HomeAwaySide::Home;
+        assert_close(
+            GoalSequenceMarket::FirstToScore(home.clone()).get_win_probability(3, 1),
+            0.75,
+        );
+        assert_close(
+            GoalSequenceMarket::ScoreInBothHalves(home.clone()).get_win_probability(2, 0),
+            0.5,
+        );
+        assert_close(
+            GoalSequenceMarket::RaceTo(home, 2).get_win_probability(1, 4),
+            0.0,
+        );
+    }
+}
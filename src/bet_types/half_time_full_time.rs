@@ -0,0 +1,133 @@
+use crate::grid::{Grid, GridBounds, JsonGrid};
+use crate::market_specifiers::{HalfTimeFullTimeSide, HomeAwayDrawSide};
+use crate::rational::Rational;
+use crate::score::Score;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The grid for one half-time result, with the model's view of it when there is one
+#[derive(Serialize)]
+pub struct JsonHalfTimeFullTimePanel {
+    pub half_time_result: String,
+    #[serde(flatten)]
+    pub payoff: JsonGrid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probability_grid: Option<HashMap<usize, HashMap<usize, f64>>>,
+}
+
+#[derive(Serialize)]
+pub struct JsonHalfTimeFullTime {
+    pub panels: Vec<JsonHalfTimeFullTimePanel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_value: Option<f64>,
+}
+
+pub struct HalfTimeFullTime {}
+impl HalfTimeFullTime {
+    /// The outcome is a half-time score and a full-time score, which doesn't fit in one grid.
+    /// Only the half-time result matters though, so this gives one grid over full-time scores
+    /// for each half-time result (home ahead, level, away ahead). Full-time scores that can't
+    /// follow the half-time result are left empty.
+    pub fn get_payoff_grids(
+        side: &HalfTimeFullTimeSide,
+        bounds: &GridBounds,
+    ) -> Result<Vec<(HomeAwayDrawSide, Grid)>> {
+        let mut grids = vec![];
+        for half_time_result in [
+            HomeAwayDrawSide::Home,
+            HomeAwayDrawSide::Draw,
+            HomeAwayDrawSide::Away,
+        ] {
+            let mut payoff_grid = Grid::new(bounds);
+            payoff_grid.set_title(format!(
+                "Half-time/Full-time: {} ({})",
+                side,
+                HalfTimeFullTime::describe_half_time(&half_time_result)
+            ));
+            payoff_grid.fill_possible_payoffs(|home_goals, away_goals| {
+                half_time_result
+                    .can_lead_to(home_goals, away_goals)
+                    .then(|| {
+                        HalfTimeFullTime::get_payoff_for_results(
+                            side,
+                            &half_time_result,
+                            &Score::new(home_goals, away_goals).result(),
+                        )
+                    })
+            })?;
+            grids.push((half_time_result, payoff_grid));
+        }
+        Ok(grids)
+    }
+
+    pub fn describe_half_time(half_time_result: &HomeAwayDrawSide) -> &'static str {
+        match half_time_result {
+            HomeAwayDrawSide::Home => "home team ahead at half time",
+            HomeAwayDrawSide::Draw => "level at half time",
+            HomeAwayDrawSide::Away => "away team ahead at half time",
+        }
+    }
+
+    /// Payoff (per unit staked at even money) of a bet on `side`
+    pub fn get_payoff(side: &HalfTimeFullTimeSide, half_time: Score, full_time: Score) -> Rational {
+        HalfTimeFullTime::get_payoff_for_results(side, &half_time.result(), &full_time.result())
+    }
+
+    fn get_payoff_for_results(
+        side: &HalfTimeFullTimeSide,
+        half_time_result: &HomeAwayDrawSide,
+        full_time_result: &HomeAwayDrawSide,
+    ) -> Rational {
+        if side.half_time == *half_time_result && side.full_time == *full_time_result {
+            Rational::one()
+        } else {
+            -Rational::one()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_payoff_needs_both_results() {
+        let side = HalfTimeFullTimeSide::from_str("d/h").unwrap();
+
+        assert_eq!(
+            HalfTimeFullTime::get_payoff(&side, Score::new(0, 0), Score::new(2, 1)),
+            Rational::one()
+        );
+        assert_eq!(
+            HalfTimeFullTime::get_payoff(&side, Score::new(1, 0), Score::new(2, 1)),
+            -Rational::one()
+        );
+        assert_eq!(
+            HalfTimeFullTime::get_payoff(&side, Score::new(1, 1), Score::new(1, 1)),
+            -Rational::one()
+        );
+    }
+
+    #[test]
+    fn test_payoff_grids_per_half_time_result() {
+        let side = HalfTimeFullTimeSide::from_str("away/home").unwrap();
+        let grids = HalfTimeFullTime::get_payoff_grids(&side, &GridBounds::square(5)).unwrap();
+        let (half_time_result, away_ahead) = &grids[2];
+
+        assert_eq!(*half_time_result, HomeAwayDrawSide::Away);
+        assert_eq!(away_ahead.get_payoff(3, 1), Some(Rational::one()));
+        assert_eq!(away_ahead.get_payoff(1, 1), Some(-Rational::one()));
+        // The away team can't have been ahead without scoring
+        assert_eq!(away_ahead.get_payoff(2, 0), None);
+        // Level at half time: every full-time score is possible but the bet loses
+        assert!(grids[1]
+            .1
+            .to_json()
+            .payoff_grid
+            .values()
+            .flat_map(|row| row.values())
+            .all(|&payoff| payoff == -1.0));
+    }
+}
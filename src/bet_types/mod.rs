@@ -1,3 +1,229 @@
+pub mod aggregate;
 pub mod asian_handicap;
+pub mod goal_sequence;
+pub mod half_time_full_time;
 pub mod over_under;
+pub mod to_qualify;
 pub mod win_draw_win;
+
+use crate::grid::{Grid, GridBounds};
+use crate::market_specifiers::{HomeAwayDrawSide, HomeAwaySide, Line, OverUnderSide, Period};
+use crate::rational::Rational;
+use crate::score::Score;
+use anyhow::{anyhow, Result};
+use asian_handicap::AsianHandicap;
+use over_under::OverUnder;
+use std::fmt;
+use win_draw_win::WinDrawWin;
+
+/// A bet type together with the side (and line) that was bet on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Market {
+    WinDrawWin(HomeAwayDrawSide),
+    AsianHandicap(HomeAwaySide, Line),
+    OverUnder(OverUnderSide, Line),
+}
+
+impl fmt::Display for Market {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Market::WinDrawWin(side) => write!(f, "{}: {}", self.get_type_name(), side),
+            Market::AsianHandicap(side, handicap) => {
+                write!(
+                    f,
+                    "{}: {} {}",
+                    self.get_type_name(),
+                    side,
+                    handicap.describe()
+                )
+            }
+            Market::OverUnder(side, goals) => {
+                write!(f, "{}: {} {}", self.get_type_name(), side, goals.describe())
+            }
+        }
+    }
+}
+
+impl Market {
+    /// Name of the bet type, e.g. "Asian Handicap"
+    pub fn get_type_name(&self) -> &'static str {
+        match self {
+            Market::WinDrawWin(_) => "Win-Draw-Win",
+            Market::AsianHandicap(_, _) => "Asian Handicap",
+            Market::OverUnder(_, _) => "Over/Under",
+        }
+    }
+
+    /// The handicap or goals line, for markets that have one
+    pub fn get_line(&self) -> Option<Line> {
+        match self {
+            Market::WinDrawWin(_) => None,
+            Market::AsianHandicap(_, line) | Market::OverUnder(_, line) => Some(*line),
+        }
+    }
+
+    /// Whether bets on `self` and `other` are on one exchange market, whatever selection each
+    /// backs: the same bet type on the same line. The away side of an Asian handicap is quoted
+    /// on the opposite line, so home -0.5 and away +0.5 are one market.
+    pub fn is_same_exchange_market(&self, other: &Market) -> bool {
+        match (self, other) {
+            (Market::WinDrawWin(_), Market::WinDrawWin(_)) => true,
+            (
+                Market::AsianHandicap(side, handicap),
+                Market::AsianHandicap(other_side, other_handicap),
+            ) => match side == other_side {
+                true => handicap == other_handicap,
+                false => handicap.quarters() == -other_handicap.quarters(),
+            },
+            (Market::OverUnder(_, goals), Market::OverUnder(_, other_goals)) => {
+                goals == other_goals
+            }
+            _ => false,
+        }
+    }
+
+    pub fn get_payoff_grid(
+        &self,
+        bounds: &GridBounds,
+        current_score: Option<Score>,
+    ) -> Result<Grid> {
+        match self {
+            Market::WinDrawWin(side) => {
+                WinDrawWin::get_payoff_grid(side.clone(), bounds, current_score)
+            }
+            Market::AsianHandicap(side, handicap) => {
+                AsianHandicap::get_payoff_grid(side.clone(), *handicap, bounds, current_score)
+            }
+            Market::OverUnder(side, goals) => {
+                OverUnder::get_payoff_grid(side.clone(), *goals, bounds, current_score)
+            }
+        }
+    }
+
+    /// Payoff (per unit staked at even money) for a final score. `current_score` is the score
+    /// when the bet was placed, for markets that settle in-play bets differently. Fails for a
+    /// final score that can't follow it.
+    pub fn get_payoff(
+        &self,
+        current_score: Option<Score>,
+        home_goals: usize,
+        away_goals: usize,
+    ) -> Result<Rational> {
+        match (self, current_score) {
+            (Market::WinDrawWin(side), _) => {
+                Ok(WinDrawWin::get_payoff(side, home_goals, away_goals))
+            }
+            (Market::AsianHandicap(side, handicap), None) => Ok(AsianHandicap::get_payoff(
+                side, *handicap, home_goals, away_goals,
+            )),
+            (Market::AsianHandicap(side, handicap), Some(current_score)) => {
+                AsianHandicap::get_in_play_payoff(
+                    side,
+                    *handicap,
+                    current_score,
+                    home_goals,
+                    away_goals,
+                )
+                .ok_or_else(|| {
+                    anyhow!(
+                        "'{}' was placed at {}, so it can't settle on {}-{}.",
+                        self,
+                        current_score,
+                        home_goals,
+                        away_goals
+                    )
+                })
+            }
+            (Market::OverUnder(side, goals), _) => {
+                Ok(OverUnder::get_payoff(side, *goals, home_goals, away_goals))
+            }
+        }
+    }
+
+    /// Payoff grid of a second-half bet over final scores, given the `half_time_score`: the bet
+    /// is settled on the goals scored after half time. `current_score` is the second-half score
+    /// when the bet was placed in play, as for `get_payoff`.
+    pub fn get_second_half_payoff_grid(
+        &self,
+        bounds: &GridBounds,
+        half_time_score: Score,
+        current_score: Option<Score>,
+    ) -> Result<Grid> {
+        let mut payoff_grid = Grid::new(bounds);
+        payoff_grid.set_title(self.to_string());
+        payoff_grid.set_period(Period::SecondHalf);
+        payoff_grid.set_half_time_score(half_time_score);
+        payoff_grid.set_current_score(match current_score {
+            Some(current_score) => Score::new(
+                half_time_score.home_goals + current_score.home_goals,
+                half_time_score.away_goals + current_score.away_goals,
+            ),
+            None => half_time_score,
+        });
+        payoff_grid.fill_profits(|home_goals, away_goals| {
+            self.get_second_half_payoff(half_time_score, current_score, home_goals, away_goals)
+        })?;
+        Ok(payoff_grid)
+    }
+
+    /// Payoff of a second-half bet if the match finishes `home_goals`-`away_goals` from
+    /// `half_time_score` at half time
+    pub fn get_second_half_payoff(
+        &self,
+        half_time_score: Score,
+        current_score: Option<Score>,
+        home_goals: usize,
+        away_goals: usize,
+    ) -> Result<Rational> {
+        let second_half = Score::new(home_goals, away_goals)
+            .goals_since(half_time_score)
+            .ok_or_else(|| {
+                anyhow!(
+                    "The match can't finish {}-{} after {} at half time.",
+                    home_goals,
+                    away_goals,
+                    half_time_score
+                )
+            })?;
+        self.get_payoff(
+            current_score,
+            second_half.home_goals,
+            second_half.away_goals,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_half_grid_settles_on_goals_after_half_time() {
+        let market = Market::AsianHandicap(HomeAwaySide::Home, Line::from_quarters(-2));
+        let half_time = Score::new(1, 0);
+        let grid = market
+            .get_second_half_payoff_grid(&GridBounds::square(5), half_time, None)
+            .unwrap();
+        // The home side needs to win the second half
+        assert_eq!(grid.get_payoff(0, 0), None);
+        assert_eq!(grid.get_payoff(1, 0), Some(Rational::from_integer(-1)));
+        assert_eq!(grid.get_payoff(2, 0), Some(Rational::one()));
+        assert_eq!(grid.get_payoff(3, 2), Some(Rational::from_integer(-1)));
+        assert!(market
+            .get_second_half_payoff(half_time, None, 0, 3)
+            .is_err());
+
+        let json = serde_json::to_value(grid.to_json()).unwrap();
+        assert_eq!(json["period"], "Second half");
+        assert_eq!(json["half_time_score"], "1-0");
+
+        // Placed in play at 0-1 in the second half: the match is at 1-1, and only goals from
+        // then on count
+        let grid = market
+            .get_second_half_payoff_grid(&GridBounds::square(5), half_time, Some(Score::new(0, 1)))
+            .unwrap();
+        assert_eq!(grid.get_payoff(2, 0), None);
+        assert_eq!(grid.get_payoff(2, 1), Some(Rational::one()));
+        assert_eq!(grid.get_payoff(2, 2), Some(Rational::from_integer(-1)));
+    }
+}
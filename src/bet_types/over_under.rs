@@ -1,67 +1,70 @@
-use crate::grid::Grid;
-use crate::market_specifiers::OverUnderSide;
+use crate::grid::{Grid, GridBounds};
+use crate::market_specifiers::{Line, OverUnderSide};
+use crate::rational::Rational;
+use crate::score::Score;
 use anyhow::Result;
 
 pub struct OverUnder {}
 impl OverUnder {
-    pub fn get_payoff_grid(side: OverUnderSide, goals: f32, grid_size: usize) -> Result<Grid> {
-        let mut payoff_grid = Grid::new(grid_size);
-
-        match side {
-            OverUnderSide::Over => {
-                OverUnder::set_payoff(&mut payoff_grid, grid_size, goals, 1.0, -1.0)?
-            }
-            OverUnderSide::Under => {
-                OverUnder::set_payoff(&mut payoff_grid, grid_size, goals, -1.0, 1.0)?
-            }
-        };
+    pub fn get_payoff_grid(
+        side: OverUnderSide,
+        goals: Line,
+        bounds: &GridBounds,
+        current_score: Option<Score>,
+    ) -> Result<Grid> {
+        let mut payoff_grid = Grid::new(bounds);
+        payoff_grid.set_title(format!("Over/Under: {} {}", side, goals.describe()));
+
+        // These settle on the final score whenever the bet was placed, so in play only the
+        // scores that can't happen any more change
+        if let Some(current_score) = current_score {
+            payoff_grid.set_current_score(current_score);
+        }
+        payoff_grid.fill_payoffs(|home_goals, away_goals| {
+            OverUnder::get_payoff(&side, goals, home_goals, away_goals)
+        })?;
         Ok(payoff_grid)
     }
 
-    fn set_payoff(
-        payoff_grid: &mut Grid,
-        grid_size: usize,
-        goals: f32,
-        over_payoff: f64,
-        under_payoff: f64,
-    ) -> Result<()> {
-        for home_goals in 0..grid_size {
-            for away_goals in 0..grid_size {
-                let total_goals = (home_goals + away_goals) as f32;
-
-                if goals == goals.floor() || goals.abs() % 0.5 == 0.0 {
-                    // round or .5
-                    payoff_grid.set_payoff(
-                        home_goals,
-                        away_goals,
-                        OverUnder::get_payoff(goals, total_goals, over_payoff, under_payoff),
-                    )?;
-                } else {
-                    // quarter
-                    let goals_lower = goals - 0.25;
-                    let goals_upper = goals + 0.25;
-
-                    let lower_payoff =
-                        OverUnder::get_payoff(goals_lower, total_goals, over_payoff, under_payoff);
-                    let upper_payoff =
-                        OverUnder::get_payoff(goals_upper, total_goals, over_payoff, under_payoff);
-
-                    let payoff = lower_payoff * 0.5 + upper_payoff * 0.5;
-                    payoff_grid.set_payoff(home_goals, away_goals, payoff)?;
-                }
+    /// Payoff (per unit staked at even money) of a bet on `side` of the given goals line
+    pub fn get_payoff(
+        side: &OverUnderSide,
+        goals: Line,
+        home_goals: usize,
+        away_goals: usize,
+    ) -> Rational {
+        let (over_payoff, under_payoff) = match side {
+            OverUnderSide::Over => (1, -1),
+            OverUnderSide::Under => (-1, 1),
+        };
+        // Saturating keeps any number of goals above every line
+        let total_quarters = i64::try_from(home_goals.saturating_add(away_goals))
+            .unwrap_or(i64::MAX)
+            .saturating_mul(4);
+        let line_payoff =
+            |line| OverUnder::get_line_payoff(line, total_quarters, over_payoff, under_payoff);
+
+        match goals.halves() {
+            // round or .5
+            None => Rational::from_integer(line_payoff(goals)),
+            // quarter: half the stake goes on each neighbouring line, so the payoff is the sum of
+            // the two in halves (two quarters each)
+            Some((first_half, second_half)) => {
+                Rational::from_quarters(2 * (line_payoff(first_half) + line_payoff(second_half)))
             }
         }
-
-        Ok(())
     }
 
-    fn get_payoff(target_goals: f32, total_goals: f32, over_payoff: f64, under_payoff: f64) -> f64 {
-        if total_goals > target_goals {
-            return over_payoff;
-        } else if total_goals < target_goals {
-            return under_payoff;
-        } else {
-            return 0.0;
+    fn get_line_payoff(
+        target: Line,
+        total_quarters: i64,
+        over_payoff: i64,
+        under_payoff: i64,
+    ) -> i64 {
+        match total_quarters.cmp(&(target.quarters() as i64)) {
+            std::cmp::Ordering::Greater => over_payoff,
+            std::cmp::Ordering::Less => under_payoff,
+            std::cmp::Ordering::Equal => 0,
         }
     }
 }
@@ -74,7 +77,13 @@ mod tests {
     where
         F: Fn(f64) -> f64,
     {
-        let ou = OverUnder::get_payoff_grid(side.clone(), goals, 10).unwrap();
+        let ou = OverUnder::get_payoff_grid(
+            side.clone(),
+            goals.to_string().parse().unwrap(),
+            &GridBounds::square(10),
+            None,
+        )
+        .unwrap();
         let payoff_grid = ou.print_json().unwrap().payoff_grid;
 
         for home_goals in 0..10 {
@@ -166,4 +175,17 @@ mod tests {
             _ => -1.0,
         });
     }
+
+    #[test]
+    fn test_payoff_with_extreme_goals() {
+        let line = |input: &str| input.parse::<Line>().unwrap();
+        assert_eq!(
+            OverUnder::get_payoff(&OverUnderSide::Over, line("1000"), usize::MAX, usize::MAX),
+            Rational::one()
+        );
+        assert_eq!(
+            OverUnder::get_payoff(&OverUnderSide::Under, line("999.25"), usize::MAX, 0),
+            Rational::from_integer(-1)
+        );
+    }
 }
@@ -0,0 +1,204 @@
+use crate::config::PENALTY_SHOOTOUT_HOME_WIN_PROBABILITY;
+use crate::grid::{Grid, GridBounds};
+use crate::market_specifiers::HomeAwaySide;
+use crate::rational::Rational;
+use crate::score::Score;
+use anyhow::Result;
+use std::cmp::Ordering;
+
+/// What happened after a draw in regulation: the goals scored in extra time, and who won
+/// the penalty shootout if it was still level
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtraTime {
+    pub score: Score,
+    pub penalty_winner: Option<HomeAwaySide>,
+}
+
+impl ExtraTime {
+    /// The team that went through, if known
+    pub fn winner(&self) -> Option<HomeAwaySide> {
+        match self.score.home_goals.cmp(&self.score.away_goals) {
+            Ordering::Greater => Some(HomeAwaySide::Home),
+            Ordering::Less => Some(HomeAwaySide::Away),
+            Ordering::Equal => self.penalty_winner.clone(),
+        }
+    }
+}
+
+/// Knockout market settled on who goes through, after extra time and penalties if needed,
+/// unlike win-draw-win which only counts the 90 minutes. A final is settled the same way, so
+/// to lift the trophy is this market too.
+pub struct ToQualify {}
+impl ToQualify {
+    /// Grid over regulation (90 minute) scores. Drawn scores go to extra time: they are settled
+    /// on `extra_time` if it's known, otherwise they are labelled "ET", together with the chance
+    /// of `side` going through if the model gave one. Tail cells holding such a draw are
+    /// ambiguous.
+    pub fn get_payoff_grid(
+        side: HomeAwaySide,
+        bounds: &GridBounds,
+        extra_time: Option<&ExtraTime>,
+        extra_time_probability: Option<f64>,
+    ) -> Result<Grid> {
+        let mut payoff_grid = Grid::new(bounds);
+        payoff_grid.set_title(format!("To qualify: {}", side));
+        payoff_grid.fill_possible_payoffs(|home_goals, away_goals| {
+            ToQualify::get_payoff(&side, home_goals, away_goals, extra_time)
+        })?;
+
+        let goes_to_extra_time =
+            |goals| ToQualify::get_payoff(&side, goals, goals, extra_time).is_none();
+        for home_goals in bounds.home_goals() {
+            for away_goals in bounds.away_goals() {
+                let is_tail = bounds.is_home_tail(home_goals) || bounds.is_away_tail(away_goals);
+                let holds_extra_time = bounds.home_goals_in_row(home_goals).any(|goals| {
+                    bounds.away_goals_in_col(away_goals).contains(&goals)
+                        && goes_to_extra_time(goals)
+                });
+                if is_tail && holds_extra_time {
+                    payoff_grid.set_ambiguous(home_goals, away_goals)?;
+                }
+            }
+        }
+
+        for goals in bounds.home_goals() {
+            let is_tail = bounds.is_home_tail(goals) || bounds.is_away_tail(goals);
+            if !bounds.away_goals().contains(&goals)
+                || is_tail
+                || payoff_grid.get_payoff(goals, goals).is_some()
+            {
+                continue;
+            }
+            let label = match extra_time_probability {
+                Some(probability) => format!("ET {:.0}%", probability * 100.0),
+                None => "ET".to_owned(),
+            };
+            payoff_grid.set_label(goals, goals, label)?;
+        }
+        Ok(payoff_grid)
+    }
+
+    /// Payoff (per unit staked at even money) of a bet on `side` going through. `None` for a
+    /// draw in regulation when the extra time (or shootout) result isn't known.
+    pub fn get_payoff(
+        side: &HomeAwaySide,
+        home_goals: usize,
+        away_goals: usize,
+        extra_time: Option<&ExtraTime>,
+    ) -> Option<Rational> {
+        let winner = match home_goals.cmp(&away_goals) {
+            Ordering::Greater => HomeAwaySide::Home,
+            Ordering::Less => HomeAwaySide::Away,
+            Ordering::Equal => extra_time?.winner()?,
+        };
+        match winner == *side {
+            true => Some(Rational::one()),
+            false => Some(-Rational::one()),
+        }
+    }
+
+    /// Chance of `side` going through after a draw in regulation, from the extra time result
+    /// probabilities (home win, draw, away win)
+    pub fn get_extra_time_probability(
+        side: &HomeAwaySide,
+        extra_time_results: (f64, f64, f64),
+    ) -> f64 {
+        let (home_win, draw, away_win) = extra_time_results;
+        match side {
+            HomeAwaySide::Home => home_win + draw * PENALTY_SHOOTOUT_HOME_WIN_PROBABILITY,
+            HomeAwaySide::Away => away_win + draw * (1.0 - PENALTY_SHOOTOUT_HOME_WIN_PROBABILITY),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draws_go_to_extra_time() {
+        let grid =
+            ToQualify::get_payoff_grid(HomeAwaySide::Home, &GridBounds::square(4), None, None)
+                .unwrap();
+        let json_grid = grid.to_json();
+
+        assert_eq!(json_grid.payoff_grid[&2][&1], 1.0);
+        assert_eq!(json_grid.payoff_grid[&0][&1], -1.0);
+        assert!(!json_grid.payoff_grid[&1].contains_key(&1));
+        assert_eq!(json_grid.labelled_cells.len(), 4);
+        assert_eq!(json_grid.labelled_cells[0].label, "ET");
+    }
+
+    #[test]
+    fn test_tail_draws_are_ambiguous() {
+        let bounds = GridBounds {
+            min_home_goals: 0,
+            max_home_goals: 4,
+            min_away_goals: 0,
+            max_away_goals: 5,
+            tail: true,
+        };
+        let grid = ToQualify::get_payoff_grid(HomeAwaySide::Home, &bounds, None, None).unwrap();
+        let json_grid = grid.to_json();
+        let is_ambiguous = |home_goals, away_goals| {
+            json_grid
+                .ambiguous_cells
+                .iter()
+                .any(|cell| cell.home_goals == home_goals && cell.away_goals == away_goals)
+        };
+        // 4+ against 4 holds the 4-4 draw, though every other score in it is a home win
+        assert_eq!(json_grid.payoff_grid[&4][&4], 1.0);
+        assert!(is_ambiguous(4, 4));
+        assert!(is_ambiguous(4, 5));
+        assert!(!is_ambiguous(4, 3));
+
+        // Once extra time is known the draws settle
+        let extra_time = ExtraTime {
+            score: Score::new(1, 0),
+            penalty_winner: None,
+        };
+        let grid = ToQualify::get_payoff_grid(HomeAwaySide::Home, &bounds, Some(&extra_time), None)
+            .unwrap();
+        assert!(grid
+            .to_json()
+            .ambiguous_cells
+            .iter()
+            .all(|cell| cell.away_goals == 5));
+    }
+
+    #[test]
+    fn test_settles_on_extra_time_and_penalties() {
+        let away = HomeAwaySide::Away;
+        let extra_time = ExtraTime {
+            score: Score::new(0, 1),
+            penalty_winner: None,
+        };
+        assert_eq!(
+            ToQualify::get_payoff(&away, 1, 1, Some(&extra_time)),
+            Some(Rational::one())
+        );
+
+        let shootout = ExtraTime {
+            score: Score::new(1, 1),
+            penalty_winner: Some(HomeAwaySide::Home),
+        };
+        assert_eq!(
+            ToQualify::get_payoff(&away, 2, 2, Some(&shootout)),
+            Some(-Rational::one())
+        );
+        // Decided in regulation: extra time never happened
+        assert_eq!(
+            ToQualify::get_payoff(&away, 3, 1, Some(&shootout)),
+            Some(-Rational::one())
+        );
+
+        let no_shootout_result = ExtraTime {
+            score: Score::new(0, 0),
+            penalty_winner: None,
+        };
+        assert_eq!(
+            ToQualify::get_payoff(&away, 0, 0, Some(&no_shootout_result)),
+            None
+        );
+    }
+}
@@ -1,47 +1,37 @@
-use crate::grid::Grid;
+use crate::grid::{Grid, GridBounds};
 use crate::market_specifiers::HomeAwayDrawSide;
+use crate::rational::Rational;
+use crate::score::Score;
 use anyhow::Result;
 
 pub struct WinDrawWin {}
 impl WinDrawWin {
-    pub fn get_payoff_grid(side: HomeAwayDrawSide, grid_size: usize) -> Result<Grid> {
-        let mut payoff_grid = Grid::new(grid_size);
+    pub fn get_payoff_grid(
+        side: HomeAwayDrawSide,
+        bounds: &GridBounds,
+        current_score: Option<Score>,
+    ) -> Result<Grid> {
+        let mut payoff_grid = Grid::new(bounds);
+        payoff_grid.set_title(format!("Win-Draw-Win: {}", side));
 
-        match side {
-            HomeAwayDrawSide::Home => {
-                WinDrawWin::set_payoff(&mut payoff_grid, grid_size, 1.0, -1.0, -1.0)?
-            }
-            HomeAwayDrawSide::Away => {
-                WinDrawWin::set_payoff(&mut payoff_grid, grid_size, -1.0, 1.0, -1.0)?
-            }
-            HomeAwayDrawSide::Draw => {
-                WinDrawWin::set_payoff(&mut payoff_grid, grid_size, -1.0, -1.0, 1.0)?
-            }
-        };
+        // These settle on the final score whenever the bet was placed, so in play only the
+        // scores that can't happen any more change
+        if let Some(current_score) = current_score {
+            payoff_grid.set_current_score(current_score);
+        }
+        payoff_grid.fill_payoffs(|home_goals, away_goals| {
+            WinDrawWin::get_payoff(&side, home_goals, away_goals)
+        })?;
         Ok(payoff_grid)
     }
 
-    fn set_payoff(
-        payoff_grid: &mut Grid,
-        grid_size: usize,
-        home_payoff: f64,
-        away_payoff: f64,
-        draw_payoff: f64,
-    ) -> Result<()> {
-        for home_goals in 0..grid_size {
-            for away_goals in 0..grid_size {
-                if home_goals > away_goals {
-                    payoff_grid.set_payoff(home_goals, away_goals, home_payoff)?;
-                } else if away_goals > home_goals {
-                    payoff_grid.set_payoff(home_goals, away_goals, away_payoff)?;
-                } else {
-                    // draw
-                    payoff_grid.set_payoff(home_goals, away_goals, draw_payoff)?;
-                }
-            }
+    /// Payoff (per unit staked at even money) of a bet on `side`
+    pub fn get_payoff(side: &HomeAwayDrawSide, home_goals: usize, away_goals: usize) -> Rational {
+        if *side == Score::new(home_goals, away_goals).result() {
+            Rational::one()
+        } else {
+            -Rational::one()
         }
-
-        Ok(())
     }
 }
 
@@ -53,7 +43,7 @@ mod tests {
     where
         F: Fn(f64) -> f64,
     {
-        let wdw = WinDrawWin::get_payoff_grid(side.clone(), 10).unwrap();
+        let wdw = WinDrawWin::get_payoff_grid(side.clone(), &GridBounds::square(10), None).unwrap();
         let payoff_grid = wdw.print_json().unwrap().payoff_grid;
 
         for home_goals in 0..10 {
@@ -99,4 +89,45 @@ mod tests {
             _ => -1.0,
         });
     }
+
+    #[test]
+    fn test_rectangular_payoff_grid() {
+        let bounds = GridBounds {
+            min_home_goals: 2,
+            max_home_goals: 6,
+            min_away_goals: 0,
+            max_away_goals: 3,
+            tail: false,
+        };
+        let wdw = WinDrawWin::get_payoff_grid(HomeAwayDrawSide::Draw, &bounds, None).unwrap();
+        let payoff_grid = wdw.print_json().unwrap().payoff_grid;
+
+        let mut home_goals: Vec<&usize> = payoff_grid.keys().collect();
+        home_goals.sort();
+        assert_eq!(home_goals, vec![&2, &3, &4, &5, &6]);
+        for row in payoff_grid.values() {
+            let mut away_goals: Vec<&usize> = row.keys().collect();
+            away_goals.sort();
+            assert_eq!(away_goals, vec![&0, &1, &2, &3]);
+        }
+        assert_eq!(payoff_grid[&3][&3], 1.0);
+        assert_eq!(payoff_grid[&2][&3], -1.0);
+    }
+
+    #[test]
+    fn test_tail_bucket_payoff_grid() {
+        let mut bounds = GridBounds::square(4);
+        bounds.tail = true;
+        let wdw = WinDrawWin::get_payoff_grid(HomeAwayDrawSide::Home, &bounds, None).unwrap();
+        let json_grid = wdw.print_json().unwrap();
+
+        // 3+ home goals against 0-2 away goals is always a home win
+        assert_eq!(json_grid.payoff_grid[&3][&2], 1.0);
+        // 3+ away goals against 0-2 home goals is always an away win
+        assert_eq!(json_grid.payoff_grid[&2][&3], -1.0);
+        // 3+ against 3+ could be anything, so only that cell is flagged
+        assert_eq!(json_grid.ambiguous_cells.len(), 1);
+        assert_eq!(json_grid.ambiguous_cells[0].home_goals, 3);
+        assert_eq!(json_grid.ambiguous_cells[0].away_goals, 3);
+    }
 }
@@ -0,0 +1,536 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::bet_types::Market;
+use crate::market_specifiers::{HomeAwayDrawSide, HomeAwaySide, Line, OverUnderSide};
+use crate::probability::PoissonModel;
+use crate::results::{
+    print_skipped_rows, HistoricalMatch, HistoricalMatches, JsonSkippedRow, SkippedRow,
+};
+use std::str::FromStr;
+
+// Calibration tables group predictions into this many buckets of equal width
+const BUCKETS: usize = 10;
+// Probabilities are floored at this for the log loss, so a result the model ruled out costs a
+// lot rather than infinitely much
+const MIN_LOG_LOSS_PROBABILITY: f64 = 1e-15;
+
+#[derive(Serialize)]
+pub struct JsonResultScores {
+    pub matches: usize,
+    pub log_loss: Option<f64>,
+    pub brier_score: Option<f64>,
+    pub ranked_probability_score: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct JsonCalibrationBucket {
+    pub from: f64,
+    pub to: f64,
+    pub bets: usize,
+    pub predicted: f64,
+    pub actual: f64,
+}
+
+#[derive(Serialize)]
+pub struct JsonCalibrationTable {
+    pub name: String,
+    pub bets: usize,
+    pub predicted: Option<f64>,
+    pub actual: Option<f64>,
+    pub buckets: Vec<JsonCalibrationBucket>,
+}
+
+#[derive(Serialize)]
+pub struct JsonCalibration {
+    pub matches: usize,
+    pub matches_without_model: usize,
+    pub skipped_rows: Vec<JsonSkippedRow>,
+    pub rho: f64,
+    pub model: JsonResultScores,
+    pub base_rates: JsonResultScores,
+    pub tables: Vec<JsonCalibrationTable>,
+}
+
+/// Scores of 1X2 probability forecasts against the results, lower is better: the log loss
+/// (minus the log of the probability of the result), the Brier score (squared errors summed
+/// over home, draw and away) and the ranked probability score (squared errors of the
+/// cumulative probabilities, which counts predicting a draw as closer to a home win than an
+/// away win is)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultScores {
+    pub matches: usize,
+    log_loss: f64,
+    brier_score: f64,
+    ranked_probability_score: f64,
+}
+
+impl ResultScores {
+    fn new() -> Self {
+        Self {
+            matches: 0,
+            log_loss: 0.0,
+            brier_score: 0.0,
+            ranked_probability_score: 0.0,
+        }
+    }
+
+    /// Adds the forecast of home, draw and away `probabilities` for a match with `result`
+    pub fn add(&mut self, probabilities: [f64; 3], result: &HomeAwayDrawSide) {
+        let outcome = match result {
+            HomeAwayDrawSide::Home => [1.0, 0.0, 0.0],
+            HomeAwayDrawSide::Draw => [0.0, 1.0, 0.0],
+            HomeAwayDrawSide::Away => [0.0, 0.0, 1.0],
+        };
+        let result_probability = probabilities
+            .iter()
+            .zip(outcome)
+            .map(|(probability, outcome)| probability * outcome)
+            .sum::<f64>();
+        self.matches += 1;
+        self.log_loss -= result_probability.max(MIN_LOG_LOSS_PROBABILITY).ln();
+        self.brier_score += probabilities
+            .iter()
+            .zip(outcome)
+            .map(|(probability, outcome)| (probability - outcome).powi(2))
+            .sum::<f64>();
+        // Home then draw then away; the last cumulative probabilities are both 1
+        let (mut cumulative_probability, mut cumulative_outcome) = (0.0, 0.0);
+        let mut ranked_probability_score = 0.0;
+        for (probability, outcome) in probabilities.iter().zip(outcome).take(2) {
+            cumulative_probability += probability;
+            cumulative_outcome += outcome;
+            ranked_probability_score += (cumulative_probability - cumulative_outcome).powi(2);
+        }
+        self.ranked_probability_score += ranked_probability_score / 2.0;
+    }
+
+    fn average(&self, total: f64) -> Option<f64> {
+        (self.matches > 0).then(|| total / self.matches as f64)
+    }
+
+    pub fn get_log_loss(&self) -> Option<f64> {
+        self.average(self.log_loss)
+    }
+
+    pub fn get_brier_score(&self) -> Option<f64> {
+        self.average(self.brier_score)
+    }
+
+    pub fn get_ranked_probability_score(&self) -> Option<f64> {
+        self.average(self.ranked_probability_score)
+    }
+
+    fn to_json(&self) -> JsonResultScores {
+        JsonResultScores {
+            matches: self.matches,
+            log_loss: self.get_log_loss(),
+            brier_score: self.get_brier_score(),
+            ranked_probability_score: self.get_ranked_probability_score(),
+        }
+    }
+}
+
+/// How a market's predictions compare with what happened, with the predictions grouped into
+/// buckets (0-10%, 10-20%, ...). A prediction is the share of the stake the model expects the
+/// bet to win at even money: the win probability, with half-wins counting as 0.75, pushes as
+/// 0.5 and half-losses as 0.25. A calibrated model's buckets win what they predict.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationTable {
+    pub name: String,
+    // `None` for the home side of the Asian handicap line of each match (AHh)
+    pub market: Option<Market>,
+    // Bets, and the sums of the predicted and actual shares won, in each bucket
+    buckets: Vec<(usize, f64, f64)>,
+}
+
+impl CalibrationTable {
+    fn new(name: String, market: Option<Market>) -> Self {
+        Self {
+            name,
+            market,
+            buckets: vec![(0, 0.0, 0.0); BUCKETS],
+        }
+    }
+
+    fn add(
+        &mut self,
+        market: &Market,
+        model: &PoissonModel,
+        historical_match: &HistoricalMatch,
+    ) -> Result<()> {
+        let share_won = |payoff: f64| (payoff + 1.0) / 2.0;
+        let predicted = share_won(model.get_expected_payoff(None, |home_goals, away_goals| {
+            market.get_payoff(None, home_goals, away_goals)
+        })?);
+        let full_time = historical_match.full_time;
+        let actual = share_won(
+            market
+                .get_payoff(None, full_time.home_goals, full_time.away_goals)?
+                .to_f64(),
+        );
+        let bucket = ((predicted * BUCKETS as f64) as usize).min(BUCKETS - 1);
+        let (bets, predicted_total, actual_total) = &mut self.buckets[bucket];
+        *bets += 1;
+        *predicted_total += predicted;
+        *actual_total += actual;
+        Ok(())
+    }
+
+    /// Bets, average predicted share won and average actual share won over all the buckets
+    pub fn get_totals(&self) -> (usize, Option<f64>, Option<f64>) {
+        let (bets, predicted, actual) = self.buckets.iter().fold(
+            (0, 0.0, 0.0),
+            |(bets, predicted, actual), (bucket_bets, bucket_predicted, bucket_actual)| {
+                (
+                    bets + bucket_bets,
+                    predicted + bucket_predicted,
+                    actual + bucket_actual,
+                )
+            },
+        );
+        match bets {
+            0 => (0, None, None),
+            bets => (
+                bets,
+                Some(predicted / bets as f64),
+                Some(actual / bets as f64),
+            ),
+        }
+    }
+
+    // Non-empty buckets: their bounds, bets, and average predicted and actual shares won
+    fn get_buckets(&self) -> Vec<(f64, f64, usize, f64, f64)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, (bets, _, _))| *bets > 0)
+            .map(|(i, (bets, predicted, actual))| {
+                (
+                    i as f64 / BUCKETS as f64,
+                    (i + 1) as f64 / BUCKETS as f64,
+                    *bets,
+                    predicted / *bets as f64,
+                    actual / *bets as f64,
+                )
+            })
+            .collect()
+    }
+
+    fn print_text(&self) {
+        let as_percentage = |fraction: Option<f64>| match fraction {
+            Some(fraction) => format!("{:.2}%", fraction * 100.0),
+            None => "-".to_owned(),
+        };
+        println!();
+        println!("{}", self.name);
+        println!(
+            "{:<10} {:>6} {:>10} {:>10}",
+            "Predicted", "Bets", "Predicted", "Actual"
+        );
+        for (from, to, bets, predicted, actual) in self.get_buckets() {
+            println!(
+                "{:<10} {:>6} {:>10} {:>10}",
+                format!("{:.0}-{:.0}%", from * 100.0, to * 100.0),
+                bets,
+                as_percentage(Some(predicted)),
+                as_percentage(Some(actual)),
+            );
+        }
+        let (bets, predicted, actual) = self.get_totals();
+        println!(
+            "{:<10} {:>6} {:>10} {:>10}",
+            "All",
+            bets,
+            as_percentage(predicted),
+            as_percentage(actual),
+        );
+    }
+
+    fn to_json(&self) -> JsonCalibrationTable {
+        let (bets, predicted, actual) = self.get_totals();
+        JsonCalibrationTable {
+            name: self.name.clone(),
+            bets,
+            predicted,
+            actual,
+            buckets: self
+                .get_buckets()
+                .into_iter()
+                .map(
+                    |(from, to, bets, predicted, actual)| JsonCalibrationBucket {
+                        from,
+                        to,
+                        bets,
+                        predicted,
+                        actual,
+                    },
+                )
+                .collect(),
+        }
+    }
+}
+
+/// How well a model's probabilities predict historical results: scores of its 1X2
+/// probabilities, next to those of the base rates (the shares of home wins, draws and away
+/// wins over the matches), and calibration tables for Asian handicap and over/under lines
+pub struct Calibration {
+    pub matches: usize,
+    pub matches_without_model: usize,
+    pub skipped: Vec<SkippedRow>,
+    pub rho: f64,
+    pub model: ResultScores,
+    pub base_rates: ResultScores,
+    pub tables: Vec<CalibrationTable>,
+}
+
+impl Calibration {
+    /// `get_model` gives the model for a match, or `None` if there isn't one. Asian handicaps
+    /// are on the home side of each of `handicaps`, or of the line of each match (AHh) without
+    /// them, and over/unders on the over side of each of `goals`. Rows whose model or line
+    /// can't be read are skipped.
+    pub fn new<F>(
+        historical_matches: &HistoricalMatches,
+        get_model: F,
+        rho: f64,
+        handicaps: Option<Vec<Line>>,
+        goals: Vec<Line>,
+    ) -> Result<Self>
+    where
+        F: Fn(&HistoricalMatch) -> Result<Option<PoissonModel>>,
+    {
+        let mut calibration = Self {
+            matches: 0,
+            matches_without_model: 0,
+            skipped: historical_matches.skipped.clone(),
+            rho,
+            model: ResultScores::new(),
+            base_rates: ResultScores::new(),
+            tables: vec![],
+        };
+
+        // Each match with a model, and its own line if the handicaps are the lines of the
+        // matches
+        let mut models = vec![];
+        for historical_match in &historical_matches.matches {
+            let in_row = |e: anyhow::Error| anyhow!("{} (line {})", e, historical_match.line);
+            let handicap = match (&handicaps, historical_match.get("AHh")) {
+                (None, Some(handicap)) => Line::from_str(handicap).map(Some).map_err(in_row),
+                _ => Ok(None),
+            };
+            match (get_model(historical_match), handicap) {
+                (Err(e), _) | (_, Err(e)) => calibration.skipped.push(SkippedRow {
+                    line: historical_match.line,
+                    reason: e.to_string(),
+                }),
+                (Ok(None), _) => {
+                    calibration.matches += 1;
+                    calibration.matches_without_model += 1;
+                }
+                (Ok(Some(model)), Ok(handicap)) => {
+                    calibration.matches += 1;
+                    models.push((historical_match, model, handicap));
+                }
+            }
+        }
+        calibration.skipped.sort_by_key(|skipped| skipped.line);
+
+        // The base rates over the matches that have a model
+        let mut result_counts = [0.0; 3];
+        for (historical_match, _, _) in &models {
+            let i = match historical_match.full_time.result() {
+                HomeAwayDrawSide::Home => 0,
+                HomeAwayDrawSide::Draw => 1,
+                HomeAwayDrawSide::Away => 2,
+            };
+            result_counts[i] += 1.0;
+        }
+        let base_rates = result_counts.map(|count| count / models.len().max(1) as f64);
+
+        match handicaps {
+            None => calibration.tables.push(CalibrationTable::new(
+                "Asian Handicap: Home on the line of each match (AHh)".to_owned(),
+                None,
+            )),
+            Some(handicaps) => {
+                for handicap in handicaps {
+                    let market = Market::AsianHandicap(HomeAwaySide::Home, handicap);
+                    calibration
+                        .tables
+                        .push(CalibrationTable::new(market.to_string(), Some(market)));
+                }
+            }
+        }
+        for goals in goals {
+            let market = Market::OverUnder(OverUnderSide::Over, goals);
+            calibration
+                .tables
+                .push(CalibrationTable::new(market.to_string(), Some(market)));
+        }
+
+        for (historical_match, model, handicap) in &models {
+            let result = historical_match.full_time.result();
+            let (home, draw, away) = model.get_result_probabilities(90.0);
+            calibration.model.add([home, draw, away], &result);
+            calibration.base_rates.add(base_rates, &result);
+
+            for table in calibration.tables.iter_mut() {
+                let market = match (&table.market, handicap) {
+                    (Some(market), _) => market.clone(),
+                    (None, Some(handicap)) => Market::AsianHandicap(HomeAwaySide::Home, *handicap),
+                    (None, None) => continue,
+                };
+                table.add(&market, model, historical_match)?;
+            }
+        }
+        Ok(calibration)
+    }
+
+    pub fn print_text(&self) {
+        let model = match self.rho == 0.0 {
+            true => "Poisson model".to_owned(),
+            false => format!("Dixon-Coles model (rho {})", self.rho),
+        };
+        println!(
+            "{} matches, {} without a model. {}.",
+            self.matches, self.matches_without_model, model
+        );
+        print_skipped_rows(&self.skipped);
+        let score = |score: Option<f64>| match score {
+            Some(score) => format!("{:.4}", score),
+            None => "-".to_owned(),
+        };
+        println!();
+        println!(
+            "{:<12} {:>9} {:>9} {:>9}",
+            "1X2", "Log loss", "Brier", "RPS"
+        );
+        for (name, scores) in [("Model", &self.model), ("Base rates", &self.base_rates)] {
+            println!(
+                "{:<12} {:>9} {:>9} {:>9}",
+                name,
+                score(scores.get_log_loss()),
+                score(scores.get_brier_score()),
+                score(scores.get_ranked_probability_score()),
+            );
+        }
+        for table in &self.tables {
+            table.print_text();
+        }
+    }
+
+    pub fn to_json(&self) -> JsonCalibration {
+        JsonCalibration {
+            matches: self.matches,
+            matches_without_model: self.matches_without_model,
+            skipped_rows: self.skipped.iter().map(SkippedRow::to_json).collect(),
+            rho: self.rho,
+            model: self.model.to_json(),
+            base_rates: self.base_rates.to_json(),
+            tables: self.tables.iter().map(CalibrationTable::to_json).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{parse_historical_matches, CsvTable};
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "Expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_result_scores() {
+        let mut scores = ResultScores::new();
+        scores.add([0.5, 0.3, 0.2], &HomeAwayDrawSide::Draw);
+        assert_close(scores.get_log_loss().unwrap(), -(0.3f64.ln()));
+        assert_close(scores.get_brier_score().unwrap(), 0.25 + 0.49 + 0.04);
+        // Cumulative 0.5 and 0.8 against 0 and 1
+        assert_close(
+            scores.get_ranked_probability_score().unwrap(),
+            (0.25 + 0.04) / 2.0,
+        );
+    }
+
+    #[test]
+    fn test_calibration_tables() {
+        let table = CsvTable::parse(
+            "HomeTeam,AwayTeam,FTHG,FTAG,HXG,AXG,AHh\n\
+             A,B,2,0,1.5,1.5,-0.25\n\
+             C,D,1,1,1.5,1.5,-0.25\n\
+             E,F,0,0,,,0\n",
+        )
+        .unwrap();
+        let calibration = Calibration::new(
+            &parse_historical_matches(&table).unwrap(),
+            |historical_match| historical_match.get_model(0.0),
+            0.0,
+            None,
+            vec![Line::from_quarters(6)],
+        )
+        .unwrap();
+        assert_eq!(calibration.matches_without_model, 1);
+        assert_eq!(calibration.model.matches, 2);
+
+        // Home -0.25 wins outright at 2-0 and half-loses at 1-1
+        let (bets, predicted, actual) = calibration.tables[0].get_totals();
+        assert_eq!(bets, 2);
+        assert_close(actual.unwrap(), (1.0 + 0.25) / 2.0);
+        assert!(predicted.unwrap() < 0.5);
+        // Both had two goals, so over 1.5 won twice
+        let (bets, _, actual) = calibration.tables[1].get_totals();
+        assert_eq!(bets, 2);
+        assert_close(actual.unwrap(), 1.0);
+        // Base rates of one home win and one draw score the home win and the draw the same
+        assert_close(
+            calibration.base_rates.get_log_loss().unwrap(),
+            -(0.5f64.ln()),
+        );
+    }
+
+    #[test]
+    fn test_skips_unreadable_rows() {
+        let table = CsvTable::parse(
+            "HomeTeam,AwayTeam,FTHG,FTAG,HXG,AXG,AHh\n\
+             A,B,2,0,1.5,1.5,-0.25\n\
+             C,D,1,1,1.5,1.5,-0.3\n\
+             E,F,0,0,lots,1.5,0\n\
+             G,H,,,1.5,1.5,0\n",
+        )
+        .unwrap();
+        let calibrate = |handicaps| {
+            Calibration::new(
+                &parse_historical_matches(&table).unwrap(),
+                |historical_match| historical_match.get_model(0.0),
+                0.0,
+                handicaps,
+                vec![],
+            )
+            .unwrap()
+        };
+        let calibration = calibrate(None);
+        assert_eq!(calibration.matches, 1);
+        assert_eq!(
+            calibration
+                .skipped
+                .iter()
+                .map(|skipped| skipped.line)
+                .collect::<Vec<_>>(),
+            vec![3, 4, 5]
+        );
+        assert!(calibration.skipped[0].reason.ends_with("(line 3)"));
+        assert_eq!(
+            calibration.skipped[1].reason,
+            "Invalid 'HXG' value 'lots' on line 4. Expected a decimal."
+        );
+        // The lines of the matches don't matter with lines of its own
+        assert_eq!(calibrate(Some(vec![Line::from_quarters(0)])).matches, 2);
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::grid::{Grid, GridBounds, JsonGrid};
+use crate::position::{get_position_profit, JsonPositionBet, PositionBet};
+use crate::rational::Rational;
+use crate::score::Score;
+
+#[derive(Serialize)]
+pub struct JsonCashOut {
+    pub bets: Vec<JsonPositionBet>,
+    pub current_score: String,
+    pub at_risk: f64,
+    pub expected_profit: f64,
+    pub fair_value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offer: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offer_margin: Option<f64>,
+    pub profit_grid: JsonGrid,
+}
+
+/// What open bets on a match are worth now. Cashing out pays an amount back and settles the
+/// bets, so the fair amount is what they are expected to return: the total at risk (stakes,
+/// and liabilities for lays) plus the expected profit.
+pub struct CashOut {
+    pub bets: Vec<PositionBet>,
+    pub current_score: Score,
+    pub expected_profit: f64,
+    pub offer: Option<Rational>,
+    // Total at risk: stakes, and liabilities for lays
+    pub at_risk: Rational,
+}
+
+impl CashOut {
+    /// Values `bets` (placed before kick-off) at `current_score`, given the probability of
+    /// each final score from here
+    pub fn new(
+        bets: Vec<PositionBet>,
+        current_score: Score,
+        score_probabilities: &[(Score, f64)],
+        offer: Option<Rational>,
+    ) -> Result<Self> {
+        let expected_profit = score_probabilities
+            .iter()
+            .map(|(score, probability)| {
+                Ok(probability
+                    * get_position_profit(&bets, score.home_goals, score.away_goals)?.to_f64())
+            })
+            .sum::<Result<f64>>()?;
+        let at_risk = bets.iter().try_fold(Rational::zero(), |at_risk, bet| {
+            at_risk.checked_add(bet.exchange_bet.liability()?)
+        })?;
+        Ok(Self {
+            bets,
+            current_score,
+            expected_profit,
+            offer,
+            at_risk,
+        })
+    }
+
+    pub fn fair_value(&self) -> f64 {
+        self.at_risk.to_f64() + self.expected_profit
+    }
+
+    /// How far the offer is below the fair value, as a fraction of the fair value. Negative
+    /// if the offer is worth more than the bets.
+    pub fn get_offer_margin(&self) -> Option<f64> {
+        let fair_value = self.fair_value();
+        match self.offer {
+            Some(offer) if fair_value > 0.0 => Some(1.0 - offer.to_f64() / fair_value),
+            _ => None,
+        }
+    }
+
+    /// Profit of the bets for each final score, with the scores that can't happen any more
+    /// greyed out
+    pub fn get_profit_grid(&self, bounds: &GridBounds) -> Result<Grid> {
+        let mut grid = Grid::new(bounds);
+        grid.set_title("Profit of the open bets".to_owned());
+        grid.set_current_score(self.current_score);
+        grid.fill_profits(|home_goals, away_goals| {
+            get_position_profit(&self.bets, home_goals, away_goals)
+        })?;
+        Ok(grid)
+    }
+
+    pub fn to_json(&self, bounds: &GridBounds) -> Result<JsonCashOut> {
+        Ok(JsonCashOut {
+            bets: self.bets.iter().map(PositionBet::to_json).collect(),
+            current_score: self.current_score.to_string(),
+            at_risk: self.at_risk.to_f64(),
+            expected_profit: self.expected_profit,
+            fair_value: self.fair_value(),
+            offer: self.offer.map(Rational::to_f64),
+            offer_margin: self.get_offer_margin(),
+            profit_grid: self.get_profit_grid(bounds)?.to_json(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probability::PoissonModel;
+
+    fn bet(json: &str) -> PositionBet {
+        PositionBet::from_json(&serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_fair_value_is_expected_return() {
+        let bets = vec![bet(
+            r#"{"bet_type": "win-draw-win", "side": "h", "odds": 3, "stake": 100}"#,
+        )];
+        // Nothing left to play at 1-0: the bet has won
+        let finished = PoissonModel::new(0.0, 0.0).unwrap();
+        let current_score = Score::new(1, 0);
+        let cash_out = CashOut::new(
+            bets.clone(),
+            current_score,
+            &finished.get_score_probabilities(Some(current_score)),
+            Some(Rational::from_integer(285)),
+        )
+        .unwrap();
+        assert!((cash_out.fair_value() - 300.0).abs() < 1e-9);
+        assert!((cash_out.get_offer_margin().unwrap() - 0.05).abs() < 1e-9);
+
+        // Level with time to go: worth the stake times the odds times the chance of a home win
+        let model = PoissonModel::new(1.2, 1.2).unwrap();
+        let current_score = Score::new(1, 1);
+        let score_probabilities = model.get_score_probabilities(Some(current_score));
+        let home_win: f64 = score_probabilities
+            .iter()
+            .filter(|(score, _)| score.home_goals > score.away_goals)
+            .map(|(_, probability)| probability)
+            .sum();
+        let cash_out = CashOut::new(bets, current_score, &score_probabilities, None).unwrap();
+        assert!((cash_out.fair_value() - 300.0 * home_win).abs() < 1e-9);
+        assert_eq!(cash_out.get_offer_margin(), None);
+    }
+}
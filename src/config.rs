@@ -1 +1,10 @@
 pub const DEFAULT_FB_GRID_SIZE: usize = 10;
+// How many goals past the grid edge are checked when settling the "N+" tail bucket.
+// Every supported market's payoff is constant this far past any realistic line.
+pub const TAIL_PROBE_GOALS: usize = 20;
+// Goals per team (still to come) that the probability model sums over
+pub const MAX_MODEL_GOALS: usize = 30;
+pub const MINUTES_PER_MATCH: f64 = 90.0;
+pub const EXTRA_TIME_MINUTES: f64 = 30.0;
+// Chance of the home team winning a penalty shootout: close enough to a coin flip
+pub const PENALTY_SHOOTOUT_HOME_WIN_PROBABILITY: f64 = 0.5;
@@ -0,0 +1,216 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::fmt;
+
+use crate::market_specifiers::BackLay;
+use crate::rational::Rational;
+
+/// How a bet was struck: backed or laid at decimal `odds` for `stake` (the backer's stake, also
+/// for lays), with the exchange taking `commission` (a fraction) of net winnings on the market
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExchangeBet {
+    pub back_lay: BackLay,
+    pub odds: Rational,
+    pub stake: Rational,
+    pub commission: Rational,
+}
+
+#[derive(Serialize)]
+pub struct JsonExchangeBet {
+    pub back_lay: String,
+    pub odds: f64,
+    pub stake: f64,
+    pub liability: f64,
+    pub commission: f64,
+}
+
+impl ExchangeBet {
+    pub fn new(
+        back_lay: BackLay,
+        odds: Rational,
+        stake: Rational,
+        commission: Rational,
+    ) -> Result<Self> {
+        if odds <= Rational::one() {
+            bail!("Invalid odds {}. Decimal odds must be above 1.", odds);
+        }
+        if !stake.is_positive() {
+            bail!("Invalid stake {}. The stake must be positive.", stake);
+        }
+        if commission.is_negative() || commission >= Rational::one() {
+            bail!(
+                "Invalid commission {}. Commission is a fraction of net winnings, from 0 up to 1 (for example 0.02 for 2%).",
+                commission
+            );
+        }
+        Ok(Self {
+            back_lay,
+            odds,
+            stake,
+            commission,
+        })
+    }
+
+    /// Backing at even money for a unit stake with no commission, which leaves payoffs as they are
+    pub fn even_money() -> Self {
+        Self {
+            back_lay: BackLay::Back,
+            odds: Rational::from_integer(2),
+            stake: Rational::one(),
+            commission: Rational::zero(),
+        }
+    }
+
+    /// Most we can lose: the stake when backing, the backer's winnings when laying
+    pub fn liability(&self) -> Result<Rational> {
+        match self.back_lay {
+            BackLay::Back => Ok(self.stake),
+            BackLay::Lay => self
+                .stake
+                .checked_mul(self.odds.checked_sub(Rational::one())?),
+        }
+    }
+
+    /// What we receive for a bet whose even money payoff per unit staked is `payoff` (e.g. 0.5
+    /// for a half-won Asian handicap), after commission
+    pub fn get_profit(&self, payoff: Rational) -> Result<Rational> {
+        self.take_commission(self.get_gross_profit(payoff)?)
+    }
+
+    /// What the bet wins or loses before commission
+    pub fn get_gross_profit(&self, payoff: Rational) -> Result<Rational> {
+        let back_profit = match payoff.is_positive() {
+            true => payoff
+                .checked_mul(self.stake)?
+                .checked_mul(self.odds.checked_sub(Rational::one())?)?,
+            false => payoff.checked_mul(self.stake)?,
+        };
+        match self.back_lay {
+            BackLay::Back => Ok(back_profit),
+            BackLay::Lay => Ok(-back_profit),
+        }
+    }
+
+    /// Net winnings on the market after this bet's commission, which is only taken from a
+    /// profit
+    pub fn take_commission(&self, net_profit: Rational) -> Result<Rational> {
+        match net_profit.is_positive() {
+            true => net_profit.checked_mul(Rational::one().checked_sub(self.commission)?),
+            false => Ok(net_profit),
+        }
+    }
+
+    /// Expected profit of a bet that wins outright with `win_probability` and loses otherwise
+    pub fn get_expected_profit(&self, win_probability: f64) -> Result<f64> {
+        Ok(win_probability * self.get_profit(Rational::one())?.to_f64()
+            + (1.0 - win_probability) * self.get_profit(-Rational::one())?.to_f64())
+    }
+
+    // The liability for display, approximated if it's too large or precise to work out exactly
+    fn approximate_liability(&self) -> f64 {
+        match self.liability() {
+            Ok(liability) => liability.to_f64(),
+            Err(_) => self.stake.to_f64() * (self.odds.to_f64() - 1.0),
+        }
+    }
+
+    pub fn to_json(&self) -> JsonExchangeBet {
+        JsonExchangeBet {
+            back_lay: self.back_lay.to_string(),
+            odds: self.odds.to_f64(),
+            stake: self.stake.to_f64(),
+            liability: self.approximate_liability(),
+            commission: self.commission.to_f64(),
+        }
+    }
+}
+
+impl fmt::Display for ExchangeBet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let liability = match self.liability() {
+            Ok(liability) => liability.to_string(),
+            Err(_) => format!("{:.2}", self.approximate_liability()),
+        };
+        let percent = match self.commission.checked_mul(Rational::from_integer(100)) {
+            Ok(percent) => percent.to_string(),
+            Err(_) => (self.commission.to_f64() * 100.0).to_string(),
+        };
+        write!(
+            f,
+            "{} at {} for a stake of {} (liability {}), {}% commission on net winnings",
+            self.back_lay, self.odds, self.stake, liability, percent
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn bet(back_lay: BackLay, odds: &str, stake: &str, commission: &str) -> ExchangeBet {
+        ExchangeBet::new(
+            back_lay,
+            Rational::from_str(odds).unwrap(),
+            Rational::from_str(stake).unwrap(),
+            Rational::from_str(commission).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_back_profit_after_commission() {
+        let back = bet(BackLay::Back, "2.5", "10", "0.05");
+
+        // 15 winnings less 5% commission
+        assert_eq!(
+            back.get_profit(Rational::one()).unwrap(),
+            Rational::new(285, 20).unwrap()
+        );
+        assert_eq!(
+            back.get_profit(-Rational::one()).unwrap(),
+            Rational::from_integer(-10)
+        );
+        // Half won: half the stake wins at the odds, the other half is refunded
+        assert_eq!(
+            back.get_profit(Rational::new(1, 2).unwrap()).unwrap(),
+            Rational::new(285, 40).unwrap()
+        );
+        assert_eq!(
+            back.get_profit(Rational::new(-1, 2).unwrap()).unwrap(),
+            Rational::from_integer(-5)
+        );
+        assert_eq!(back.liability().unwrap(), Rational::from_integer(10));
+    }
+
+    #[test]
+    fn test_lay_profit_and_liability() {
+        let lay = bet(BackLay::Lay, "2.02", "10", "0.02");
+
+        assert_eq!(lay.liability().unwrap(), Rational::new(102, 10).unwrap());
+        assert_eq!(
+            lay.get_profit(Rational::one()).unwrap(),
+            Rational::new(-102, 10).unwrap()
+        );
+        // Keeps the backer's stake, less commission
+        assert_eq!(
+            lay.get_profit(-Rational::one()).unwrap(),
+            Rational::new(98, 10).unwrap()
+        );
+        assert_eq!(lay.get_profit(Rational::zero()).unwrap(), Rational::zero());
+    }
+
+    #[test]
+    fn test_rejects_invalid_bets() {
+        let rational = |input| Rational::from_str(input).unwrap();
+        assert!(
+            ExchangeBet::new(BackLay::Back, rational("1"), rational("1"), rational("0")).is_err()
+        );
+        assert!(
+            ExchangeBet::new(BackLay::Back, rational("2"), rational("0"), rational("0")).is_err()
+        );
+        assert!(
+            ExchangeBet::new(BackLay::Lay, rational("2"), rational("1"), rational("1")).is_err()
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::{anyhow, bail, Result};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::config::MINUTES_PER_MATCH;
+use crate::market_specifiers::{HomeAwaySide, Period};
+use crate::score::Score;
+
+/// A goal, with the minute it was scored in if known (stoppage time counts as the
+/// minute it was added to, e.g. 45+2 is 45)
+#[derive(Debug, PartialEq, Clone)]
+pub struct Goal {
+    pub team: HomeAwaySide,
+    pub minute: Option<u32>,
+}
+
+impl Goal {
+    /// The half the goal was scored in, if its minute is known
+    pub fn half(&self) -> Option<Period> {
+        self.minute
+            .map(|minute| match minute as f64 <= MINUTES_PER_MATCH / 2.0 {
+                true => Period::FirstHalf,
+                false => Period::SecondHalf,
+            })
+    }
+}
+
+/// The goals of a match in the order they were scored, for markets that depend on more than
+/// the final score
+#[derive(Debug, PartialEq, Clone)]
+pub struct GoalSequence {
+    pub goals: Vec<Goal>,
+}
+
+impl GoalSequence {
+    pub fn final_score(&self) -> Score {
+        let home_goals = self
+            .goals
+            .iter()
+            .filter(|goal| goal.team == HomeAwaySide::Home)
+            .count();
+        Score::new(home_goals, self.goals.len() - home_goals)
+    }
+
+    // Parses one goal such as "H", "a", "H23" or "A45+2"
+    fn parse_goal(input: &str) -> Result<Goal> {
+        let invalid = || {
+            anyhow!(
+                "Invalid goal '{}'. Goals are the team (H or A) optionally followed by the minute, for example H23 or A45+2.",
+                input
+            )
+        };
+
+        let mut chars = input.chars();
+        let team = match chars.next().map(|c| c.to_ascii_lowercase()) {
+            Some('h') => HomeAwaySide::Home,
+            Some('a') => HomeAwaySide::Away,
+            _ => return Err(invalid()),
+        };
+        let minute = chars.as_str();
+        if minute.is_empty() {
+            return Ok(Goal { team, minute: None });
+        }
+
+        let (minute, added_time) = minute.split_once('+').unwrap_or((minute, "0"));
+        let minute: u32 = minute.parse().map_err(|_| invalid())?;
+        added_time.parse::<u32>().map_err(|_| invalid())?;
+        if minute as f64 > MINUTES_PER_MATCH {
+            bail!(
+                "Invalid goal '{}'. Minutes can't be past {} (use e.g. 90+3 for stoppage time).",
+                input,
+                MINUTES_PER_MATCH
+            );
+        }
+        Ok(Goal {
+            team,
+            minute: Some(minute),
+        })
+    }
+}
+
+impl FromStr for GoalSequence {
+    type Err = anyhow::Error;
+
+    /// Goals separated by commas or spaces, e.g. "H23, A45+2, H81". "none" (or nothing)
+    /// for a goalless match.
+    fn from_str(input: &str) -> Result<GoalSequence> {
+        if input.trim() == "none" {
+            return Ok(GoalSequence { goals: vec![] });
+        }
+        let goals = input
+            .split([',', ' '])
+            .filter(|goal| !goal.is_empty())
+            .map(GoalSequence::parse_goal)
+            .collect::<Result<Vec<Goal>>>()?;
+
+        // Goal times, where given, must not go back in time
+        let minutes: Vec<u32> = goals.iter().filter_map(|goal| goal.minute).collect();
+        if minutes.windows(2).any(|pair| pair[0] > pair[1]) {
+            bail!(
+                "Invalid goal sequence '{}'. Goals must be listed in the order they were scored.",
+                input
+            );
+        }
+        Ok(GoalSequence { goals })
+    }
+}
+
+impl fmt::Display for GoalSequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.goals.is_empty() {
+            return write!(f, "none");
+        }
+        let goals: Vec<String> = self
+            .goals
+            .iter()
+            .map(|goal| {
+                let team = match goal.team {
+                    HomeAwaySide::Home => "H",
+                    HomeAwaySide::Away => "A",
+                };
+                match goal.minute {
+                    Some(minute) => format!("{}{}", team, minute),
+                    None => team.to_owned(),
+                }
+            })
+            .collect();
+        write!(f, "{}", goals.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_goals_with_and_without_minutes() {
+        let sequence = GoalSequence::from_str("H23, a45+2 H").unwrap();
+
+        assert_eq!(sequence.final_score(), Score::new(2, 1));
+        assert_eq!(sequence.goals[1].team, HomeAwaySide::Away);
+        assert_eq!(sequence.goals[1].half(), Some(Period::FirstHalf));
+        assert_eq!(sequence.goals[2].minute, None);
+        assert_eq!(sequence.to_string(), "H23, A45, H");
+        assert_eq!(GoalSequence::from_str("none").unwrap().goals, vec![]);
+    }
+
+    #[test]
+    fn test_rejects_invalid_sequences() {
+        for input in ["X12", "H12x", "H95", "H60, A30", "H45+"] {
+            assert!(
+                GoalSequence::from_str(input).is_err(),
+                "Expected '{}' to be rejected",
+                input
+            );
+        }
+    }
+}
@@ -1,48 +1,383 @@
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
 use anyhow::{bail, Result};
 use colored::*;
 use serde::Serialize;
-use serde_json;
 
 use crate::bet_grid_handler::OutputMode;
+use crate::config::TAIL_PROBE_GOALS;
+use crate::exchange::{ExchangeBet, JsonExchangeBet};
+use crate::market_specifiers::{BackLay, Period};
+use crate::rational::Rational;
+use crate::score::Score;
+use crate::settlement::Outcome;
+
+/// Range of home and away goals (both inclusive) that a grid covers. With `tail` set,
+/// the last row and column are "N+" buckets holding every score at or beyond the edge.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GridBounds {
+    pub min_home_goals: usize,
+    pub max_home_goals: usize,
+    pub min_away_goals: usize,
+    pub max_away_goals: usize,
+    pub tail: bool,
+}
+
+impl GridBounds {
+    /// Square grid from 0-0 up to `grid_size - 1` goals for each team
+    pub fn square(grid_size: usize) -> Self {
+        Self {
+            min_home_goals: 0,
+            max_home_goals: grid_size.saturating_sub(1),
+            min_away_goals: 0,
+            max_away_goals: grid_size.saturating_sub(1),
+            tail: false,
+        }
+    }
+
+    pub fn home_goals(&self) -> RangeInclusive<usize> {
+        self.min_home_goals..=self.max_home_goals
+    }
+
+    pub fn away_goals(&self) -> RangeInclusive<usize> {
+        self.min_away_goals..=self.max_away_goals
+    }
+
+    pub fn num_home_goals(&self) -> usize {
+        self.max_home_goals + 1 - self.min_home_goals
+    }
+
+    pub fn num_away_goals(&self) -> usize {
+        self.max_away_goals + 1 - self.min_away_goals
+    }
+
+    pub fn is_home_tail(&self, home_goals: usize) -> bool {
+        self.tail && home_goals == self.max_home_goals
+    }
+
+    pub fn is_away_tail(&self, away_goals: usize) -> bool {
+        self.tail && away_goals == self.max_away_goals
+    }
+
+    /// Home goals a row stands for: just its own score, or everything from the edge up for the tail
+    pub fn home_goals_in_row(&self, home_goals: usize) -> RangeInclusive<usize> {
+        match self.is_home_tail(home_goals) {
+            true => home_goals..=home_goals + TAIL_PROBE_GOALS,
+            false => home_goals..=home_goals,
+        }
+    }
+
+    /// Away goals a column stands for: just its own score, or everything from the edge up for the tail
+    pub fn away_goals_in_col(&self, away_goals: usize) -> RangeInclusive<usize> {
+        match self.is_away_tail(away_goals) {
+            true => away_goals..=away_goals + TAIL_PROBE_GOALS,
+            false => away_goals..=away_goals,
+        }
+    }
+
+    /// Every final score the grid stands for, including those in the tail buckets
+    pub fn scores(&self) -> Vec<(usize, usize)> {
+        let mut scores = vec![];
+        for home_goals in self
+            .home_goals()
+            .flat_map(|goals| self.home_goals_in_row(goals))
+        {
+            for away_goals in self
+                .away_goals()
+                .flat_map(|goals| self.away_goals_in_col(goals))
+            {
+                scores.push((home_goals, away_goals));
+            }
+        }
+        scores
+    }
+
+    // Header label for a row/column, e.g. "9" or "9+" for the tail bucket
+    fn goals_label(&self, goals: usize, is_tail: bool) -> String {
+        match is_tail {
+            true => format!("{}+", goals),
+            false => goals.to_string(),
+        }
+    }
+}
 
 pub struct Grid {
-    grid: Vec<Vec<f64>>,
-    grid_size: usize,
+    // `None` for cells the match can no longer finish on (below the current score). Market
+    // grids hold even money payoffs per unit staked, even with an exchange bet set.
+    grid: Vec<Vec<Option<Rational>>>,
+    // Tail cells whose payoff isn't the same for every score they hold
+    ambiguous: Vec<Vec<bool>>,
+    // Text shown instead of a payoff, for cells that don't settle on the score alone
+    labels: Vec<Vec<Option<String>>>,
+    bounds: GridBounds,
+    title: Option<String>,
+    current_score: Option<Score>,
+    period: Option<Period>,
+    half_time_score: Option<Score>,
+    exchange_bet: Option<ExchangeBet>,
+    // What the exchange bet receives for each cell, worked out when it's set
+    profits: Vec<Vec<Option<Rational>>>,
+    show_outcomes: bool,
+}
+
+#[derive(Serialize)]
+pub struct JsonCell {
+    pub home_goals: usize,
+    pub away_goals: usize,
+}
+
+#[derive(Serialize)]
+pub struct JsonLabelledCell {
+    pub home_goals: usize,
+    pub away_goals: usize,
+    pub label: String,
 }
 
 #[derive(Serialize)]
 pub struct JsonGrid {
-    grid_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    market: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    period: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    half_time_score: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current_score: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exchange: Option<JsonExchangeBet>,
+    #[serde(flatten)]
+    bounds: GridBounds,
     pub payoff_grid: HashMap<usize, HashMap<usize, f64>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ambiguous_cells: Vec<JsonCell>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labelled_cells: Vec<JsonLabelledCell>,
 }
 
 impl Grid {
-    pub fn new(grid_size: usize) -> Self {
-        let size = grid_size.try_into().unwrap();
+    pub fn new(bounds: &GridBounds) -> Self {
         Self {
-            grid: vec![vec![0.0; size]; size],
-            grid_size: size,
+            grid: vec![vec![None; bounds.num_away_goals()]; bounds.num_home_goals()],
+            ambiguous: vec![vec![false; bounds.num_away_goals()]; bounds.num_home_goals()],
+            labels: vec![vec![None; bounds.num_away_goals()]; bounds.num_home_goals()],
+            bounds: bounds.clone(),
+            title: None,
+            current_score: None,
+            period: None,
+            half_time_score: None,
+            exchange_bet: None,
+            profits: vec![],
+            show_outcomes: false,
         }
     }
 
-    pub fn set_payoff(&mut self, idx_x: usize, idx_y: usize, payoff: f64) -> Result<()> {
-        if !self.is_in_bounds(idx_x, idx_y) {
-            bail!(
-                "Got invalid x or y coordinate: {} {}. It must be between 0 and {}.",
-                idx_x,
-                idx_y,
-                self.grid_size
-            );
+    /// Marks the grid as being over the goals scored in `period` rather than the final score
+    pub fn set_period(&mut self, period: Period) {
+        self.period = Some(period);
+    }
+
+    /// Marks a second-half grid as being over final scores from `half_time_score` at half
+    /// time, rather than over the goals scored in the second half
+    pub fn set_half_time_score(&mut self, half_time_score: Score) {
+        self.half_time_score = Some(half_time_score);
+    }
+
+    /// Turns the even money payoffs per unit staked into what `exchange_bet` actually
+    /// receives. Set it once the payoffs are filled in.
+    pub fn set_exchange_bet(&mut self, exchange_bet: &ExchangeBet) -> Result<()> {
+        self.profits = self
+            .grid
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|payoff| {
+                        payoff
+                            .map(|payoff| exchange_bet.get_profit(payoff))
+                            .transpose()
+                    })
+                    .collect::<Result<_>>()
+            })
+            .collect::<Result<_>>()?;
+        self.exchange_bet = Some(exchange_bet.clone());
+        Ok(())
+    }
+
+    /// Prints each cell of a market grid as the outcome of the bet (win, half-win, push, ...)
+    /// instead of its payoff
+    pub fn set_show_outcomes(&mut self) {
+        self.show_outcomes = true;
+    }
+
+    /// Marks the match as in play at `current_score`: final scores below it can't happen,
+    /// so `fill_payoffs` leaves those cells empty (and they are greyed out when printed)
+    pub fn set_current_score(&mut self, current_score: Score) {
+        self.current_score = Some(current_score);
+    }
+
+    fn is_reachable(&self, home_goals: usize, away_goals: usize) -> bool {
+        match &self.current_score {
+            Some(current_score) => current_score.can_reach(home_goals, away_goals),
+            None => true,
+        }
+    }
+
+    /// Sets the market description shown above the grid (and in json output)
+    pub fn set_title(&mut self, title: String) {
+        self.title = Some(title);
+    }
+
+    pub fn set_payoff(
+        &mut self,
+        home_goals: usize,
+        away_goals: usize,
+        payoff: Rational,
+    ) -> Result<()> {
+        let (row, col) = self.get_cell_index(home_goals, away_goals)?;
+        self.grid[row][col] = Some(payoff);
+        Ok(())
+    }
+
+    /// Sets every reachable cell from `payoff(home_goals, away_goals)`. Tail cells are settled
+    /// over all the scores they hold: if those disagree the cell is flagged as ambiguous and
+    /// shows the payoff of the lowest (reachable) score.
+    pub fn fill_payoffs<F>(&mut self, payoff: F) -> Result<()>
+    where
+        F: Fn(usize, usize) -> Rational,
+    {
+        self.fill_possible_payoffs(|home_goals, away_goals| Some(payoff(home_goals, away_goals)))
+    }
+
+    /// Like `fill_payoffs`, for markets where some final scores are ruled out by more than the
+    /// current score: `payoff` returns `None` for those, and they are left empty.
+    pub fn fill_possible_payoffs<F>(&mut self, payoff: F) -> Result<()>
+    where
+        F: Fn(usize, usize) -> Option<Rational>,
+    {
+        self.try_fill_payoffs(|home_goals, away_goals| Ok(payoff(home_goals, away_goals)))
+    }
+
+    /// Like `fill_payoffs`, for values that can fail to work out, e.g. the profit of a position
+    pub fn fill_profits<F>(&mut self, profit: F) -> Result<()>
+    where
+        F: Fn(usize, usize) -> Result<Rational>,
+    {
+        self.try_fill_payoffs(|home_goals, away_goals| profit(home_goals, away_goals).map(Some))
+    }
+
+    fn try_fill_payoffs<F>(&mut self, payoff: F) -> Result<()>
+    where
+        F: Fn(usize, usize) -> Result<Option<Rational>>,
+    {
+        for home_goals in self.bounds.home_goals() {
+            for away_goals in self.bounds.away_goals() {
+                let mut payoffs = vec![];
+                for home in self.bounds.home_goals_in_row(home_goals) {
+                    for away in self.bounds.away_goals_in_col(away_goals) {
+                        if !self.is_reachable(home, away) {
+                            continue;
+                        }
+                        if let Some(payoff) = payoff(home, away)? {
+                            payoffs.push(payoff);
+                        }
+                    }
+                }
+                let edge_payoff = match payoffs.first() {
+                    Some(&edge_payoff) => edge_payoff,
+                    None => continue,
+                };
+                let is_ambiguous = payoffs
+                    .iter()
+                    .any(|&other_payoff| other_payoff != edge_payoff);
+
+                self.set_payoff(home_goals, away_goals, edge_payoff)?;
+                self.ambiguous[home_goals - self.bounds.min_home_goals]
+                    [away_goals - self.bounds.min_away_goals] = is_ambiguous;
+            }
         }
+        Ok(())
+    }
 
-        self.grid[idx_x][idx_y] = payoff;
+    /// Shows `label` in a cell instead of its payoff, e.g. "ET" for a knockout tie that goes
+    /// to extra time
+    pub fn set_label(&mut self, home_goals: usize, away_goals: usize, label: String) -> Result<()> {
+        let (row, col) = self.get_cell_index(home_goals, away_goals)?;
+        self.labels[row][col] = Some(label);
         Ok(())
     }
 
-    fn is_in_bounds(&self, idx_x: usize, idx_y: usize) -> bool {
-        idx_x < self.grid_size && idx_y < self.grid_size
+    /// Flags a tail cell as ambiguous when it holds scores `fill_possible_payoffs` couldn't
+    /// settle, e.g. draws that go to extra time
+    pub fn set_ambiguous(&mut self, home_goals: usize, away_goals: usize) -> Result<()> {
+        let (row, col) = self.get_cell_index(home_goals, away_goals)?;
+        self.ambiguous[row][col] = true;
+        Ok(())
+    }
+
+    fn get_label(&self, home_goals: usize, away_goals: usize) -> Option<&String> {
+        self.labels[home_goals - self.bounds.min_home_goals]
+            [away_goals - self.bounds.min_away_goals]
+            .as_ref()
+    }
+
+    fn is_ambiguous(&self, home_goals: usize, away_goals: usize) -> bool {
+        self.ambiguous[home_goals - self.bounds.min_home_goals]
+            [away_goals - self.bounds.min_away_goals]
+    }
+
+    // Cell text, with ambiguous tail cells marked by a trailing "*" and unreachable cells by "."
+    fn payoff_label(&self, home_goals: usize, away_goals: usize) -> String {
+        if let Some(label) = self.get_label(home_goals, away_goals) {
+            return label.clone();
+        }
+        match self.get_payoff(home_goals, away_goals) {
+            None => ".".to_owned(),
+            Some(payoff) if self.is_ambiguous(home_goals, away_goals) => format!("{}*", payoff),
+            Some(payoff) => payoff.to_string(),
+        }
+    }
+
+    pub fn get_payoff(&self, home_goals: usize, away_goals: usize) -> Option<Rational> {
+        let grid = match self.exchange_bet {
+            Some(_) => &self.profits,
+            None => &self.grid,
+        };
+        grid[home_goals - self.bounds.min_home_goals][away_goals - self.bounds.min_away_goals]
+    }
+
+    /// How a bet on the market settles on a score, from its even money payoff
+    pub fn get_outcome(&self, home_goals: usize, away_goals: usize) -> Option<Outcome> {
+        let payoff = self.grid[home_goals - self.bounds.min_home_goals]
+            [away_goals - self.bounds.min_away_goals]?;
+        let back_lay = self
+            .exchange_bet
+            .as_ref()
+            .map_or(BackLay::Back, |exchange_bet| exchange_bet.back_lay);
+        Some(Outcome::from_bet_payoff(payoff, back_lay))
+    }
+
+    // Row and column of the cell for a score, which must be in the grid
+    fn get_cell_index(&self, home_goals: usize, away_goals: usize) -> Result<(usize, usize)> {
+        if !self.is_in_bounds(home_goals, away_goals) {
+            bail!(
+                "Got invalid home or away goals: {} {}. They must be between {}-{} and {}-{}.",
+                home_goals,
+                away_goals,
+                self.bounds.min_home_goals,
+                self.bounds.max_home_goals,
+                self.bounds.min_away_goals,
+                self.bounds.max_away_goals
+            );
+        }
+        Ok((
+            home_goals - self.bounds.min_home_goals,
+            away_goals - self.bounds.min_away_goals,
+        ))
+    }
+
+    fn is_in_bounds(&self, home_goals: usize, away_goals: usize) -> bool {
+        self.bounds.home_goals().contains(&home_goals)
+            && self.bounds.away_goals().contains(&away_goals)
     }
 
     pub fn print(&self, output_mode: &OutputMode) -> Result<()> {
@@ -56,108 +391,251 @@ impl Grid {
     }
 
     pub fn print_text(&self) -> Result<()> {
-        let col_lengths = self.get_column_lengths();
-        // "+ 3" because each payoff is prepended by a space and appended by space & "|"
-        let line_length: usize = 7 + col_lengths.values().map(|&v| v + 3).sum::<usize>();
-        let away_start = line_length / 2 - 2;
-
-        // Prints top line with "AWAY"
-        print!("{}", " ".repeat(away_start));
-        print!("AWAY");
-        print!("{}", " ".repeat(away_start));
-        println!();
-
-        let num_rows = 6 + self.grid_size;
-        let home_start = num_rows / 2 - 2;
-        let home_end = num_rows / 2 + 2;
-
-        let home = vec!["H", "O", "M", "E"];
-
-        // (horizontally) print away goals
-        print!("     ||");
-        for away_goals in 0..self.grid_size {
-            let col_length = self.get_col_length(&col_lengths, away_goals);
-            let away_goals_len = away_goals.to_string().len();
-            let extra_spaces = col_length.saturating_sub(away_goals_len);
-            print!(" {}{} |", " ".repeat(extra_spaces), away_goals);
+        if let Some(title) = &self.title {
+            println!("{}", title);
         }
-        println!();
-        // blank line separating away goals from payoff grid
-        println!("{}", "-".repeat(line_length));
-
-        // print out the grid (together with home goals)
-        for home_goals in 0..self.grid_size {
-            let row_idx = home_goals + 3;
-
-            // Prints home goals (and home label)
-            if row_idx >= home_start && row_idx < home_end {
-                print!("{} {:>2} ||", home[row_idx - home_start], home_goals);
-            } else {
-                print!("  {:>2} ||", home_goals);
+        if let Some(period) = &self.period {
+            match &self.half_time_score {
+                Some(half_time_score) => println!(
+                    "{} market from {} at half time: the grid shows final scores",
+                    period, half_time_score
+                ),
+                None => println!(
+                    "{} market: the grid shows goals scored in that period",
+                    period
+                ),
             }
+        }
+        if let Some(current_score) = &self.current_score {
+            println!(
+                "In play at {} (greyed out scores can't happen any more)",
+                current_score
+            );
+        }
+        if let Some(exchange_bet) = &self.exchange_bet {
+            println!("{}", exchange_bet);
+        }
 
-            // Payoff
-            for away_goals in 0..self.grid_size {
-                let col_length = self.get_col_length(&col_lengths, away_goals);
-
-                let payoff = self.grid[home_goals][away_goals];
-                let payoff_str = payoff.to_string();
-                let extra_spaces = col_length.saturating_sub(payoff_str.len());
-                // println!("extra spaces {} because my payoff len is {} and total {}", extra_spaces, payoff_str.len(), col_length);
-                if payoff > 0.0 {
-                    print!(" {}{} |", " ".repeat(extra_spaces), payoff_str.green());
-                } else if payoff < 0.0 {
-                    print!(" {}{} |", " ".repeat(extra_spaces), payoff_str.red());
-                } else {
-                    print!(" {}{} |", " ".repeat(extra_spaces), payoff_str.white());
-                }
-            }
-            println!();
+        match self.show_outcomes {
+            true => self.print_outcomes(),
+            false => print_goals_table(&self.bounds, |home_goals, away_goals| {
+                let style = match self.get_payoff(home_goals, away_goals) {
+                    _ if self.get_label(home_goals, away_goals).is_some() => CellStyle::Neutral,
+                    None => CellStyle::Unreachable,
+                    Some(payoff) if payoff.is_positive() => CellStyle::Positive,
+                    Some(payoff) if payoff.is_negative() => CellStyle::Negative,
+                    Some(_) => CellStyle::Neutral,
+                };
+                (self.payoff_label(home_goals, away_goals), style)
+            }),
+        }
+
+        if self
+            .ambiguous
+            .iter()
+            .flatten()
+            .any(|&is_ambiguous| is_ambiguous)
+        {
+            println!("* payoff depends on the exact score beyond the grid edge (shown for the edge score)");
         }
 
         Ok(())
     }
 
-    fn get_column_lengths(&self) -> HashMap<usize, usize> {
-        // Each column length is the longest
-        let mut goal_to_col_len = HashMap::new();
-        for away_goals in 0..self.grid_size {
-            let mut largest_payoff = String::new();
-            for home_goals in 0..self.grid_size {
-                let payoff_str = self.grid[home_goals][away_goals].to_string();
-                if payoff_str.len() > largest_payoff.len() {
-                    largest_payoff = payoff_str.clone();
-                }
+    // The grid of outcome letters, and their legend
+    fn print_outcomes(&self) {
+        print_goals_table(&self.bounds, |home_goals, away_goals| {
+            if let Some(label) = self.get_label(home_goals, away_goals) {
+                return (label.clone(), CellStyle::Neutral);
             }
-            goal_to_col_len.insert(away_goals, largest_payoff.len());
-        }
-        return goal_to_col_len;
-    }
-
-    fn get_col_length(&self, col_lengths: &HashMap<usize, usize>, goals: usize) -> usize {
-        match col_lengths.get(&goals) {
-            Some(computed_col_length) => *computed_col_length,
-            _ => 3,
-        }
+            match self.get_outcome(home_goals, away_goals) {
+                None => (".".to_owned(), CellStyle::Unreachable),
+                Some(outcome) if self.is_ambiguous(home_goals, away_goals) => (
+                    format!("{}*", outcome.letter()),
+                    CellStyle::Outcome(outcome),
+                ),
+                Some(outcome) => (outcome.letter().to_owned(), CellStyle::Outcome(outcome)),
+            }
+        });
+        let legend: Vec<String> = [
+            Outcome::Win,
+            Outcome::HalfWin,
+            Outcome::Push,
+            Outcome::HalfLoss,
+            Outcome::Loss,
+            Outcome::Void,
+        ]
+        .iter()
+        .map(|outcome| {
+            format!(
+                "{} {}",
+                CellStyle::Outcome(*outcome).paint(outcome.letter()),
+                outcome.to_string().to_lowercase()
+            )
+        })
+        .collect();
+        println!("{}", legend.join(", "));
     }
 
-    pub fn print_json(&self) -> Result<JsonGrid> {
+    pub fn to_json(&self) -> JsonGrid {
         let mut map_grid: HashMap<usize, HashMap<usize, f64>> = HashMap::new();
+        let mut ambiguous_cells = vec![];
+        let mut labelled_cells = vec![];
 
-        for home_goals in 0..self.grid_size {
+        for home_goals in self.bounds.home_goals() {
             let mut row = HashMap::new();
-            for away_goals in 0..self.grid_size {
-                row.insert(away_goals, self.grid[home_goals][away_goals]);
+            for away_goals in self.bounds.away_goals() {
+                // Scores that can't happen any more are left out
+                if let Some(payoff) = self.get_payoff(home_goals, away_goals) {
+                    row.insert(away_goals, payoff.to_f64());
+                }
+                if self.is_ambiguous(home_goals, away_goals) {
+                    ambiguous_cells.push(JsonCell {
+                        home_goals,
+                        away_goals,
+                    });
+                }
+                if let Some(label) = self.get_label(home_goals, away_goals) {
+                    labelled_cells.push(JsonLabelledCell {
+                        home_goals,
+                        away_goals,
+                        label: label.clone(),
+                    });
+                }
             }
             map_grid.insert(home_goals, row);
         }
 
-        let json_grid = JsonGrid {
-            grid_size: self.grid_size,
+        JsonGrid {
+            market: self.title.clone(),
+            period: self.period.map(|period| period.to_string()),
+            half_time_score: self.half_time_score.map(|score| score.to_string()),
+            current_score: self.current_score.map(|score| score.to_string()),
+            exchange: self.exchange_bet.as_ref().map(ExchangeBet::to_json),
+            bounds: self.bounds.clone(),
             payoff_grid: map_grid,
-        };
+            ambiguous_cells,
+            labelled_cells,
+        }
+    }
+
+    pub fn print_json(&self) -> Result<JsonGrid> {
+        let json_grid = self.to_json();
         let json_output = serde_json::to_string_pretty(&json_grid)?;
         println!("{}", json_output);
         Ok(json_grid)
     }
 }
+
+/// How a cell is coloured when a goals table is printed
+pub enum CellStyle {
+    Positive,
+    Negative,
+    Neutral,
+    Unreachable,
+    Outcome(Outcome),
+}
+
+impl CellStyle {
+    fn paint(&self, label: &str) -> ColoredString {
+        match self {
+            CellStyle::Positive => label.green(),
+            CellStyle::Negative => label.red(),
+            CellStyle::Neutral => label.white(),
+            CellStyle::Unreachable => label.dimmed(),
+            CellStyle::Outcome(Outcome::Win) => label.green().bold(),
+            CellStyle::Outcome(Outcome::HalfWin) => label.bright_green(),
+            CellStyle::Outcome(Outcome::Push) => label.yellow(),
+            CellStyle::Outcome(Outcome::HalfLoss) => label.bright_red(),
+            CellStyle::Outcome(Outcome::Loss) => label.red().bold(),
+            CellStyle::Outcome(Outcome::Void) => label.blue(),
+        }
+    }
+}
+
+/// Prints a table with away goals across and home goals down, e.g. a payoff grid.
+/// `cell` gives the text and style of each cell.
+pub fn print_goals_table<F>(bounds: &GridBounds, cell: F)
+where
+    F: Fn(usize, usize) -> (String, CellStyle),
+{
+    // Each column length is the longest cell (or the away goals header, if that's longer)
+    let mut col_lengths = HashMap::new();
+    for away_goals in bounds.away_goals() {
+        let mut col_length = bounds
+            .goals_label(away_goals, bounds.is_away_tail(away_goals))
+            .len();
+        for home_goals in bounds.home_goals() {
+            col_length = col_length.max(cell(home_goals, away_goals).0.chars().count());
+        }
+        col_lengths.insert(away_goals, col_length);
+    }
+    // Home goals labels are at least 2 wide, wider for e.g. "10+" tail buckets
+    let home_label_length = bounds
+        .home_goals()
+        .map(|home_goals| {
+            bounds
+                .goals_label(home_goals, bounds.is_home_tail(home_goals))
+                .len()
+        })
+        .max()
+        .unwrap_or(0)
+        .max(2);
+    // "+ 3" because each payoff is prepended by a space and appended by space & "|"
+    let line_length: usize =
+        home_label_length + 5 + col_lengths.values().map(|&v| v + 3).sum::<usize>();
+    let away_start = line_length / 2 - 2;
+
+    // Prints top line with "AWAY"
+    print!("{}", " ".repeat(away_start));
+    print!("AWAY");
+    print!("{}", " ".repeat(away_start));
+    println!();
+
+    let num_rows = 6 + bounds.num_home_goals();
+    let home_start = num_rows / 2 - 2;
+    let home_end = num_rows / 2 + 2;
+
+    let home = ["H", "O", "M", "E"];
+
+    // (horizontally) print away goals
+    print!("{}||", " ".repeat(home_label_length + 3));
+    for away_goals in bounds.away_goals() {
+        let col_length = col_lengths[&away_goals];
+        let away_goals_label = bounds.goals_label(away_goals, bounds.is_away_tail(away_goals));
+        let extra_spaces = col_length.saturating_sub(away_goals_label.len());
+        print!(" {}{} |", " ".repeat(extra_spaces), away_goals_label);
+    }
+    println!();
+    // blank line separating away goals from the grid
+    println!("{}", "-".repeat(line_length));
+
+    // print out the grid (together with home goals)
+    for (row, home_goals) in bounds.home_goals().enumerate() {
+        let row_idx = row + 3;
+
+        // Prints home goals (and home label)
+        let home_goals_label = bounds.goals_label(home_goals, bounds.is_home_tail(home_goals));
+        if row_idx >= home_start && row_idx < home_end {
+            print!(
+                "{} {:>width$} ||",
+                home[row_idx - home_start],
+                home_goals_label,
+                width = home_label_length
+            );
+        } else {
+            print!(
+                "  {:>width$} ||",
+                home_goals_label,
+                width = home_label_length
+            );
+        }
+
+        for away_goals in bounds.away_goals() {
+            let (label, style) = cell(home_goals, away_goals);
+            let extra_spaces = col_lengths[&away_goals].saturating_sub(label.chars().count());
+            print!(" {}{} |", " ".repeat(extra_spaces), style.paint(&label));
+        }
+        println!();
+    }
+}
@@ -0,0 +1,423 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::grid::{Grid, GridBounds, JsonGrid};
+use crate::linear_program::{maximise, LinearProgramSolution};
+use crate::position::{
+    get_position_profit, parse_number, round_stake, CommissionMarkets, JsonBetSpec,
+    JsonPositionBet, PositionBet,
+};
+use crate::rational::Rational;
+
+// Weight on the total hedge stake in the objective, so that of equally good hedges the
+// smallest one is picked
+const STAKE_PENALTY: f64 = 1e-6;
+// Most times the stakes are solved again for the markets that now make a profit
+const MAX_COMMISSION_ROUNDS: usize = 10;
+
+/// What the hedge stakes should achieve over the scores in the grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HedgeObjective {
+    /// Make the worst net profit as high as possible
+    WorstCase,
+    /// Make the net profit as even as possible: the smallest gap between best and worst
+    Equalise,
+}
+
+impl FromStr for HedgeObjective {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<HedgeObjective, ()> {
+        match input {
+            "worst-case" => Ok(HedgeObjective::WorstCase),
+            "w" => Ok(HedgeObjective::WorstCase),
+            "equalise" | "equalize" => Ok(HedgeObjective::Equalise),
+            "e" => Ok(HedgeObjective::Equalise),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for HedgeObjective {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HedgeObjective::WorstCase => write!(f, "Maximise the worst case"),
+            HedgeObjective::Equalise => write!(f, "Equalise profit"),
+        }
+    }
+}
+
+/// A position file: the bets already placed, and the markets available to hedge them with.
+/// Hedge markets are bets without a stake, optionally capped with `max_stake`.
+#[derive(Deserialize)]
+pub struct JsonPositionFile {
+    pub position: Vec<JsonBetSpec>,
+    #[serde(default)]
+    pub hedges: Vec<JsonHedgeMarket>,
+}
+
+#[derive(Deserialize)]
+pub struct JsonHedgeMarket {
+    #[serde(flatten)]
+    pub bet: JsonBetSpec,
+    pub max_stake: Option<Value>,
+}
+
+#[derive(Serialize)]
+pub struct JsonHedge {
+    pub objective: String,
+    pub position: Vec<JsonPositionBet>,
+    pub hedges: Vec<JsonPositionBet>,
+    pub worst_case: f64,
+    pub best_case: f64,
+    pub unhedged_worst_case: f64,
+    pub unhedged_best_case: f64,
+    pub net_grid: JsonGrid,
+}
+
+/// A position together with the hedge bets that flatten it. Hedges that aren't needed have
+/// a stake of 0.
+pub struct Hedge {
+    pub objective: HedgeObjective,
+    pub position: Vec<PositionBet>,
+    pub hedges: Vec<PositionBet>,
+}
+
+impl Hedge {
+    /// Finds the hedge stakes that best meet `objective` over the final scores in `bounds`.
+    /// Commission is taken from the net winnings of each market, so a hedge on the same market
+    /// as the position offsets it before commission. Stakes are priced linearly with
+    /// commission taken on the markets in profit on each score, and solved again until those
+    /// stay the same.
+    pub fn new(
+        objective: HedgeObjective,
+        position: Vec<PositionBet>,
+        markets: Vec<(PositionBet, Option<Rational>)>,
+        bounds: &GridBounds,
+    ) -> Result<Self> {
+        let scores = bounds.scores();
+        let all_bets: Vec<PositionBet> = position
+            .iter()
+            .chain(markets.iter().map(|(bet, _)| bet))
+            .cloned()
+            .collect();
+        let commission_markets = CommissionMarkets::new(&all_bets);
+        let (position_markets, hedge_markets) =
+            commission_markets.bet_markets.split_at(position.len());
+
+        // Profit before commission of the position on each commission market, and of each
+        // hedge market per unit staked, for each score
+        let mut position_gross_profits =
+            vec![vec![0.0; commission_markets.commissions.len()]; scores.len()];
+        let mut unit_gross_profits = vec![];
+        for (gross_profits, &(home_goals, away_goals)) in
+            position_gross_profits.iter_mut().zip(&scores)
+        {
+            for (bet, &market) in position.iter().zip(position_markets) {
+                gross_profits[market] += bet.get_gross_profit(home_goals, away_goals)?.to_f64();
+            }
+            unit_gross_profits.push(
+                markets
+                    .iter()
+                    .map(|(bet, _)| Ok(bet.get_gross_profit(home_goals, away_goals)?.to_f64()))
+                    .collect::<Result<Vec<_>>>()?,
+            );
+        }
+
+        let mut stakes = vec![0.0; markets.len()];
+        let mut kept_fractions = vec![];
+        for _ in 0..MAX_COMMISSION_ROUNDS {
+            // What's kept of each commission market's net profit with the current stakes
+            let new_kept_fractions: Vec<Vec<f64>> = position_gross_profits
+                .iter()
+                .zip(&unit_gross_profits)
+                .map(|(position_gross_profits, unit_gross_profits)| {
+                    let mut gross_profits = position_gross_profits.clone();
+                    for ((stake, unit_profit), &market) in
+                        stakes.iter().zip(unit_gross_profits).zip(hedge_markets)
+                    {
+                        gross_profits[market] += stake * unit_profit;
+                    }
+                    commission_markets.get_kept_fractions(&gross_profits)
+                })
+                .collect();
+            if new_kept_fractions == kept_fractions {
+                break;
+            }
+            kept_fractions = new_kept_fractions;
+
+            let position_profits: Vec<f64> = position_gross_profits
+                .iter()
+                .zip(&kept_fractions)
+                .map(|(gross_profits, kept)| {
+                    gross_profits
+                        .iter()
+                        .zip(kept)
+                        .map(|(profit, kept)| profit * kept)
+                        .sum()
+                })
+                .collect();
+            let unit_profits: Vec<Vec<f64>> = unit_gross_profits
+                .iter()
+                .zip(&kept_fractions)
+                .map(|(unit_gross_profits, kept)| {
+                    unit_gross_profits
+                        .iter()
+                        .zip(hedge_markets)
+                        .map(|(profit, &market)| profit * kept[market])
+                        .collect()
+                })
+                .collect();
+            stakes = match objective {
+                HedgeObjective::WorstCase => {
+                    Hedge::maximise_worst_case(&position_profits, &unit_profits, &markets)?
+                }
+                HedgeObjective::Equalise => {
+                    Hedge::minimise_spread(&position_profits, &unit_profits, &markets)?
+                }
+            };
+        }
+
+        let hedges = markets
+            .into_iter()
+            .zip(stakes)
+            .map(|((mut bet, max_stake), stake)| {
+                let stake = round_stake(stake)?;
+                bet.exchange_bet.stake = match max_stake {
+                    Some(max_stake) if stake > max_stake => max_stake,
+                    _ => stake,
+                };
+                Ok(bet)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            objective,
+            position,
+            hedges,
+        })
+    }
+
+    // Variables are the stakes and the worst case's margin over the unhedged worst case
+    // (which the hedge can always match by staking nothing), so the origin is feasible
+    fn maximise_worst_case(
+        position_profits: &[f64],
+        unit_profits: &[Vec<f64>],
+        markets: &[(PositionBet, Option<Rational>)],
+    ) -> Result<Vec<f64>> {
+        let num_markets = markets.len();
+        let unhedged_worst_case = position_profits.iter().cloned().fold(f64::MAX, f64::min);
+
+        let mut objective = vec![-STAKE_PENALTY; num_markets];
+        objective.push(1.0);
+        let (mut a, mut b) = (vec![], vec![]);
+        for (profit, unit_profits) in position_profits.iter().zip(unit_profits) {
+            let mut row: Vec<f64> = unit_profits.iter().map(|unit| -unit).collect();
+            row.push(1.0);
+            a.push(row);
+            b.push(profit - unhedged_worst_case);
+        }
+        Hedge::add_stake_limits(markets, num_markets + 1, &mut a, &mut b);
+
+        match maximise(&objective, &a, &b) {
+            LinearProgramSolution::Optimal { x, .. } => Ok(x[..num_markets].to_vec()),
+            LinearProgramSolution::Unbounded => bail!("The hedge markets make a profit on every score in the grid on their own, so there's no limit to the stakes. Set a 'max_stake' on them."),
+            LinearProgramSolution::Infeasible => Err(anyhow!("Couldn't find hedge stakes.")),
+        }
+    }
+
+    // Variables are the stakes, the lowest net profit (split into positive and negative
+    // parts) and the spread above it that every score must fall within
+    fn minimise_spread(
+        position_profits: &[f64],
+        unit_profits: &[Vec<f64>],
+        markets: &[(PositionBet, Option<Rational>)],
+    ) -> Result<Vec<f64>> {
+        let num_markets = markets.len();
+        let mut objective = vec![-STAKE_PENALTY; num_markets];
+        objective.extend([0.0, 0.0, -1.0]);
+        let (mut a, mut b) = (vec![], vec![]);
+        for (profit, unit_profits) in position_profits.iter().zip(unit_profits) {
+            // lowest <= net profit
+            let mut row: Vec<f64> = unit_profits.iter().map(|unit| -unit).collect();
+            row.extend([1.0, -1.0, 0.0]);
+            a.push(row);
+            b.push(*profit);
+            // net profit <= lowest + spread
+            let mut row = unit_profits.clone();
+            row.extend([-1.0, 1.0, -1.0]);
+            a.push(row);
+            b.push(-profit);
+        }
+        Hedge::add_stake_limits(markets, num_markets + 3, &mut a, &mut b);
+
+        match maximise(&objective, &a, &b) {
+            LinearProgramSolution::Optimal { x, .. } => Ok(x[..num_markets].to_vec()),
+            _ => Err(anyhow!("Couldn't find hedge stakes.")),
+        }
+    }
+
+    fn add_stake_limits(
+        markets: &[(PositionBet, Option<Rational>)],
+        num_variables: usize,
+        a: &mut Vec<Vec<f64>>,
+        b: &mut Vec<f64>,
+    ) {
+        for (i, (_, max_stake)) in markets.iter().enumerate() {
+            if let Some(max_stake) = max_stake {
+                let mut row = vec![0.0; num_variables];
+                row[i] = 1.0;
+                a.push(row);
+                b.push(max_stake.to_f64());
+            }
+        }
+    }
+
+    /// Reads the hedge markets of a position file, with their stake limits
+    pub fn get_markets(hedges: &[JsonHedgeMarket]) -> Result<Vec<(PositionBet, Option<Rational>)>> {
+        hedges
+            .iter()
+            .map(|hedge| {
+                let max_stake = parse_number(hedge.max_stake.as_ref(), "max_stake")?;
+                if max_stake.is_some_and(|max_stake| max_stake.is_negative()) {
+                    bail!("Invalid max_stake. It can't be negative.");
+                }
+                Ok((PositionBet::from_json(&hedge.bet)?, max_stake))
+            })
+            .collect()
+    }
+
+    /// Profit of the position and the hedges together, which offset each other on the same
+    /// market before commission
+    pub fn get_net_profit(&self, home_goals: usize, away_goals: usize) -> Result<Rational> {
+        let bets: Vec<PositionBet> = self.position.iter().chain(&self.hedges).cloned().collect();
+        get_position_profit(&bets, home_goals, away_goals)
+    }
+
+    /// Lowest and highest net profit over the scores in `bounds`, with the hedges or without
+    pub fn get_range(&self, bounds: &GridBounds, hedged: bool) -> Result<(Rational, Rational)> {
+        let profits: Vec<Rational> = bounds
+            .scores()
+            .into_iter()
+            .map(|(home_goals, away_goals)| match hedged {
+                true => self.get_net_profit(home_goals, away_goals),
+                false => get_position_profit(&self.position, home_goals, away_goals),
+            })
+            .collect::<Result<_>>()?;
+        let worst_case = profits.iter().min().cloned().unwrap_or_else(Rational::zero);
+        let best_case = profits.iter().max().cloned().unwrap_or_else(Rational::zero);
+        Ok((worst_case, best_case))
+    }
+
+    pub fn get_net_grid(&self, bounds: &GridBounds) -> Result<Grid> {
+        let mut grid = Grid::new(bounds);
+        grid.set_title("Net profit after hedging".to_owned());
+        grid.fill_profits(|home_goals, away_goals| self.get_net_profit(home_goals, away_goals))?;
+        Ok(grid)
+    }
+
+    pub fn to_json(&self, bounds: &GridBounds) -> Result<JsonHedge> {
+        let (worst_case, best_case) = self.get_range(bounds, true)?;
+        let (unhedged_worst_case, unhedged_best_case) = self.get_range(bounds, false)?;
+        Ok(JsonHedge {
+            objective: self.objective.to_string(),
+            position: self.position.iter().map(PositionBet::to_json).collect(),
+            hedges: self.hedges.iter().map(PositionBet::to_json).collect(),
+            worst_case: worst_case.to_f64(),
+            best_case: best_case.to_f64(),
+            unhedged_worst_case: unhedged_worst_case.to_f64(),
+            unhedged_best_case: unhedged_best_case.to_f64(),
+            net_grid: self.get_net_grid(bounds)?.to_json(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::JsonBetSpec;
+
+    fn bet(json: &str) -> PositionBet {
+        let spec: JsonBetSpec = serde_json::from_str(json).unwrap();
+        PositionBet::from_json(&spec).unwrap()
+    }
+
+    fn hedge(objective: HedgeObjective, max_stake: Option<Rational>) -> Hedge {
+        // Backed home at 3 for 100: lay it back at 2
+        let position = vec![bet(
+            r#"{"bet_type": "win-draw-win", "side": "h", "odds": 3, "stake": 100}"#,
+        )];
+        let markets = vec![(
+            bet(r#"{"bet_type": "win-draw-win", "side": "h", "odds": 2, "lay": true}"#),
+            max_stake,
+        )];
+        Hedge::new(objective, position, markets, &GridBounds::square(4)).unwrap()
+    }
+
+    #[test]
+    fn test_locks_in_profit() {
+        for objective in [HedgeObjective::WorstCase, HedgeObjective::Equalise] {
+            let hedge = hedge(objective, None);
+            // Laying 150 at 2 wins 50 either way
+            assert_eq!(
+                hedge.hedges[0].exchange_bet.stake,
+                Rational::from_integer(150)
+            );
+            assert_eq!(
+                hedge.get_range(&GridBounds::square(4), true).unwrap(),
+                (Rational::from_integer(50), Rational::from_integer(50))
+            );
+        }
+    }
+
+    #[test]
+    fn test_respects_max_stake() {
+        let hedge = hedge(HedgeObjective::WorstCase, Some(Rational::from_integer(60)));
+        assert_eq!(
+            hedge.hedges[0].exchange_bet.stake,
+            Rational::from_integer(60)
+        );
+        // Not home: -100 + 60
+        assert_eq!(
+            hedge.get_net_profit(0, 0).unwrap(),
+            Rational::from_integer(-40)
+        );
+        assert_eq!(
+            hedge.get_range(&GridBounds::square(4), false).unwrap(),
+            (Rational::from_integer(-100), Rational::from_integer(200))
+        );
+    }
+
+    #[test]
+    fn test_commission_on_net_market_winnings() {
+        // The same back and lay as above on an exchange charging 5%: laying 150 still wins 50
+        // on the market whatever happens, and commission takes 2.5 of it once
+        let position = vec![bet(
+            r#"{"bet_type": "win-draw-win", "side": "h", "odds": 3, "stake": 100, "commission": 0.05}"#,
+        )];
+        let markets = vec![(
+            bet(
+                r#"{"bet_type": "win-draw-win", "side": "h", "odds": 2, "lay": true, "commission": 0.05}"#,
+            ),
+            None,
+        )];
+        let hedge = Hedge::new(
+            HedgeObjective::Equalise,
+            position,
+            markets,
+            &GridBounds::square(4),
+        )
+        .unwrap();
+        assert_eq!(
+            hedge.hedges[0].exchange_bet.stake,
+            Rational::from_integer(150)
+        );
+        let net_profit = Rational::new(95, 2).unwrap();
+        assert_eq!(
+            hedge.get_range(&GridBounds::square(4), true).unwrap(),
+            (net_profit, net_profit)
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::grid::{Grid, GridBounds, JsonGrid};
+use crate::position::{
+    get_position_profit, round_stake, CommissionMarkets, JsonPositionBet, PositionBet,
+};
+use crate::rational::Rational;
+use crate::score::Score;
+
+// Stakes (as fractions of the bankroll) are solved to within this
+const STAKE_TOLERANCE: f64 = 1e-10;
+const MAX_SWEEPS: usize = 1000;
+const BISECTION_STEPS: usize = 100;
+// Most times the stakes are optimised again for the markets that now make a profit
+const MAX_COMMISSION_ROUNDS: usize = 10;
+
+#[derive(Serialize)]
+pub struct JsonKellyBet {
+    #[serde(flatten)]
+    pub bet: JsonPositionBet,
+    pub single_bet_stake: f64,
+}
+
+#[derive(Serialize)]
+pub struct JsonKelly {
+    pub bankroll: f64,
+    pub kelly_fraction: f64,
+    pub bets: Vec<JsonKellyBet>,
+    pub expected_log_growth: f64,
+    pub expected_profit: f64,
+    pub profit_grid: JsonGrid,
+}
+
+/// Kelly stakes for bets on the same match: the stakes that maximise the expected log of the
+/// bankroll over the final scores together, so bets that win and lose on the same scores
+/// share one allowance. Commission is taken from the net winnings of each market. Each bet is
+/// compared with its Kelly stake as the only bet.
+pub struct Kelly {
+    pub bankroll: Rational,
+    pub kelly_fraction: f64,
+    pub bets: Vec<PositionBet>,
+    pub single_bet_stakes: Vec<Rational>,
+    pub expected_log_growth: f64,
+    pub expected_profit: f64,
+}
+
+impl Kelly {
+    /// Stakes for `bets` (their stakes are ignored) scaled by `kelly_fraction` (1 for full
+    /// Kelly), for final scores with the given probabilities
+    pub fn new(
+        bets: Vec<PositionBet>,
+        score_probabilities: &[(Score, f64)],
+        bankroll: Rational,
+        kelly_fraction: f64,
+    ) -> Result<Self> {
+        // Profit of each bet before commission for each score, per unit of bankroll staked
+        let unit_gross_profits: Vec<Vec<f64>> = score_probabilities
+            .iter()
+            .map(|(score, _)| {
+                bets.iter()
+                    .map(|bet| {
+                        Ok(Kelly::unit_bet(bet)
+                            .get_gross_profit(score.home_goals, score.away_goals)?
+                            .to_f64())
+                    })
+                    .collect()
+            })
+            .collect::<Result<_>>()?;
+        let probabilities: Vec<f64> = score_probabilities
+            .iter()
+            .map(|(_, probability)| *probability)
+            .collect();
+
+        let fractions =
+            Kelly::optimise_after_commission(&probabilities, &unit_gross_profits, &bets)?;
+        let mut single_bet_stakes = vec![];
+        for (i, bet) in bets.iter().enumerate() {
+            let single_profits: Vec<Vec<f64>> = unit_gross_profits
+                .iter()
+                .map(|profits| vec![profits[i]])
+                .collect();
+            let single = Kelly::optimise_after_commission(
+                &probabilities,
+                &single_profits,
+                std::slice::from_ref(bet),
+            )?;
+            single_bet_stakes.push(round_stake(single[0] * kelly_fraction * bankroll.to_f64())?);
+        }
+
+        let bets: Vec<PositionBet> = bets
+            .into_iter()
+            .zip(&fractions)
+            .map(|(mut bet, fraction)| {
+                bet.exchange_bet.stake =
+                    round_stake(fraction * kelly_fraction * bankroll.to_f64())?;
+                Ok(bet)
+            })
+            .collect::<Result<_>>()?;
+
+        // Growth and profit of the rounded stakes that are actually recommended
+        let mut expected_log_growth = 0.0;
+        let mut expected_profit = 0.0;
+        for (score, probability) in score_probabilities {
+            let profit = get_position_profit(&bets, score.home_goals, score.away_goals)?.to_f64();
+            expected_log_growth += probability * (1.0 + profit / bankroll.to_f64()).ln();
+            expected_profit += probability * profit;
+        }
+
+        Ok(Self {
+            bankroll,
+            kelly_fraction,
+            bets,
+            single_bet_stakes,
+            expected_log_growth,
+            expected_profit,
+        })
+    }
+
+    // The bet for a stake of 1
+    fn unit_bet(bet: &PositionBet) -> PositionBet {
+        let mut unit_bet = bet.clone();
+        unit_bet.exchange_bet.stake = Rational::one();
+        unit_bet
+    }
+
+    // Full Kelly fractions with commission on the net winnings of each market: optimised with
+    // commission taken on the markets in profit on each score, and again until those stay the
+    // same
+    fn optimise_after_commission(
+        probabilities: &[f64],
+        unit_gross_profits: &[Vec<f64>],
+        bets: &[PositionBet],
+    ) -> Result<Vec<f64>> {
+        let commission_markets = CommissionMarkets::new(bets);
+        let mut fractions = vec![0.0; bets.len()];
+        let mut kept_fractions = vec![];
+        for _ in 0..MAX_COMMISSION_ROUNDS {
+            let new_kept_fractions: Vec<Vec<f64>> = unit_gross_profits
+                .iter()
+                .map(|unit_gross_profits| {
+                    let mut gross_profits = vec![0.0; commission_markets.commissions.len()];
+                    for ((fraction, unit_profit), &market) in fractions
+                        .iter()
+                        .zip(unit_gross_profits)
+                        .zip(&commission_markets.bet_markets)
+                    {
+                        gross_profits[market] += fraction * unit_profit;
+                    }
+                    commission_markets.get_kept_fractions(&gross_profits)
+                })
+                .collect();
+            if new_kept_fractions == kept_fractions {
+                break;
+            }
+            kept_fractions = new_kept_fractions;
+
+            let unit_profits: Vec<Vec<f64>> = unit_gross_profits
+                .iter()
+                .zip(&kept_fractions)
+                .map(|(unit_gross_profits, kept)| {
+                    unit_gross_profits
+                        .iter()
+                        .zip(&commission_markets.bet_markets)
+                        .map(|(profit, &market)| profit * kept[market])
+                        .collect()
+                })
+                .collect();
+            fractions = Kelly::optimise(probabilities, &unit_profits, bets)?;
+        }
+        Ok(fractions)
+    }
+
+    // Full Kelly fractions of the bankroll by coordinate ascent: the expected log growth is
+    // concave, so maximising it one stake at a time converges to the joint optimum
+    fn optimise(
+        probabilities: &[f64],
+        unit_profits: &[Vec<f64>],
+        bets: &[PositionBet],
+    ) -> Result<Vec<f64>> {
+        let mut fractions = vec![0.0; bets.len()];
+        // Bankroll left after each score, as a fraction of the starting bankroll
+        let mut wealth = vec![1.0; probabilities.len()];
+
+        for _ in 0..MAX_SWEEPS {
+            let mut largest_change: f64 = 0.0;
+            for (j, bet) in bets.iter().enumerate() {
+                let old_fraction = fractions[j];
+                // Wealth without this bet
+                for (wealth, profits) in wealth.iter_mut().zip(unit_profits) {
+                    *wealth -= old_fraction * profits[j];
+                }
+                let growth_slope = |fraction: f64| -> f64 {
+                    probabilities
+                        .iter()
+                        .zip(unit_profits)
+                        .zip(&wealth)
+                        .map(|((probability, profits), wealth)| {
+                            probability * profits[j] / (wealth + fraction * profits[j])
+                        })
+                        .sum()
+                };
+
+                let fraction = match growth_slope(0.0) > 0.0 {
+                    false => 0.0,
+                    true => {
+                        // The stake that would lose the whole (remaining) bankroll on some score
+                        let ruin = probabilities
+                            .iter()
+                            .zip(unit_profits)
+                            .zip(&wealth)
+                            .filter(|((probability, profits), _)| {
+                                **probability > 0.0 && profits[j] < 0.0
+                            })
+                            .map(|((_, profits), wealth)| wealth / -profits[j])
+                            .fold(f64::INFINITY, f64::min);
+                        if ruin.is_infinite() {
+                            bail!("'{}' can't lose on any score the model allows, so its Kelly stake has no limit.", bet);
+                        }
+                        let (mut low, mut high) = (0.0, ruin);
+                        for _ in 0..BISECTION_STEPS {
+                            let middle = (low + high) / 2.0;
+                            match growth_slope(middle) > 0.0 {
+                                true => low = middle,
+                                false => high = middle,
+                            }
+                        }
+                        low
+                    }
+                };
+
+                for (wealth, profits) in wealth.iter_mut().zip(unit_profits) {
+                    *wealth += fraction * profits[j];
+                }
+                fractions[j] = fraction;
+                largest_change = largest_change.max((fraction - old_fraction).abs());
+            }
+            if largest_change < STAKE_TOLERANCE {
+                break;
+            }
+        }
+        Ok(fractions)
+    }
+
+    pub fn get_profit_grid(&self, bounds: &GridBounds) -> Result<Grid> {
+        let mut grid = Grid::new(bounds);
+        grid.set_title("Profit with the Kelly stakes".to_owned());
+        grid.fill_profits(|home_goals, away_goals| {
+            get_position_profit(&self.bets, home_goals, away_goals)
+        })?;
+        Ok(grid)
+    }
+
+    pub fn to_json(&self, bounds: &GridBounds) -> Result<JsonKelly> {
+        Ok(JsonKelly {
+            bankroll: self.bankroll.to_f64(),
+            kelly_fraction: self.kelly_fraction,
+            bets: self
+                .bets
+                .iter()
+                .zip(&self.single_bet_stakes)
+                .map(|(bet, single_bet_stake)| JsonKellyBet {
+                    bet: bet.to_json(),
+                    single_bet_stake: single_bet_stake.to_f64(),
+                })
+                .collect(),
+            expected_log_growth: self.expected_log_growth,
+            expected_profit: self.expected_profit,
+            profit_grid: self.get_profit_grid(bounds)?.to_json(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probability::PoissonModel;
+
+    fn bet(json: &str) -> PositionBet {
+        PositionBet::from_json(&serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_single_bet_matches_kelly_formula() {
+        // Win probability p at odds b + 1: stake (bp - q) / b of the bankroll
+        let model = PoissonModel::new(1.5, 1.0).unwrap();
+        let probabilities = model.get_score_probabilities(None);
+        let win_probability: f64 = probabilities
+            .iter()
+            .filter(|(score, _)| score.home_goals > score.away_goals)
+            .map(|(_, probability)| probability)
+            .sum();
+        let kelly = Kelly::new(
+            vec![bet(
+                r#"{"bet_type": "win-draw-win", "side": "h", "odds": 2.5}"#,
+            )],
+            &probabilities,
+            Rational::from_integer(1000),
+            1.0,
+        )
+        .unwrap();
+        let expected = (1.5 * win_probability - (1.0 - win_probability)) / 1.5 * 1000.0;
+        assert!((kelly.bets[0].exchange_bet.stake.to_f64() - expected).abs() < 0.01);
+        assert_eq!(kelly.bets[0].exchange_bet.stake, kelly.single_bet_stakes[0]);
+
+        // Commission on the winnings shortens the odds to 1 + 1.5 x 0.95
+        let kelly = Kelly::new(
+            vec![bet(
+                r#"{"bet_type": "win-draw-win", "side": "h", "odds": 2.5, "commission": 0.05}"#,
+            )],
+            &probabilities,
+            Rational::from_integer(1000),
+            1.0,
+        )
+        .unwrap();
+        let net_odds = 1.5 * 0.95;
+        let expected = (net_odds * win_probability - (1.0 - win_probability)) / net_odds * 1000.0;
+        assert!((kelly.bets[0].exchange_bet.stake.to_f64() - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_correlated_bets_share_the_stake() {
+        // The same bet twice is worth no more than once
+        let probabilities = PoissonModel::new(1.8, 0.8)
+            .unwrap()
+            .get_score_probabilities(None);
+        let home =
+            r#"{"bet_type": "asian-handicap", "side": "home", "handicap": -0.5, "odds": 2.2}"#;
+        let kelly = Kelly::new(
+            vec![bet(home), bet(home)],
+            &probabilities,
+            Rational::from_integer(1000),
+            0.5,
+        )
+        .unwrap();
+        let total =
+            kelly.bets[0].exchange_bet.stake.to_f64() + kelly.bets[1].exchange_bet.stake.to_f64();
+        assert!((total - kelly.single_bet_stakes[0].to_f64()).abs() <= 0.02);
+        assert!(kelly.expected_log_growth > 0.0);
+
+        // A bet without an edge gets nothing
+        let kelly = Kelly::new(
+            vec![bet(
+                r#"{"bet_type": "win-draw-win", "side": "a", "odds": 3}"#,
+            )],
+            &probabilities,
+            Rational::from_integer(1000),
+            1.0,
+        )
+        .unwrap();
+        assert_eq!(kelly.bets[0].exchange_bet.stake, Rational::zero());
+    }
+}
@@ -0,0 +1,692 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::position::read_json_file;
+use crate::rational::Rational;
+use crate::score::Score;
+use crate::settlement::{settle_bets, JsonSettleBetSpec, Outcome, SettleBet, Settlement};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// The full-time and (optional) half-time scores a bet was settled on
+type SettledScores = (Score, Option<Score>);
+
+/// A ledger file: every bet recorded, settled or not
+#[derive(Serialize, Deserialize, Default)]
+pub struct JsonLedger {
+    #[serde(default)]
+    pub bets: Vec<JsonLedgerEntry>,
+}
+
+/// A recorded bet: a settle bets file bet, with where and when it was placed and, once the
+/// match is over, its result
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JsonLedgerEntry {
+    pub id: usize,
+    pub date: String,
+    pub event: String,
+    pub bookmaker: String,
+    #[serde(flatten)]
+    pub bet: JsonSettleBetSpec,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<JsonLedgerResult>,
+}
+
+/// The scores a bet was settled on. The outcome and profit are kept for anyone reading the
+/// file, but reports settle the bet again from the scores. The profit is written as a decimal
+/// string rather than a float; it's only rounded when it holds a share of the market's commission.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JsonLedgerResult {
+    pub score: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub half_time_score: Option<String>,
+    pub outcome: String,
+    pub profit: String,
+}
+
+#[derive(Serialize)]
+pub struct JsonLedgerTotals {
+    pub bets: usize,
+    pub staked: f64,
+    pub at_risk: f64,
+    pub profit: f64,
+    #[serde(rename = "yield")]
+    pub yield_: Option<f64>,
+    pub roi: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct JsonLedgerGroup {
+    pub name: String,
+    #[serde(flatten)]
+    pub totals: JsonLedgerTotals,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cumulative_profit: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct JsonLedgerSummary {
+    pub open_bets: usize,
+    pub overall: JsonLedgerTotals,
+    pub by_market_type: Vec<JsonLedgerGroup>,
+    pub by_line: Vec<JsonLedgerGroup>,
+    pub by_bookmaker: Vec<JsonLedgerGroup>,
+    pub by_month: Vec<JsonLedgerGroup>,
+}
+
+/// A recorded bet, read back from the ledger, and how it settled if it has
+pub struct LedgerEntry {
+    pub id: usize,
+    // Always a valid YYYY-MM-DD date, so the month is its first 7 characters
+    pub date: String,
+    pub event: String,
+    pub bookmaker: String,
+    pub bet: SettleBet,
+    pub settlement: Option<Settlement>,
+}
+
+impl LedgerEntry {
+    /// Reads a recorded bet, and the scores it settled on if it has. The bet isn't settled yet,
+    /// as its commission depends on the other bets on the same market.
+    fn from_json(entry: &JsonLedgerEntry) -> Result<(Self, Option<SettledScores>)> {
+        let in_entry = |e: anyhow::Error| anyhow!("Ledger bet #{}: {}", entry.id, e);
+        let date = parse_date(&entry.date).map_err(in_entry)?;
+        let bet = SettleBet::from_json(&entry.bet).map_err(in_entry)?;
+        let result = match &entry.result {
+            None => None,
+            Some(result) => {
+                let full_time = Score::from_str(&result.score).map_err(in_entry)?;
+                let half_time = match &result.half_time_score {
+                    None => None,
+                    Some(half_time) => Some(Score::from_str(half_time).map_err(in_entry)?),
+                };
+                // Settled on its own first, to report a bet that can't be settled by its id
+                bet.settle(full_time, half_time).map_err(in_entry)?;
+                Some((full_time, half_time))
+            }
+        };
+        let entry = Self {
+            id: entry.id,
+            date,
+            event: entry.event.clone(),
+            bookmaker: entry.bookmaker.clone(),
+            bet,
+            settlement: None,
+        };
+        Ok((entry, result))
+    }
+
+    /// Reads recorded bets and settles those with a result. Bets on one match with the same
+    /// bookmaker are settled together, so commission is taken once from the net winnings of
+    /// each market.
+    fn read_all(json_entries: &[JsonLedgerEntry]) -> Result<Vec<Self>> {
+        let (mut entries, results): (Vec<LedgerEntry>, Vec<_>) = json_entries
+            .iter()
+            .map(LedgerEntry::from_json)
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        for i in 0..entries.len() {
+            let (full_time, half_time) = match results[i] {
+                Some(result) if entries[i].settlement.is_none() => result,
+                _ => continue,
+            };
+            let together: Vec<usize> = (i..entries.len())
+                .filter(|&j| {
+                    results[j] == results[i]
+                        && entries[j].event == entries[i].event
+                        && entries[j].bookmaker == entries[i].bookmaker
+                })
+                .collect();
+            let bets: Vec<&SettleBet> = together.iter().map(|&j| &entries[j].bet).collect();
+            let settlements = settle_bets(&bets, full_time, half_time)?;
+            for (j, settlement) in together.into_iter().zip(settlements) {
+                entries[j].settlement = Some(settlement);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Bets recorded in a ledger file
+pub struct Ledger {
+    ledger: JsonLedger,
+}
+
+impl Ledger {
+    /// Reads the ledger at `path`. A ledger that doesn't exist yet is empty if `create` is set.
+    pub fn read(path: &str, create: bool) -> Result<Self> {
+        if create && !Path::new(path).exists() {
+            return Ok(Self {
+                ledger: JsonLedger::default(),
+            });
+        }
+        let ledger = Self {
+            ledger: read_json_file(path)?,
+        };
+        // Catch bets edited by hand into something invalid now, rather than in a report
+        ledger.get_entries()?;
+        Ok(ledger)
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(&self.ledger)?)
+            .map_err(|e| anyhow!("Couldn't write '{}': {}", path, e))
+    }
+
+    pub fn get_entries(&self) -> Result<Vec<LedgerEntry>> {
+        LedgerEntry::read_all(&self.ledger.bets)
+    }
+
+    pub fn get_json_entries(&self) -> &[JsonLedgerEntry] {
+        &self.ledger.bets
+    }
+
+    /// Adds `bets`, placed on `date` with `bookmaker` on the match `event`. Returns them as
+    /// recorded.
+    pub fn record(
+        &mut self,
+        date: &str,
+        event: &str,
+        bookmaker: &str,
+        bets: Vec<JsonSettleBetSpec>,
+    ) -> Result<Vec<LedgerEntry>> {
+        if bets.is_empty() {
+            bail!("There are no bets to record.");
+        }
+        let first_id = self
+            .ledger
+            .bets
+            .iter()
+            .map(|entry| entry.id + 1)
+            .max()
+            .unwrap_or(1);
+        let date = parse_date(date)?;
+        let mut recorded = vec![];
+        for (id, bet) in (first_id..).zip(bets) {
+            let entry = JsonLedgerEntry {
+                id,
+                date: date.clone(),
+                event: event.to_owned(),
+                bookmaker: bookmaker.to_owned(),
+                bet,
+                result: None,
+            };
+            recorded.push(LedgerEntry::from_json(&entry)?.0);
+            self.ledger.bets.push(entry);
+        }
+        Ok(recorded)
+    }
+
+    /// Settles every open bet on `event` with the final (and half-time) score. Returns the
+    /// bets it settled.
+    pub fn settle(
+        &mut self,
+        event: &str,
+        full_time: Score,
+        half_time: Option<Score>,
+    ) -> Result<Vec<LedgerEntry>> {
+        let open: Vec<usize> = (0..self.ledger.bets.len())
+            .filter(|&i| self.ledger.bets[i].event == event && self.ledger.bets[i].result.is_none())
+            .collect();
+        if open.is_empty() {
+            bail!("There are no open bets on '{}' in the ledger.", event);
+        }
+        // Check every bet settles on these scores before changing any
+        for &i in &open {
+            let entry = &self.ledger.bets[i];
+            SettleBet::from_json(&entry.bet)
+                .and_then(|bet| bet.settle(full_time, half_time))
+                .map_err(|e| anyhow!("Ledger bet #{}: {}", entry.id, e))?;
+        }
+        for &i in &open {
+            self.ledger.bets[i].result = Some(JsonLedgerResult {
+                score: full_time.to_string(),
+                half_time_score: half_time.map(|half_time| half_time.to_string()),
+                outcome: String::new(),
+                profit: String::new(),
+            });
+        }
+
+        // Settling more bets on a market can change the commission of those already settled
+        // on it, so every bet on the match is written again
+        let entries = self.get_entries()?;
+        for (json_entry, entry) in self.ledger.bets.iter_mut().zip(&entries) {
+            if let (Some(result), Some(settlement)) = (&mut json_entry.result, &entry.settlement) {
+                if json_entry.event == event {
+                    result.outcome = settlement.outcome.to_string();
+                    result.profit = settlement.profit.to_string();
+                }
+            }
+        }
+        Ok(entries
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| open.contains(i))
+            .map(|(_, entry)| entry)
+            .collect())
+    }
+}
+
+/// Stakes and profit of a set of settled bets. Void bets are counted, but their stakes
+/// aren't, as they were handed back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerTotals {
+    pub bets: usize,
+    pub staked: Rational,
+    pub at_risk: Rational,
+    pub profit: Rational,
+}
+
+impl LedgerTotals {
+    fn new() -> Self {
+        Self {
+            bets: 0,
+            staked: Rational::zero(),
+            at_risk: Rational::zero(),
+            profit: Rational::zero(),
+        }
+    }
+
+    fn add(&mut self, bet: &SettleBet, settlement: &Settlement) -> Result<()> {
+        self.bets += 1;
+        if settlement.outcome != Outcome::Void {
+            self.staked = self.staked.checked_add(bet.exchange_bet.stake)?;
+            self.at_risk = self.at_risk.checked_add(bet.exchange_bet.liability()?)?;
+        }
+        self.profit = self.profit.checked_add(settlement.profit)?;
+        Ok(())
+    }
+
+    /// Profit as a fraction of the stakes
+    pub fn get_yield(&self) -> Option<f64> {
+        match self.staked.is_positive() {
+            true => Some(self.profit.to_f64() / self.staked.to_f64()),
+            false => None,
+        }
+    }
+
+    /// Profit as a fraction of the amount put at risk: the stakes of backs and the
+    /// liabilities of lays
+    pub fn get_roi(&self) -> Option<f64> {
+        match self.at_risk.is_positive() {
+            true => Some(self.profit.to_f64() / self.at_risk.to_f64()),
+            false => None,
+        }
+    }
+
+    fn to_json(&self) -> JsonLedgerTotals {
+        JsonLedgerTotals {
+            bets: self.bets,
+            staked: self.staked.to_f64(),
+            at_risk: self.at_risk.to_f64(),
+            profit: self.profit.to_f64(),
+            yield_: self.get_yield(),
+            roi: self.get_roi(),
+        }
+    }
+}
+
+/// Profit and loss of the settled bets in a ledger, overall and broken down by market type,
+/// line, bookmaker and the month the bets were placed in
+pub struct LedgerSummary {
+    pub open_bets: usize,
+    pub overall: LedgerTotals,
+    pub by_market_type: Vec<(String, LedgerTotals)>,
+    pub by_line: Vec<(String, LedgerTotals)>,
+    pub by_bookmaker: Vec<(String, LedgerTotals)>,
+    pub by_month: Vec<(String, LedgerTotals)>,
+    // Profit up to the end of each month in `by_month`
+    pub cumulative_profits: Vec<Rational>,
+}
+
+impl LedgerSummary {
+    pub fn new(entries: &[LedgerEntry]) -> Result<Self> {
+        let mut summary = Self {
+            open_bets: 0,
+            overall: LedgerTotals::new(),
+            by_market_type: vec![],
+            by_line: vec![],
+            by_bookmaker: vec![],
+            by_month: vec![],
+            cumulative_profits: vec![],
+        };
+        for entry in entries {
+            let settlement = match &entry.settlement {
+                Some(settlement) => settlement,
+                None => {
+                    summary.open_bets += 1;
+                    continue;
+                }
+            };
+            let market_type = entry.bet.market.get_type_name().to_owned();
+            summary.overall.add(&entry.bet, settlement)?;
+            if let Some(line) = entry.bet.market.get_line() {
+                let line = format!("{} {}", market_type, line);
+                LedgerSummary::add_to_group(&mut summary.by_line, line, entry, settlement)?;
+            }
+            LedgerSummary::add_to_group(
+                &mut summary.by_market_type,
+                market_type,
+                entry,
+                settlement,
+            )?;
+            LedgerSummary::add_to_group(
+                &mut summary.by_bookmaker,
+                entry.bookmaker.clone(),
+                entry,
+                settlement,
+            )?;
+            LedgerSummary::add_to_group(
+                &mut summary.by_month,
+                entry.date[..7].to_owned(),
+                entry,
+                settlement,
+            )?;
+        }
+        summary.by_month.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut cumulative_profit = Rational::zero();
+        for (_, totals) in &summary.by_month {
+            cumulative_profit = cumulative_profit.checked_add(totals.profit)?;
+            summary.cumulative_profits.push(cumulative_profit);
+        }
+        Ok(summary)
+    }
+
+    // Groups are kept in the order they first appear in
+    fn add_to_group(
+        groups: &mut Vec<(String, LedgerTotals)>,
+        name: String,
+        entry: &LedgerEntry,
+        settlement: &Settlement,
+    ) -> Result<()> {
+        match groups
+            .iter_mut()
+            .find(|(other_name, _)| *other_name == name)
+        {
+            Some((_, totals)) => totals.add(&entry.bet, settlement)?,
+            None => {
+                let mut totals = LedgerTotals::new();
+                totals.add(&entry.bet, settlement)?;
+                groups.push((name, totals));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print_text(&self) {
+        println!(
+            "{} settled bets, {} open",
+            self.overall.bets, self.open_bets
+        );
+        LedgerSummary::print_table("Overall", &[("All bets".to_owned(), self.overall.clone())]);
+        LedgerSummary::print_table("By market type", &self.by_market_type);
+        LedgerSummary::print_table("By line", &self.by_line);
+        LedgerSummary::print_table("By bookmaker", &self.by_bookmaker);
+        LedgerSummary::print_table("By month", &self.by_month);
+
+        println!();
+        println!("Cumulative profit");
+        for ((month, _), cumulative_profit) in self.by_month.iter().zip(&self.cumulative_profits) {
+            println!("  {:<10} {:>12}", month, cumulative_profit.to_string());
+        }
+    }
+
+    fn print_table(title: &str, groups: &[(String, LedgerTotals)]) {
+        if groups.is_empty() {
+            return;
+        }
+        let as_percentage = |fraction: Option<f64>| match fraction {
+            Some(fraction) => format!("{:.2}%", fraction * 100.0),
+            None => "-".to_owned(),
+        };
+        let name_width = groups
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(title.len());
+        println!();
+        println!(
+            "{:<name_width$} {:>5} {:>12} {:>12} {:>12} {:>9} {:>9}",
+            title, "Bets", "Staked", "At risk", "Profit", "Yield", "ROI"
+        );
+        for (name, totals) in groups {
+            println!(
+                "{:<name_width$} {:>5} {:>12} {:>12} {:>12} {:>9} {:>9}",
+                name,
+                totals.bets,
+                totals.staked.to_string(),
+                totals.at_risk.to_string(),
+                totals.profit.to_string(),
+                as_percentage(totals.get_yield()),
+                as_percentage(totals.get_roi()),
+            );
+        }
+    }
+
+    pub fn to_json(&self) -> JsonLedgerSummary {
+        let groups = |groups: &[(String, LedgerTotals)]| -> Vec<JsonLedgerGroup> {
+            groups
+                .iter()
+                .map(|(name, totals)| JsonLedgerGroup {
+                    name: name.clone(),
+                    totals: totals.to_json(),
+                    cumulative_profit: None,
+                })
+                .collect()
+        };
+        let mut by_month = groups(&self.by_month);
+        for (group, cumulative_profit) in by_month.iter_mut().zip(&self.cumulative_profits) {
+            group.cumulative_profit = Some(cumulative_profit.to_f64());
+        }
+        JsonLedgerSummary {
+            open_bets: self.open_bets,
+            overall: self.overall.to_json(),
+            by_market_type: groups(&self.by_market_type),
+            by_line: groups(&self.by_line),
+            by_bookmaker: groups(&self.by_bookmaker),
+            by_month,
+        }
+    }
+}
+
+/// Checks a date is written YYYY-MM-DD
+pub fn parse_date(input: &str) -> Result<String> {
+    let invalid = || anyhow!("Invalid date '{}'. Dates are written YYYY-MM-DD.", input);
+    let parts: Vec<&str> = input.split('-').collect();
+    if parts.len() != 3
+        || [4, 2, 2].iter().zip(&parts).any(|(length, part)| {
+            part.len() != *length || !part.chars().all(|c| c.is_ascii_digit())
+        })
+    {
+        return Err(invalid());
+    }
+    let year: u32 = parts[0].parse().map_err(|_| invalid())?;
+    let month: u32 = parts[1].parse().map_err(|_| invalid())?;
+    let day: u32 = parts[2].parse().map_err(|_| invalid())?;
+    let is_leap_year =
+        year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year => 29,
+        2 => 28,
+        _ => return Err(invalid()),
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return Err(invalid());
+    }
+    Ok(input.to_owned())
+}
+
+/// Today's date (UTC), YYYY-MM-DD
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    date_from_days((seconds / SECONDS_PER_DAY) as i64)
+}
+
+// The date `days` after 1970-01-01, counting in 400 year cycles of the Gregorian calendar
+// with years starting in March, so the leap day comes last
+fn date_from_days(days: i64) -> String {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Days from 1970-01-01 to a date written YYYY-MM-DD, the inverse of `date_from_days`
+pub fn days_from_date(date: &str) -> Result<i64> {
+    let date = parse_date(date)?;
+    let year: i64 = date[..4].parse()?;
+    let month: i64 = date[5..7].parse()?;
+    let day: i64 = date[8..].parse()?;
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Ok(era * 146_097 + day_of_era - 719_468)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bet(json: &str) -> JsonSettleBetSpec {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_records_settles_and_summarises() {
+        let mut ledger = Ledger::read("/nonexistent/ledger.json", true).unwrap();
+        ledger
+            .record(
+                "2026-09-12",
+                "Arsenal v Chelsea",
+                "Pinnacle",
+                vec![
+                    bet(r#"{"bet_type": "asian-handicap", "side": "home", "handicap": -0.25, "odds": 2, "stake": 100}"#),
+                    bet(r#"{"bet_type": "over-under", "side": "over", "goals": 2.5, "odds": 1.9, "stake": 50}"#),
+                ],
+            )
+            .unwrap();
+        ledger
+            .record(
+                "2026-10-03",
+                "Leeds v Everton",
+                "Betfair",
+                vec![bet(r#"{"bet_type": "win-draw-win", "side": "d", "lay": true, "odds": 3.5, "stake": 20}"#)],
+            )
+            .unwrap();
+        assert!(ledger
+            .record(
+                "12/10/2026",
+                "Spurs v Fulham",
+                "Betfair",
+                vec![bet(r#"{"bet_type": "win-draw-win", "side": "h"}"#)],
+            )
+            .is_err());
+
+        let settled = ledger
+            .settle("Arsenal v Chelsea", Score::new(1, 1), None)
+            .unwrap();
+        assert_eq!(
+            settled[0].settlement.as_ref().unwrap().outcome,
+            Outcome::HalfLoss
+        );
+        assert!(ledger
+            .settle("Arsenal v Chelsea", Score::new(1, 1), None)
+            .is_err());
+
+        let entries = ledger.get_entries().unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        let summary = LedgerSummary::new(&entries).unwrap();
+        assert_eq!(summary.open_bets, 1);
+        assert_eq!(
+            summary.overall,
+            LedgerTotals {
+                bets: 2,
+                staked: Rational::from_integer(150),
+                at_risk: Rational::from_integer(150),
+                profit: Rational::from_integer(-100),
+            }
+        );
+        assert_eq!(summary.overall.get_yield(), Some(-100.0 / 150.0));
+        assert_eq!(summary.by_line[0].0, "Asian Handicap -0.25");
+        assert_eq!(summary.by_bookmaker.len(), 1);
+        assert_eq!(summary.by_month[0].0, "2026-09");
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(date_from_days(0), "1970-01-01");
+        assert_eq!(date_from_days(11_016), "2000-02-29");
+        assert_eq!(date_from_days(20_745), "2026-10-19");
+        for days in [0, 11_016, 20_745, -1] {
+            assert_eq!(days_from_date(&date_from_days(days)).unwrap(), days);
+        }
+        assert!(parse_date("2026-10-19").is_ok());
+        assert!(parse_date("2026-13-01").is_err());
+        assert!(parse_date("26-10-19").is_err());
+        // Days past the end of the month, with leap days only in leap years
+        assert!(parse_date("2024-02-29").is_ok());
+        assert!(parse_date("2000-02-29").is_ok());
+        assert!(parse_date("2025-02-29").is_err());
+        assert!(parse_date("1900-02-29").is_err());
+        assert!(parse_date("2026-02-31").is_err());
+        assert!(parse_date("2026-04-31").is_err());
+        assert!(days_from_date("2026-02-31").is_err());
+    }
+
+    #[test]
+    fn test_rejects_invalid_dates_and_keeps_exact_profits() {
+        let mut ledger = Ledger::read("/nonexistent/ledger.json", true).unwrap();
+        ledger
+            .record(
+                "2026-09-12",
+                "Arsenal v Chelsea",
+                "Pinnacle",
+                vec![bet(
+                    r#"{"bet_type": "win-draw-win", "side": "h", "odds": 2.02, "stake": 10.1}"#,
+                )],
+            )
+            .unwrap();
+        ledger
+            .settle("Arsenal v Chelsea", Score::new(2, 0), None)
+            .unwrap();
+        let json = serde_json::to_string(&ledger.ledger).unwrap();
+        assert!(json.contains(r#""profit":"10.302""#));
+
+        // A date edited by hand to something short or multi-byte is reported, not sliced
+        for date in ["2026", "2026-9-1", "2026-0é-01"] {
+            let edited = json.replace("2026-09-12", date);
+            let ledger = Ledger {
+                ledger: serde_json::from_str(&edited).unwrap(),
+            };
+            assert!(ledger.get_entries().is_err());
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::grid::{Grid, GridBounds, JsonGrid};
+use crate::position::{JsonPositionBet, PositionBet};
+use crate::rational::Rational;
+use crate::score::Score;
+
+#[derive(Serialize)]
+pub struct JsonMarketTurnover {
+    pub market: String,
+    pub bets: usize,
+    pub turnover: f64,
+}
+
+#[derive(Serialize)]
+pub struct JsonWorstCase {
+    pub home_goals: usize,
+    pub away_goals: usize,
+    pub profit: f64,
+}
+
+#[derive(Serialize)]
+pub struct JsonLiability {
+    pub bets: Vec<JsonPositionBet>,
+    pub turnover: f64,
+    pub turnover_by_market: Vec<JsonMarketTurnover>,
+    pub worst_case: JsonWorstCase,
+    pub liability_grid: JsonGrid,
+}
+
+/// A bookmaker's book on one match: the customers' bets. Whatever a customer wins the
+/// bookmaker loses, so the bookmaker's result for each final score is the negated sum of
+/// the customers' profits.
+pub struct Book {
+    pub bets: Vec<PositionBet>,
+}
+
+impl Book {
+    pub fn new(bets: Vec<PositionBet>) -> Self {
+        Self { bets }
+    }
+
+    /// The bookmaker's profit (negative for a payout) if the match finishes
+    /// `home_goals`-`away_goals`. Each bet is a different customer's, so they aren't netted
+    /// against each other before commission.
+    pub fn get_bookmaker_profit(&self, home_goals: usize, away_goals: usize) -> Result<Rational> {
+        let profits = self
+            .bets
+            .iter()
+            .map(|bet| bet.get_profit(home_goals, away_goals))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(-Rational::checked_sum(profits)?)
+    }
+
+    /// Total staked by the customers
+    pub fn get_turnover(&self) -> Result<Rational> {
+        Rational::checked_sum(self.bets.iter().map(|bet| bet.exchange_bet.stake))
+    }
+
+    /// Number of bets and the amount staked on each market, in the order the markets first
+    /// appear in the book
+    pub fn get_turnover_by_market(&self) -> Result<Vec<(String, usize, Rational)>> {
+        let mut turnover_by_market: Vec<(String, usize, Rational)> = vec![];
+        for bet in &self.bets {
+            let market = bet.market.to_string();
+            match turnover_by_market
+                .iter_mut()
+                .find(|(other_market, _, _)| *other_market == market)
+            {
+                Some((_, bets, turnover)) => {
+                    *bets += 1;
+                    *turnover = turnover.checked_add(bet.exchange_bet.stake)?;
+                }
+                None => turnover_by_market.push((market, 1, bet.exchange_bet.stake)),
+            }
+        }
+        Ok(turnover_by_market)
+    }
+
+    /// The final score (over the scores in the grid) the bookmaker does worst on, and its
+    /// profit there
+    pub fn get_worst_case(&self, bounds: &GridBounds) -> Result<(Score, Rational)> {
+        let mut worst: Option<(Score, Rational)> = None;
+        for (home_goals, away_goals) in bounds.scores() {
+            let profit = self.get_bookmaker_profit(home_goals, away_goals)?;
+            match worst {
+                Some((_, worst_profit)) if worst_profit <= profit => {}
+                _ => worst = Some((Score::new(home_goals, away_goals), profit)),
+            }
+        }
+        Ok(worst.unwrap_or((Score::new(0, 0), Rational::zero())))
+    }
+
+    pub fn get_liability_grid(&self, bounds: &GridBounds) -> Result<Grid> {
+        let mut grid = Grid::new(bounds);
+        grid.set_title("Bookmaker profit (negative is a payout)".to_owned());
+        grid.fill_profits(|home_goals, away_goals| {
+            self.get_bookmaker_profit(home_goals, away_goals)
+        })?;
+        Ok(grid)
+    }
+
+    pub fn to_json(&self, bounds: &GridBounds) -> Result<JsonLiability> {
+        let (worst_score, worst_profit) = self.get_worst_case(bounds)?;
+        Ok(JsonLiability {
+            bets: self.bets.iter().map(PositionBet::to_json).collect(),
+            turnover: self.get_turnover()?.to_f64(),
+            turnover_by_market: self
+                .get_turnover_by_market()?
+                .into_iter()
+                .map(|(market, bets, turnover)| JsonMarketTurnover {
+                    market,
+                    bets,
+                    turnover: turnover.to_f64(),
+                })
+                .collect(),
+            worst_case: JsonWorstCase {
+                home_goals: worst_score.home_goals,
+                away_goals: worst_score.away_goals,
+                profit: worst_profit.to_f64(),
+            },
+            liability_grid: self.get_liability_grid(bounds)?.to_json(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bet(json: &str) -> PositionBet {
+        PositionBet::from_json(&serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_book_is_the_negated_customer_position() {
+        let home = r#"{"bet_type": "win-draw-win", "side": "h", "odds": 2, "stake": 100}"#;
+        let book = Book::new(vec![
+            bet(home),
+            bet(r#"{"bet_type": "win-draw-win", "side": "d", "odds": 3.5, "stake": 50}"#),
+            bet(home),
+        ]);
+        assert_eq!(
+            book.get_bookmaker_profit(0, 0).unwrap(),
+            Rational::from_integer(75)
+        );
+        assert_eq!(
+            book.get_bookmaker_profit(0, 1).unwrap(),
+            Rational::from_integer(250)
+        );
+        assert_eq!(book.get_turnover().unwrap(), Rational::from_integer(250));
+        assert_eq!(
+            book.get_turnover_by_market().unwrap(),
+            vec![
+                (
+                    "Win-Draw-Win: Home".to_owned(),
+                    2,
+                    Rational::from_integer(200)
+                ),
+                (
+                    "Win-Draw-Win: Draw".to_owned(),
+                    1,
+                    Rational::from_integer(50)
+                ),
+            ]
+        );
+        // The first home win in the grid
+        assert_eq!(
+            book.get_worst_case(&GridBounds::square(5)).unwrap(),
+            (Score::new(1, 0), Rational::from_integer(-150))
+        );
+    }
+}
@@ -0,0 +1,198 @@
+// Tolerance for treating tableau entries as zero
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, PartialEq)]
+pub enum LinearProgramSolution {
+    Optimal { x: Vec<f64>, value: f64 },
+    Infeasible,
+    Unbounded,
+}
+
+/// Maximises `c`·x subject to `a` x <= `b` and x >= 0 with the two-phase simplex method.
+/// Bland's rule picks the pivots, so it can't cycle on degenerate problems.
+pub fn maximise(c: &[f64], a: &[Vec<f64>], b: &[f64]) -> LinearProgramSolution {
+    let (rows, variables) = (a.len(), c.len());
+    // Columns: the variables, a slack per constraint, then the phase one auxiliary variable
+    let auxiliary = variables + rows;
+    let mut tableau = Tableau {
+        rows: a
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let mut tableau_row = row.clone();
+                tableau_row.extend((0..rows).map(|j| if i == j { 1.0 } else { 0.0 }));
+                tableau_row.push(-1.0);
+                tableau_row
+            })
+            .collect(),
+        rhs: b.to_vec(),
+        basis: (variables..auxiliary).collect(),
+        objective: vec![0.0; auxiliary + 1],
+        value: 0.0,
+        blocked: None,
+    };
+
+    // Phase one: with some constraints not met at the origin, find a feasible basis by
+    // minimising the auxiliary variable that relaxes all of them
+    let most_violated = (0..rows)
+        .filter(|&i| b[i] < 0.0)
+        .min_by(|&i, &j| b[i].total_cmp(&b[j]));
+    if let Some(row) = most_violated {
+        tableau.objective[auxiliary] = -1.0;
+        tableau.pivot(row, auxiliary);
+        tableau.optimise();
+        if tableau.value < -EPSILON {
+            return LinearProgramSolution::Infeasible;
+        }
+        // A degenerate phase one can leave the auxiliary variable basic at zero
+        if let Some(row) = tableau.basis.iter().position(|&j| j == auxiliary) {
+            if let Some(column) = (0..auxiliary).find(|&j| tableau.rows[row][j].abs() > EPSILON) {
+                tableau.pivot(row, column);
+            }
+        }
+    }
+    tableau.blocked = Some(auxiliary);
+
+    // Phase two: the real objective, in terms of the current non-basic variables
+    tableau.objective = vec![0.0; auxiliary + 1];
+    tableau.objective[..variables].copy_from_slice(c);
+    tableau.value = 0.0;
+    for row in 0..rows {
+        let cost = match tableau.basis[row] {
+            j if j < variables => c[j],
+            _ => continue,
+        };
+        for j in 0..=auxiliary {
+            tableau.objective[j] -= cost * tableau.rows[row][j];
+        }
+        tableau.value += cost * tableau.rhs[row];
+    }
+    if !tableau.optimise() {
+        return LinearProgramSolution::Unbounded;
+    }
+
+    let mut x = vec![0.0; variables];
+    for (row, &j) in tableau.basis.iter().enumerate() {
+        if j < variables {
+            x[j] = tableau.rhs[row];
+        }
+    }
+    LinearProgramSolution::Optimal {
+        x,
+        value: tableau.value,
+    }
+}
+
+// The objective is `value` plus `objective`·x over the non-basic variables
+struct Tableau {
+    rows: Vec<Vec<f64>>,
+    rhs: Vec<f64>,
+    basis: Vec<usize>,
+    objective: Vec<f64>,
+    value: f64,
+    // Column that may not enter the basis (the auxiliary variable in phase two)
+    blocked: Option<usize>,
+}
+
+impl Tableau {
+    // Pivots until no variable improves the objective. False if it is unbounded.
+    fn optimise(&mut self) -> bool {
+        loop {
+            let entering = (0..self.objective.len())
+                .find(|&j| Some(j) != self.blocked && self.objective[j] > EPSILON);
+            let column = match entering {
+                Some(column) => column,
+                None => return true,
+            };
+            let leaving = (0..self.rows.len())
+                .filter(|&i| self.rows[i][column] > EPSILON)
+                .min_by(|&i, &j| {
+                    let ratio_i = self.rhs[i] / self.rows[i][column];
+                    let ratio_j = self.rhs[j] / self.rows[j][column];
+                    ratio_i
+                        .total_cmp(&ratio_j)
+                        .then(self.basis[i].cmp(&self.basis[j]))
+                });
+            match leaving {
+                Some(row) => self.pivot(row, column),
+                None => return false,
+            }
+        }
+    }
+
+    fn pivot(&mut self, row: usize, column: usize) {
+        let pivot = self.rows[row][column];
+        for entry in self.rows[row].iter_mut() {
+            *entry /= pivot;
+        }
+        self.rhs[row] /= pivot;
+
+        let pivot_row = self.rows[row].clone();
+        let pivot_rhs = self.rhs[row];
+        for i in 0..self.rows.len() {
+            let factor = self.rows[i][column];
+            if i == row || factor == 0.0 {
+                continue;
+            }
+            for (entry, pivot_entry) in self.rows[i].iter_mut().zip(&pivot_row) {
+                *entry -= factor * pivot_entry;
+            }
+            self.rhs[i] -= factor * pivot_rhs;
+        }
+        let factor = self.objective[column];
+        for (entry, pivot_entry) in self.objective.iter_mut().zip(&pivot_row) {
+            *entry -= factor * pivot_entry;
+        }
+        self.value += factor * pivot_rhs;
+        self.basis[row] = column;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_optimal(solution: LinearProgramSolution, expected_x: &[f64], expected_value: f64) {
+        match solution {
+            LinearProgramSolution::Optimal { x, value } => {
+                assert!((value - expected_value).abs() < 1e-9, "Got value {}", value);
+                for (actual, expected) in x.iter().zip(expected_x) {
+                    assert!((actual - expected).abs() < 1e-9, "Got x {:?}", x);
+                }
+            }
+            other => panic!("Expected an optimal solution, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_maximises_from_the_origin() {
+        // max 3x + 2y, x + y <= 4, x + 3y <= 6, x <= 3
+        let a = vec![vec![1.0, 1.0], vec![1.0, 3.0], vec![1.0, 0.0]];
+        assert_optimal(
+            maximise(&[3.0, 2.0], &a, &[4.0, 6.0, 3.0]),
+            &[3.0, 1.0],
+            11.0,
+        );
+    }
+
+    #[test]
+    fn test_needs_phase_one() {
+        // min x + y (max -x - y), x + y >= 2, x - y <= 1
+        let a = vec![vec![-1.0, -1.0], vec![1.0, -1.0]];
+        match maximise(&[-1.0, -1.0], &a, &[-2.0, 1.0]) {
+            LinearProgramSolution::Optimal { value, .. } => assert!((value + 2.0).abs() < 1e-9),
+            other => panic!("Expected an optimal solution, got {:?}", other),
+        }
+
+        // x <= 1 and x >= 2
+        let a = vec![vec![1.0], vec![-1.0]];
+        assert_eq!(
+            maximise(&[1.0], &a, &[1.0, -2.0]),
+            LinearProgramSolution::Infeasible
+        );
+        assert_eq!(
+            maximise(&[1.0, 0.0], &[vec![-1.0, 1.0]], &[1.0]),
+            LinearProgramSolution::Unbounded
+        );
+    }
+}
@@ -1,44 +1,368 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
+mod arbitrage;
+mod backtest;
 mod bet_grid_handler;
 mod bet_types;
+mod calibration;
+mod cash_out;
 mod config;
 mod error;
+mod exchange;
+mod goal_sequence;
 mod grid;
+mod hedge;
+mod kelly;
+mod ledger;
+mod liability;
+mod linear_program;
 mod market_specifiers;
+mod position;
+mod probability;
+mod ratings;
+mod rational;
+mod results;
+mod score;
+mod settlement;
+mod simulation;
 
 fn main() {
+    let ledger_arg = || {
+        Arg::new("ledger")
+            .long("ledger")
+            .required(true)
+            .help("Json ledger file. Recording bets creates it if it doesn't exist.")
+    };
     let matches = Command::new("Football Betting Payoff Grid")
         .version("1.0")
         .author("Anton 'Gw1p' Bendrikov")
         .about("CLI tool that displays payoff grids for football bet types")
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("bet_type")
             .long("bet-type")
             .required(true)
-            .help("The type of bet (e.g. win-draw-win, asian-handicap, over-under)")
+            .help("The type of bet (e.g. win-draw-win, asian-handicap, over-under, ht-ft, first-to-score, last-to-score, race-to, score-both-halves, to-qualify (or its alias to-lift-trophy), aggregate-winner, aggregate-handicap, aggregate-total)")
         )
         .arg(Arg::new("output")
             .long("output")
-            .help("Output format (json, text). Defaults to text.")
+            .global(true)
+            .help("Output format (json, text, or outcomes: text with each payoff grid cell showing the outcome of the bet, W, ½W, P, ½L, L or V). Defaults to text.")
         )
         .arg(Arg::new("grid_size")
             .long("grid_size")
+            .global(true)
             .help("Grid size (numeric). Defaults to 10 (starting from 0).")
         )
+        .arg(Arg::new("min_home_goals")
+            .long("min-home-goals")
+            .global(true)
+            .help("Smallest home goals shown in the grid. Defaults to 0, or the current score's home goals in play.")
+        )
+        .arg(Arg::new("max_home_goals")
+            .long("max-home-goals")
+            .global(true)
+            .help("Largest home goals shown in the grid. Defaults to grid size - 1, counted from the current score in play.")
+        )
+        .arg(Arg::new("min_away_goals")
+            .long("min-away-goals")
+            .global(true)
+            .help("Smallest away goals shown in the grid. Defaults to 0, or the current score's away goals in play.")
+        )
+        .arg(Arg::new("max_away_goals")
+            .long("max-away-goals")
+            .global(true)
+            .help("Largest away goals shown in the grid. Defaults to grid size - 1, counted from the current score in play.")
+        )
+        .arg(Arg::new("tail")
+            .long("tail")
+            .global(true)
+            .action(ArgAction::SetTrue)
+            .help("Turn the last row and column into 'N+' buckets for every score at or beyond the grid edge.")
+        )
         .arg(
             Arg::new("side")
             .long("side")
-            .help("Bet type 'side'. For win-draw-win, this is 'home', 'away', 'draw'. For overs/unders this is 'over' and 'under'.")
+            .help("Bet type 'side'. For win-draw-win, this is 'home', 'away', 'draw'. For overs/unders this is 'over' and 'under'. For ht-ft this is the half-time then full-time result, e.g. 'draw/home' ('d/h').")
         )
         .arg(Arg::new("handicap")
             .long("handicap")
             .help("Handicap for Asian Handicap bets (for example, -1.5).")
         ).arg(Arg::new("goals")
             .long("goals")
-            .help("Goals for Over Under bets (for example, 1 or 1.5).")
+            .help("Goals for Over Under bets (for example, 1 or 1.5), or the target for race-to bets (for example, 2).")
+        ).arg(Arg::new("goal_sequence")
+            .long("goal-sequence")
+            .help("Goals in the order they were scored, to settle goal sequence bets (for example, 'H23, A45+2, H81', or 'none'). Minutes are optional except for score-both-halves.")
+        ).arg(Arg::new("first_leg_score")
+            .long("first-leg-score")
+            .help("First-leg score of a two-legged tie for aggregate bets, as played (for example, 2-1): the first-leg home team, who are the away team in the second leg, first. The grid is over second-leg scores.")
+        ).arg(Arg::new("away_goals_rule")
+            .long("away-goals-rule")
+            .action(ArgAction::SetTrue)
+            .help("Break aggregate ties on away goals (including a score draw in extra time).")
+        ).arg(Arg::new("extra_time_score")
+            .long("extra-time-score")
+            .help("Goals scored in extra time (for example, 1-0), to settle to-qualify bets on ties that were level after 90 minutes.")
+        ).arg(Arg::new("penalty_winner")
+            .long("penalty-winner")
+            .help("Winner of the penalty shootout ('home' or 'away') if extra time was level too.")
+        ).arg(Arg::new("lay")
+            .long("lay")
+            .action(ArgAction::SetTrue)
+            .help("Lay the side on an exchange instead of backing it. The grid shows what the layer receives.")
+        ).arg(Arg::new("odds")
+            .long("odds")
+            .help("Decimal odds the bet was struck at (for example, 2.02). Defaults to 2 (even money).")
+        ).arg(Arg::new("stake")
+            .long("stake")
+            .help("Backer's stake (for lays too, so the liability is stake x (odds - 1)). Defaults to 1.")
+        ).arg(Arg::new("commission")
+            .long("commission")
+            .help("Exchange commission on net winnings as a fraction (for example, 0.02 for 2%). Defaults to 0.")
+        ).arg(Arg::new("period")
+            .long("period")
+            .help("Period the bet settles on: 'full-time', 'first-half' or 'second-half' ('ft', '1h', '2h'). Defaults to full time. The grid (and current score) are then goals scored in that period.")
+        ).arg(Arg::new("half_time_score")
+            .long("half-time-score")
+            .help("Half-time score of a second-half bet (for example, 1-0). The grid is then over final scores, with the bet settled on the goals scored after half time.")
+        ).arg(Arg::new("current_score")
+            .long("current-score")
+            .global(true)
+            .help("Current score of a match in play (for example, 1-0). Scores below it are greyed out, and Asian Handicap bets are settled by in-running rules, counting only goals scored from now on.")
+        ).arg(Arg::new("minutes_remaining")
+            .long("minutes-remaining")
+            .global(true)
+            .help("Minutes left in a match in play, for the probability model (for example, 30).")
+        ).arg(Arg::new("home_xg")
+            .long("home-xg")
+            .global(true)
+            .help("Home team expected goals over a full match. Together with 'away-xg' adds final score probabilities and the expected value.")
+        ).arg(Arg::new("away_xg")
+            .long("away-xg")
+            .global(true)
+            .help("Away team expected goals over a full match. Together with 'home-xg' adds final score probabilities and the expected value.")
+        ).arg(Arg::new("rho")
+            .long("rho")
+            .global(true)
+            .allow_hyphen_values(true)
+            .help("Dixon-Coles correction for the probability model, which moves probability between 0-0, 1-0, 0-1 and 1-1 (for example, -0.1 makes low draws more likely). Defaults to 0, independent goals.")
+        ).arg(Arg::new("ratings")
+            .long("ratings")
+            .global(true)
+            .help("Ratings file saved by the 'ratings' command. Instead of 'home-xg' and 'away-xg', the probability model then comes from the ratings of 'home-team' and 'away-team' (and their rho, unless 'rho' is passed).")
+        ).arg(Arg::new("home_team")
+            .long("home-team")
+            .global(true)
+            .help("Home team, named as in the results the ratings were fitted to.")
+        ).arg(Arg::new("away_team")
+            .long("away-team")
+            .global(true)
+            .help("Away team, named as in the results the ratings were fitted to.")
+        ).subcommand(Command::new("hedge")
+            .about("Finds the stakes on the given hedge markets that flatten a position over the scores in the grid, and shows the net grid")
+            .arg(Arg::new("position")
+                .long("position")
+                .required(true)
+                .help("Json file with the 'position' (a list of bets, each with bet_type, side, handicap or goals, odds, stake, and optionally lay and commission) and the 'hedges' (bets without a stake, optionally with a max_stake).")
+            ).arg(Arg::new("objective")
+                .long("objective")
+                .help("What the hedge should achieve: 'worst-case' to make the worst net profit as high as possible, or 'equalise' to make the net profit as even as possible ('w', 'e'). Defaults to worst-case.")
+            )
+        ).subcommand(Command::new("arbitrage")
+            .about("Searches prices quoted on one match for stakes that can't lose: an arbitrage (a profit on every score) or a middle (no loss anywhere, a profit on some scores)")
+            .arg(Arg::new("quotes")
+                .long("quotes")
+                .required(true)
+                .help("Json file with the 'quotes': bets without a stake (bet_type, side, handicap or goals, odds, and optionally lay and commission), each with the 'bookmaker' offering it.")
+            ).arg(Arg::new("total_stake")
+                .long("total-stake")
+                .help("Total to stake across the quotes, counting the liability of lays. Defaults to 100.")
+            )
+        ).subcommand(Command::new("kelly")
+            .about("Kelly stakes for bets on one match, taking into account that they win and lose on the same scores. Needs 'home-xg' and 'away-xg'.")
+            .arg(Arg::new("bets")
+                .long("bets")
+                .required(true)
+                .help("Json file with the candidate 'bets' (bet_type, side, handicap or goals, odds, and optionally lay and commission). Stakes are ignored.")
+            ).arg(Arg::new("bankroll")
+                .long("bankroll")
+                .help("Bankroll to stake from. Defaults to 100.")
+            ).arg(Arg::new("kelly_fraction")
+                .long("kelly-fraction")
+                .help("Fraction of the full Kelly stakes to bet (for example, 0.5 for half Kelly). Defaults to 1.")
+            )
+        ).subcommand(Command::new("cash-out")
+            .about("Fair cash-out value of open bets on one match at the current score, and how an offered cash-out compares. Needs 'home-xg' and 'away-xg'.")
+            .arg(Arg::new("bets")
+                .long("bets")
+                .required(true)
+                .help("Json file with the open 'bets' (bet_type, side, handicap or goals, odds, stake, and optionally lay and commission).")
+            ).arg(Arg::new("offer")
+                .long("offer")
+                .help("Cash-out amount offered by the bookmaker, to compare with the fair value.")
+            )
+        ).subcommand(Command::new("liability")
+            .about("The bookmaker's side of a book of customer bets on one match: profit or payout on each score, the worst-case score and the turnover")
+            .arg(Arg::new("bets")
+                .long("bets")
+                .required(true)
+                .help("Json file with the customers' 'bets' (bet_type, side, handicap or goals, odds and stake).")
+            )
+        ).subcommand(Command::new("settle")
+            .about("Settles bets on the final score (and the half-time score): the outcome of each bet, what it returns and its profit")
+            .arg(Arg::new("bets")
+                .long("bets")
+                .required(true)
+                .help("Json file with the 'bets' (bet_type, side, handicap or goals, odds, stake, and optionally lay, commission, period and void). 'ht-ft' bets are supported too.")
+            ).arg(Arg::new("score")
+                .long("score")
+                .required(true)
+                .help("Final score, e.g. 2-1.")
+            ).arg(Arg::new("half_time_score")
+                .long("half-time-score")
+                .help("Half-time score, e.g. 1-0. Needed for first-half, second-half and half-time/full-time bets.")
+            )
+        ).subcommand(Command::new("ledger")
+            .about("Records bets in a ledger file, settles them and reports profit and loss")
+            .subcommand_required(true)
+            .subcommand(Command::new("record")
+                .about("Records the bets of a bets file, placed with a bookmaker on a match")
+                .arg(ledger_arg())
+                .arg(Arg::new("bets")
+                    .long("bets")
+                    .required(true)
+                    .help("Json file with the 'bets' (as for 'settle': bet_type, side, handicap or goals, odds, stake, and optionally lay, commission and period).")
+                ).arg(Arg::new("event")
+                    .long("event")
+                    .required(true)
+                    .help("The match the bets are on, e.g. 'Arsenal v Chelsea'. Bets are settled by event.")
+                ).arg(Arg::new("bookmaker")
+                    .long("bookmaker")
+                    .required(true)
+                    .help("Bookmaker or exchange the bets were placed with.")
+                ).arg(Arg::new("date")
+                    .long("date")
+                    .help("Date the bets were placed, YYYY-MM-DD. Defaults to today.")
+                )
+            ).subcommand(Command::new("list")
+                .about("Lists the bets in the ledger")
+                .arg(ledger_arg())
+                .arg(Arg::new("event")
+                    .long("event")
+                    .help("Only list bets on this match.")
+                ).arg(Arg::new("open")
+                    .long("open")
+                    .action(ArgAction::SetTrue)
+                    .help("Only list bets that haven't been settled.")
+                )
+            ).subcommand(Command::new("settle")
+                .about("Settles the open bets on a match with its result")
+                .arg(ledger_arg())
+                .arg(Arg::new("event")
+                    .long("event")
+                    .required(true)
+                    .help("The match to settle, as recorded.")
+                ).arg(Arg::new("score")
+                    .long("score")
+                    .required(true)
+                    .help("Final score, e.g. 2-1.")
+                ).arg(Arg::new("half_time_score")
+                    .long("half-time-score")
+                    .help("Half-time score, e.g. 1-0. Needed for first-half, second-half and half-time/full-time bets.")
+                )
+            ).subcommand(Command::new("summary")
+                .about("Profit and loss of the settled bets, overall and by market type, line, bookmaker and month, with yield and ROI")
+                .arg(ledger_arg())
+            )
+        ).subcommand(Command::new("backtest")
+            .about("Runs betting strategies over historical matches: backs the side of a market with the best expected value under the model at the pre-closing odds, settles the bets on the results, and reports profit, ROI, hit rate, drawdown and closing-line value")
+            .arg(Arg::new("results")
+                .long("results")
+                .required(true)
+                .help("CSV file of matches in the football-data.co.uk layout: HomeTeam, AwayTeam, FTHG, FTAG, optionally Date, HTHG and HTAG, the odds (e.g. PSH, PSCH, AHh, PAHH, P>2.5), and the model's pre-match expected goals in HXG and AXG (with 'rho' for a Dixon-Coles model).")
+            ).arg(Arg::new("strategy")
+                .long("strategy")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .help("Market to bet on, optionally with one side of it: win-draw-win, asian-handicap or over-under ('1x2', 'ah', 'ou'), e.g. 'asian-handicap:home'. Can be repeated or comma-separated. Defaults to all three markets.")
+            ).arg(Arg::new("min_ev")
+                .long("min-ev")
+                .help("Expected profit per unit staked a bet needs under the model (for example, 0.03 for 3%). Defaults to 0.03.")
+            ).arg(Arg::new("stake")
+                .long("stake")
+                .help("Stake of each bet. Defaults to 1.")
+            ).arg(Arg::new("odds")
+                .long("odds")
+                .help("Whose odds to bet at and compare with at the close: pinnacle, bet365, max or avg. Defaults to pinnacle.")
+            )
+        ).subcommand(Command::new("calibrate")
+            .about("How well the model's probabilities predict historical results: log loss, Brier score and ranked probability score of its 1X2 probabilities, and calibration tables for Asian handicap and over/under lines")
+            .arg(Arg::new("results")
+                .long("results")
+                .required(true)
+                .help("CSV file of matches in the football-data.co.uk layout: HomeTeam, AwayTeam, FTHG and FTAG, the model's pre-match expected goals in HXG and AXG (with 'rho' for a Dixon-Coles model), and optionally the Asian handicap line in AHh.")
+            ).arg(Arg::new("handicaps")
+                .long("handicaps")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .allow_hyphen_values(true)
+                .help("Home handicaps to calibrate, e.g. -0.5,0,0.5. Defaults to the line of each match, from the AHh column.")
+            ).arg(Arg::new("goals")
+                .long("goals")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .help("Goal lines to calibrate the over side of, e.g. 2.5,3. Defaults to 1.5,2.5,3.5.")
+            )
+        ).subcommand(Command::new("ratings")
+            .about("Fits each team's attack and defence and the home advantage to past results, as a Poisson model (or Dixon-Coles, with its rho), optionally counting recent matches more, and saves them for predicting the expected goals of any fixture")
+            .arg(Arg::new("results")
+                .long("results")
+                .required(true)
+                .help("CSV file of matches in the football-data.co.uk layout: HomeTeam, AwayTeam, FTHG and FTAG, and Date for time decay.")
+            ).arg(Arg::new("half_life")
+                .long("half-life")
+                .help("Days after which a match counts half as much as one played on the date of the ratings (for example, 180). Defaults to every match counting the same.")
+            ).arg(Arg::new("date")
+                .long("date")
+                .help("Date to fit the ratings on (YYYY-MM-DD), leaving out later matches. Defaults to the date of the last match.")
+            ).arg(Arg::new("dixon_coles")
+                .long("dixon-coles")
+                .action(ArgAction::SetTrue)
+                .help("Fit the Dixon-Coles rho too, which corrects the probabilities of 0-0, 1-0, 0-1 and 1-1.")
+            ).arg(Arg::new("save")
+                .long("save")
+                .help("Json file to save the ratings to, for 'ratings' in the payoff grid.")
+            )
+        ).subcommand(Command::new("simulate")
+            .about("Monte Carlo simulation of bets on one match: settles them on final scores drawn from the probability model for the spread of the profit (percentiles, probability of a loss and a histogram), and optionally bankroll paths over many such matches for a staking plan. Needs 'home-xg' and 'away-xg' (or 'ratings').")
+            .arg(Arg::new("bets")
+                .long("bets")
+                .required(true)
+                .help("Json file with the 'bets' (bet_type, side, handicap or goals, odds, stake, and optionally lay and commission).")
+            ).arg(Arg::new("samples")
+                .long("samples")
+                .help("Number of matches to simulate for the profit distribution. Defaults to 10000.")
+            ).arg(Arg::new("seed")
+                .long("seed")
+                .help("Seed of the random numbers, to repeat a simulation exactly. Defaults to one taken from the clock, which is shown in the output.")
+            ).arg(Arg::new("matches")
+                .long("matches")
+                .help("Number of matches in a row, each with the same bets, to simulate bankroll paths over (for example, 500). No bankroll paths without it.")
+            ).arg(Arg::new("paths")
+                .long("paths")
+                .help("Number of bankroll paths. Defaults to 1000.")
+            ).arg(Arg::new("bankroll")
+                .long("bankroll")
+                .help("Starting bankroll of each path. Defaults to 100.")
+            ).arg(Arg::new("staking")
+                .long("staking")
+                .help("Staking plan of the bankroll paths: 'flat' for the stakes in the bets file on every match, 'proportional' for those stakes scaled with the bankroll, or 'kelly' for Kelly stakes on the current bankroll ('f', 'p', 'k'). Defaults to flat.")
+            ).arg(Arg::new("kelly_fraction")
+                .long("kelly-fraction")
+                .help("Fraction of the full Kelly stakes for 'kelly' staking (for example, 0.5 for half Kelly). Defaults to 1.")
+            )
         ).get_matches();
-    match bet_grid_handler::BetGrid::new().run(matches) {
-        Err(e) => println!("{:?}", e),
-        _ => (),
+    if let Err(e) = bet_grid_handler::BetGrid::new().run(matches) {
+        println!("{:?}", e);
     }
 }
@@ -1,6 +1,12 @@
+use crate::config::MINUTES_PER_MATCH;
+use crate::rational::Rational;
+use anyhow::{anyhow, Result};
 use std::fmt;
 use std::str::FromStr;
 
+// Lines can be at most this many goals either way, which keeps the arithmetic on them in range
+const MAX_LINE_GOALS: i32 = 1000;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum HomeAwayDrawSide {
     Home,
@@ -34,6 +40,43 @@ impl fmt::Display for HomeAwayDrawSide {
     }
 }
 
+impl HomeAwayDrawSide {
+    /// Whether a match with this result so far (e.g. at half time) can still finish
+    /// `home_goals`-`away_goals`: a team that is ahead must have scored
+    pub fn can_lead_to(&self, home_goals: usize, away_goals: usize) -> bool {
+        match self {
+            HomeAwayDrawSide::Home => home_goals >= 1,
+            HomeAwayDrawSide::Away => away_goals >= 1,
+            HomeAwayDrawSide::Draw => true,
+        }
+    }
+}
+
+/// Half-time/full-time double result, e.g. "d/h" for level at half time and a home win
+#[derive(Debug, PartialEq, Clone)]
+pub struct HalfTimeFullTimeSide {
+    pub half_time: HomeAwayDrawSide,
+    pub full_time: HomeAwayDrawSide,
+}
+
+impl FromStr for HalfTimeFullTimeSide {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<HalfTimeFullTimeSide, ()> {
+        let (half_time, full_time) = input.split_once('/').ok_or(())?;
+        Ok(HalfTimeFullTimeSide {
+            half_time: HomeAwayDrawSide::from_str(half_time)?,
+            full_time: HomeAwayDrawSide::from_str(full_time)?,
+        })
+    }
+}
+
+impl fmt::Display for HalfTimeFullTimeSide {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.half_time, self.full_time)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum OverUnderSide {
     Over,
@@ -91,3 +134,363 @@ impl fmt::Display for HomeAwaySide {
         }
     }
 }
+
+/// Backing a side wins when it does; laying it (on an exchange) wins when it doesn't
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BackLay {
+    Back,
+    Lay,
+}
+
+impl fmt::Display for BackLay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BackLay::Back => write!(f, "Back"),
+            BackLay::Lay => write!(f, "Lay"),
+        }
+    }
+}
+
+/// Part of the match a bet is settled on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Period {
+    FullTime,
+    FirstHalf,
+    SecondHalf,
+}
+
+impl Period {
+    /// Length of the period in (regulation) minutes
+    pub fn minutes(&self) -> f64 {
+        match self {
+            Period::FullTime => MINUTES_PER_MATCH,
+            Period::FirstHalf | Period::SecondHalf => MINUTES_PER_MATCH / 2.0,
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Period, ()> {
+        match input {
+            "full-time" => Ok(Period::FullTime),
+            "ft" => Ok(Period::FullTime),
+            "first-half" => Ok(Period::FirstHalf),
+            "1h" => Ok(Period::FirstHalf),
+            "second-half" => Ok(Period::SecondHalf),
+            "2h" => Ok(Period::SecondHalf),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Period::FullTime => write!(f, "Full time"),
+            Period::FirstHalf => write!(f, "First half"),
+            Period::SecondHalf => write!(f, "Second half"),
+        }
+    }
+}
+
+/// A handicap or goals line. Lines are only valid in steps of a quarter goal,
+/// so they are stored as a whole number of quarters (e.g. -0.75 is -3).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Line {
+    quarters: i32,
+}
+
+impl Line {
+    pub fn from_quarters(quarters: i32) -> Self {
+        Self { quarters }
+    }
+
+    pub fn quarters(&self) -> i32 {
+        self.quarters
+    }
+
+    pub fn value(&self) -> Rational {
+        Rational::from_quarters(self.quarters as i64)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.quarters < 0
+    }
+
+    /// Splits a quarter line into the two half/whole lines its stake is divided across
+    /// (e.g. -0.25 is -0 and -0.5). Returns `None` for whole and half lines.
+    pub fn halves(&self) -> Option<(Line, Line)> {
+        if self.quarters % 2 == 0 {
+            return None;
+        }
+        let toward_zero = self.quarters - self.quarters.signum();
+        let away_from_zero = self.quarters + self.quarters.signum();
+        Some((
+            Line::from_quarters(toward_zero),
+            Line::from_quarters(away_from_zero),
+        ))
+    }
+
+    /// Human-friendly description: the canonical decimal line, followed by the
+    /// split notation for quarter lines (e.g. "-0.25 (0/-0.5)").
+    pub fn describe(&self) -> String {
+        match self.halves() {
+            Some((first, second)) => format!("{} ({}/{})", self, first, second),
+            None => self.to_string(),
+        }
+    }
+
+    // Parses an unsigned line such as "2", "2.75", ".5", "½" or "1¼" into quarters
+    fn parse_unsigned_quarters(input: &str) -> Option<i32> {
+        let (number, fraction_char_quarters) = match input.chars().last()? {
+            '¼' => (&input[..input.len() - '¼'.len_utf8()], 1),
+            '½' => (&input[..input.len() - '½'.len_utf8()], 2),
+            '¾' => (&input[..input.len() - '¾'.len_utf8()], 3),
+            _ => (input, 0),
+        };
+
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if whole.is_empty() && fraction.is_empty() && fraction_char_quarters == 0 {
+            return None;
+        }
+        if !whole.chars().all(|c| c.is_ascii_digit())
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+            || (fraction_char_quarters != 0 && number.contains('.'))
+        {
+            return None;
+        }
+
+        let whole_quarters = match whole {
+            "" => 0,
+            digits => digits.parse::<i32>().ok()?.checked_mul(4)?,
+        };
+        // Compare the fraction exactly so inputs like 0.3 or 2.1 can never be rounded onto a line
+        let fraction_quarters = match fraction.trim_end_matches('0') {
+            "" => 0,
+            "25" => 1,
+            "5" => 2,
+            "75" => 3,
+            _ => return None,
+        };
+
+        whole_quarters.checked_add(fraction_quarters + fraction_char_quarters)
+    }
+
+    // Parses an optionally signed line, returning the sign separately so that "-0" keeps it
+    fn parse_signed_quarters(input: &str) -> Option<(Option<i32>, i32)> {
+        if let Some(rest) = input.strip_prefix('-') {
+            Some((Some(-1), Line::parse_unsigned_quarters(rest)?))
+        } else if let Some(rest) = input.strip_prefix('+') {
+            Some((Some(1), Line::parse_unsigned_quarters(rest)?))
+        } else {
+            Some((None, Line::parse_unsigned_quarters(input)?))
+        }
+    }
+}
+
+impl FromStr for Line {
+    type Err = anyhow::Error;
+
+    /// Accepts decimal lines ("-0.25", "2.5"), fraction characters ("+½", "1¼") and
+    /// Asian split notation ("0/0.5", "-0/0.5", "0/-0.5", "2-2.5", "+½/1").
+    fn from_str(input: &str) -> Result<Line> {
+        let invalid = || {
+            anyhow!(
+                "Invalid line '{}'. Lines must be a multiple of 0.25 (for example, -0.25, 1, 1.5, 2.75) or a split line such as 0/0.5 or 2-2.5.",
+                input
+            )
+        };
+
+        let parse = |part: &str| match Line::parse_signed_quarters(part) {
+            Some((_, quarters)) if quarters > MAX_LINE_GOALS * 4 => Err(anyhow!(
+                "Invalid line '{}'. Lines can't be more than {} goals either way.",
+                input,
+                MAX_LINE_GOALS
+            )),
+            Some(parsed) => Ok(parsed),
+            None => Err(invalid()),
+        };
+
+        let trimmed = input.trim();
+        // A '-' after the first character separates the two halves of a split line (e.g. "2-2.5")
+        let separator = trimmed
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| *c == '/' || *c == '-')
+            .map(|(idx, _)| idx);
+
+        match separator {
+            None => {
+                let (sign, quarters) = parse(trimmed)?;
+                Ok(Line::from_quarters(sign.unwrap_or(1) * quarters))
+            }
+            Some(idx) => {
+                let (first, second) = (&trimmed[..idx], &trimmed[idx + 1..]);
+                let (first_sign, first_quarters) = parse(first)?;
+                let (second_sign, second_quarters) = parse(second)?;
+
+                // The leading sign applies to both halves ("-0/0.5" is -0 and -0.5), but a
+                // sign on the second half is also accepted on its own ("0/-0.5")
+                let sign = match (first_sign, second_sign) {
+                    (Some(first), Some(second)) if first != second => return Err(invalid()),
+                    (Some(sign), _) | (None, Some(sign)) => sign,
+                    (None, None) => 1,
+                };
+
+                // Both halves must be whole or half lines, exactly half a goal apart
+                if first_quarters % 2 != 0
+                    || second_quarters % 2 != 0
+                    || (first_quarters - second_quarters).abs() != 2
+                {
+                    return Err(invalid());
+                }
+
+                Ok(Line::from_quarters(
+                    sign * (first_quarters + second_quarters) / 2,
+                ))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_names_and_lengths() {
+        for (input, period) in [
+            ("full-time", Period::FullTime),
+            ("ft", Period::FullTime),
+            ("first-half", Period::FirstHalf),
+            ("1h", Period::FirstHalf),
+            ("second-half", Period::SecondHalf),
+            ("2h", Period::SecondHalf),
+        ] {
+            assert_eq!(Period::from_str(input), Ok(period));
+        }
+        assert!(Period::from_str("3h").is_err());
+        assert_eq!(Period::SecondHalf.to_string(), "Second half");
+        assert_eq!(Period::FirstHalf.minutes(), 45.0);
+        assert_eq!(Period::FullTime.minutes(), 90.0);
+    }
+
+    #[test]
+    fn test_line_accepts_quarter_multiples() {
+        for (input, quarters) in [
+            ("0", 0),
+            ("1", 4),
+            ("-1", -4),
+            ("+1.5", 6),
+            ("0.25", 1),
+            ("-0.75", -3),
+            ("2.50", 10),
+            (".5", 2),
+            ("3.", 12),
+        ] {
+            assert_eq!(
+                Line::from_str(input).unwrap(),
+                Line::from_quarters(quarters),
+                "Expected '{}' to parse as {} quarters",
+                input,
+                quarters
+            );
+        }
+    }
+
+    #[test]
+    fn test_line_rejects_other_values() {
+        for input in [
+            "0.3", "2.1", "-0.125", "1.05", "", "-", ".", "abc", "1e1", "NaN", "1..5",
+        ] {
+            assert!(
+                Line::from_str(input).is_err(),
+                "Expected '{}' to be rejected",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_line_is_bounded() {
+        assert_eq!(Line::from_str("-1000").unwrap(), Line::from_quarters(-4000));
+        assert_eq!(
+            Line::from_str("999.5/1000").unwrap().halves(),
+            Some((Line::from_quarters(3998), Line::from_quarters(4000)))
+        );
+        for input in ["1000.25", "-536870911.75", "536870911.5/536870911"] {
+            assert!(
+                Line::from_str(input).is_err(),
+                "Expected '{}' to be rejected",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_line_accepts_split_notation() {
+        for (input, quarters) in [
+            ("0/0.5", 1),
+            ("-0/0.5", -1),
+            ("0/-0.5", -1),
+            ("0.5/1", 3),
+            ("-1/1.5", -5),
+            ("2-2.5", 9),
+            ("2.5-3", 11),
+            ("+½/1", 3),
+            ("½", 2),
+            ("-1½", -6),
+            ("2¼", 9),
+            ("1/0.5", 3),
+        ] {
+            assert_eq!(
+                Line::from_str(input).unwrap(),
+                Line::from_quarters(quarters),
+                "Expected '{}' to parse as {} quarters",
+                input,
+                quarters
+            );
+        }
+    }
+
+    #[test]
+    fn test_line_rejects_invalid_split_notation() {
+        for input in [
+            "0/1",
+            "0.25/0.75",
+            "0/0.5/1",
+            "-0/+0.5",
+            "0/",
+            "/0.5",
+            "1.½",
+            "½½",
+        ] {
+            assert!(
+                Line::from_str(input).is_err(),
+                "Expected '{}' to be rejected",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_line_describe() {
+        assert_eq!(Line::from_quarters(-1).describe(), "-0.25 (0/-0.5)");
+        assert_eq!(Line::from_quarters(9).describe(), "2.25 (2/2.5)");
+        assert_eq!(Line::from_quarters(-6).describe(), "-1.5");
+    }
+
+    #[test]
+    fn test_line_display() {
+        assert_eq!(Line::from_quarters(-3).to_string(), "-0.75");
+        assert_eq!(Line::from_quarters(8).to_string(), "2");
+    }
+}
@@ -0,0 +1,446 @@
+use anyhow::{anyhow, bail, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+use crate::bet_types::Market;
+use crate::exchange::{ExchangeBet, JsonExchangeBet};
+use crate::market_specifiers::{BackLay, HomeAwayDrawSide, HomeAwaySide, Line, OverUnderSide};
+use crate::rational::Rational;
+use crate::score::Score;
+
+// Stakes worked out by the tool are whole cents
+const STAKE_UNITS: i64 = 100;
+
+/// A bet as written in a bet file, with the same names and defaults as the command line
+/// arguments. Numbers can be given as json numbers or strings.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JsonBetSpec {
+    pub bet_type: String,
+    pub side: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handicap: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub goals: Option<Value>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lay: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub odds: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stake: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commission: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_score: Option<String>,
+}
+
+/// A bets file: bets on one match
+#[derive(Deserialize)]
+pub struct JsonBetsFile {
+    pub bets: Vec<JsonBetSpec>,
+}
+
+#[derive(Serialize)]
+pub struct JsonPositionBet {
+    pub market: String,
+    pub exchange: JsonExchangeBet,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_score: Option<String>,
+}
+
+/// One bet of a position: the market, how it was struck, and the score when it was placed
+/// in play
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionBet {
+    pub market: Market,
+    pub exchange_bet: ExchangeBet,
+    pub current_score: Option<Score>,
+}
+
+impl PositionBet {
+    pub fn from_json(spec: &JsonBetSpec) -> Result<Self> {
+        Ok(Self {
+            market: parse_market(spec)?,
+            exchange_bet: parse_exchange_bet(spec)?,
+            current_score: parse_current_score(spec)?,
+        })
+    }
+
+    /// Profit of the bet if the match finishes `home_goals`-`away_goals`
+    pub fn get_profit(&self, home_goals: usize, away_goals: usize) -> Result<Rational> {
+        self.exchange_bet
+            .take_commission(self.get_gross_profit(home_goals, away_goals)?)
+    }
+
+    /// Profit of the bet before commission
+    pub fn get_gross_profit(&self, home_goals: usize, away_goals: usize) -> Result<Rational> {
+        self.exchange_bet.get_gross_profit(self.market.get_payoff(
+            self.current_score,
+            home_goals,
+            away_goals,
+        )?)
+    }
+
+    /// Whether the bets are netted against each other before commission: bets on any
+    /// selection of one exchange market, placed at the same score with the same commission
+    pub fn is_same_commission_market(&self, other: &PositionBet) -> bool {
+        self.market.is_same_exchange_market(&other.market)
+            && self.current_score == other.current_score
+            && self.exchange_bet.commission == other.exchange_bet.commission
+    }
+
+    pub fn to_json(&self) -> JsonPositionBet {
+        JsonPositionBet {
+            market: self.market.to_string(),
+            exchange: self.exchange_bet.to_json(),
+            current_score: self.current_score.map(|score| score.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for PositionBet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.market, self.exchange_bet)?;
+        if let Some(current_score) = self.current_score {
+            write!(f, ", placed at {}", current_score)?;
+        }
+        Ok(())
+    }
+}
+
+/// The markets commission is charged on. Bets on any selection of the same market (placed at
+/// the same score, with the same commission) are taken to be on one exchange account, which
+/// charges commission once on their net winnings.
+pub struct CommissionMarkets {
+    /// Index of the market of each bet
+    pub bet_markets: Vec<usize>,
+    /// Commission of each market
+    pub commissions: Vec<Rational>,
+}
+
+impl CommissionMarkets {
+    pub fn new(bets: &[PositionBet]) -> Self {
+        CommissionMarkets::group(bets, PositionBet::is_same_commission_market, |bet| {
+            bet.exchange_bet.commission
+        })
+    }
+
+    /// Groups `bets` into markets, with `is_same_market` telling whether two bets are netted
+    /// against each other and `get_commission` the commission of a bet
+    pub fn group<T, F, G>(bets: &[T], is_same_market: F, get_commission: G) -> Self
+    where
+        F: Fn(&T, &T) -> bool,
+        G: Fn(&T) -> Rational,
+    {
+        let mut first_bets: Vec<&T> = vec![];
+        let mut bet_markets = vec![];
+        for bet in bets {
+            let market = match first_bets
+                .iter()
+                .position(|first_bet| is_same_market(first_bet, bet))
+            {
+                Some(market) => market,
+                None => {
+                    first_bets.push(bet);
+                    first_bets.len() - 1
+                }
+            };
+            bet_markets.push(market);
+        }
+        Self {
+            bet_markets,
+            commissions: first_bets.into_iter().map(get_commission).collect(),
+        }
+    }
+
+    /// Profit of each bet after commission, from their profits before it. The commission on a
+    /// market's net winnings is shared between its winning bets in proportion to what they won.
+    pub fn take_commission(&self, gross_profits: &[Rational]) -> Result<Vec<Rational>> {
+        let mut net_profits = vec![Rational::zero(); self.commissions.len()];
+        let mut winnings = vec![Rational::zero(); self.commissions.len()];
+        for (&gross_profit, &market) in gross_profits.iter().zip(&self.bet_markets) {
+            net_profits[market] = net_profits[market].checked_add(gross_profit)?;
+            if gross_profit.is_positive() {
+                winnings[market] = winnings[market].checked_add(gross_profit)?;
+            }
+        }
+        gross_profits
+            .iter()
+            .zip(&self.bet_markets)
+            .map(|(&gross_profit, &market)| {
+                if !gross_profit.is_positive() || !net_profits[market].is_positive() {
+                    return Ok(gross_profit);
+                }
+                let commission = net_profits[market].checked_mul(self.commissions[market])?;
+                gross_profit.checked_sub(
+                    commission.checked_mul(gross_profit.checked_div(winnings[market])?)?,
+                )
+            })
+            .collect()
+    }
+
+    /// Fraction of each market's net profit that is kept after commission, from the net
+    /// profits before commission (`gross_profits`, one for each market)
+    pub fn get_kept_fractions(&self, gross_profits: &[f64]) -> Vec<f64> {
+        gross_profits
+            .iter()
+            .zip(&self.commissions)
+            .map(|(&gross_profit, commission)| match gross_profit > 0.0 {
+                true => 1.0 - commission.to_f64(),
+                false => 1.0,
+            })
+            .collect()
+    }
+}
+
+/// Combined profit of `bets` if the match finishes `home_goals`-`away_goals`, with commission
+/// taken from the net winnings of each market
+pub fn get_position_profit(
+    bets: &[PositionBet],
+    home_goals: usize,
+    away_goals: usize,
+) -> Result<Rational> {
+    let gross_profits = bets
+        .iter()
+        .map(|bet| bet.get_gross_profit(home_goals, away_goals))
+        .collect::<Result<Vec<_>>>()?;
+    Rational::checked_sum(CommissionMarkets::new(bets).take_commission(&gross_profits)?)
+}
+
+/// Reads the bets of a bets file
+pub fn read_bets_file(path: &str) -> Result<Vec<PositionBet>> {
+    let bets_file: JsonBetsFile = read_json_file(path)?;
+    if bets_file.bets.is_empty() {
+        bail!("The bets file '{}' has no 'bets'.", path);
+    }
+    bets_file.bets.iter().map(PositionBet::from_json).collect()
+}
+
+/// How a bet in a bet file was struck, defaulting to backing at even money for a unit stake
+pub fn parse_exchange_bet(spec: &JsonBetSpec) -> Result<ExchangeBet> {
+    let even_money = ExchangeBet::even_money();
+    let back_lay = match spec.lay {
+        true => BackLay::Lay,
+        false => BackLay::Back,
+    };
+    ExchangeBet::new(
+        back_lay,
+        parse_number(spec.odds.as_ref(), "odds")?.unwrap_or(even_money.odds),
+        parse_number(spec.stake.as_ref(), "stake")?.unwrap_or(even_money.stake),
+        parse_number(spec.commission.as_ref(), "commission")?.unwrap_or(even_money.commission),
+    )
+}
+
+/// The score an in-play bet was placed at, which in-running Asian handicaps settle from
+pub fn parse_current_score(spec: &JsonBetSpec) -> Result<Option<Score>> {
+    spec.current_score
+        .as_deref()
+        .map(|current_score| {
+            Score::from_str(current_score).map_err(|e| anyhow!("Invalid current_score: {}", e))
+        })
+        .transpose()
+}
+
+/// A stake worked out in floating point, rounded to the nearest cent
+pub fn round_stake(stake: f64) -> Result<Rational> {
+    Rational::new((stake * STAKE_UNITS as f64).round() as i64, STAKE_UNITS)
+}
+
+/// Reads a json bet file into `T`
+pub fn read_json_file<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| anyhow!("Couldn't read '{}': {}", path, e))?;
+    serde_json::from_str(&contents).map_err(|e| anyhow!("Invalid bet file '{}': {}", path, e))
+}
+
+/// A json number (or a string holding one) as an exact fraction. `None` if it's missing.
+pub fn parse_number(value: Option<&Value>, name: &str) -> Result<Option<Rational>> {
+    let input = match value {
+        None => return Ok(None),
+        Some(Value::String(input)) => input.clone(),
+        Some(Value::Number(number)) => number.to_string(),
+        Some(other) => bail!("Invalid {} '{}'. Must be a number.", name, other),
+    };
+    Rational::from_str(&input)
+        .map(Some)
+        .map_err(|e| anyhow!("Invalid {}: {}", name, e))
+}
+
+// The line of an Asian handicap or over/under bet, which bet files give as a number
+fn parse_line(value: Option<&Value>, name: &str, bet_type: &str) -> Result<Line> {
+    let input = match value {
+        None => bail!("Missing '{}' for {} bet.", name, bet_type),
+        Some(Value::String(input)) => input.clone(),
+        Some(other) => other.to_string(),
+    };
+    Line::from_str(&input)
+}
+
+/// The market of a bet in a bet file (full-time win-draw-win, Asian handicap or over/under)
+pub fn parse_market(spec: &JsonBetSpec) -> Result<Market> {
+    let bet_type = spec.bet_type.as_str();
+    let invalid_side = || anyhow!("Invalid side '{}' for {} bet.", spec.side, bet_type);
+    match bet_type {
+        "win-draw-win" => {
+            let side = HomeAwayDrawSide::from_str(&spec.side).map_err(|_| invalid_side())?;
+            Ok(Market::WinDrawWin(side))
+        }
+        "asian-handicap" => {
+            let side = HomeAwaySide::from_str(&spec.side).map_err(|_| invalid_side())?;
+            let handicap = parse_line(spec.handicap.as_ref(), "handicap", bet_type)?;
+            Ok(Market::AsianHandicap(side, handicap))
+        }
+        "over-under" => {
+            let side = OverUnderSide::from_str(&spec.side).map_err(|_| invalid_side())?;
+            let goals = parse_line(spec.goals.as_ref(), "goals", bet_type)?;
+            if goals.is_negative() {
+                bail!("Invalid goals line '{}'. Over/under lines can't be negative.", goals);
+            }
+            Ok(Market::OverUnder(side, goals))
+        }
+        _ => bail!(
+            "Unsupported bet type '{}' in bet file. Should be one of: 'win-draw-win', 'asian-handicap' or 'over-under'.",
+            bet_type
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bet(json: &str) -> Result<PositionBet> {
+        PositionBet::from_json(&serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn test_reads_bets_with_defaults() {
+        let handicap = bet(
+            r#"{"bet_type": "asian-handicap", "side": "home", "handicap": -0.25, "odds": 1.95, "stake": "100"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            handicap.market,
+            Market::AsianHandicap(HomeAwaySide::Home, Line::from_quarters(-1))
+        );
+        assert_eq!(handicap.exchange_bet.odds, Rational::new(195, 100).unwrap());
+        // Half lost at 1-1
+        assert_eq!(
+            handicap.get_profit(1, 1).unwrap(),
+            Rational::from_integer(-50)
+        );
+
+        // Laid the draw at evens for 1
+        let lay = bet(r#"{"bet_type": "win-draw-win", "side": "d", "lay": true}"#).unwrap();
+        assert_eq!(lay.exchange_bet.back_lay, BackLay::Lay);
+        assert_eq!(lay.exchange_bet.stake, Rational::one());
+        assert_eq!(
+            get_position_profit(&[handicap, lay], 2, 0).unwrap(),
+            Rational::from_integer(96)
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_bets() {
+        assert!(bet(r#"{"bet_type": "over-under", "side": "over"}"#).is_err());
+        assert!(bet(r#"{"bet_type": "over-under", "side": "home", "goals": 2.5}"#).is_err());
+        assert!(bet(r#"{"bet_type": "ht-ft", "side": "d/h"}"#).is_err());
+        assert!(bet(r#"{"bet_type": "win-draw-win", "side": "h", "odds": true}"#).is_err());
+    }
+
+    #[test]
+    fn test_in_play_bets_count_goals_after_the_current_score() {
+        let in_play = bet(
+            r#"{"bet_type": "asian-handicap", "side": "away", "handicap": 0, "odds": 2, "stake": 10, "current_score": "0-1"}"#,
+        )
+        .unwrap();
+        assert_eq!(in_play.current_score, Some(Score::new(0, 1)));
+        // Level from 0-1, so the stake is returned
+        assert_eq!(in_play.get_profit(1, 2).unwrap(), Rational::zero());
+        assert_eq!(in_play.get_profit(0, 1).unwrap(), Rational::zero());
+        assert_eq!(
+            in_play.get_profit(1, 1).unwrap(),
+            Rational::from_integer(-10)
+        );
+        assert!(in_play.get_profit(0, 0).is_err());
+
+        assert!(bet(
+            r#"{"bet_type": "asian-handicap", "side": "away", "handicap": 0, "current_score": "one-nil"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_commission_on_net_market_winnings() {
+        // Backed home at 3 and laid it at 2.5 on the same exchange, 10 each
+        let back = bet(
+            r#"{"bet_type": "win-draw-win", "side": "h", "odds": 3, "stake": 10, "commission": 0.05}"#,
+        )
+        .unwrap();
+        let lay = bet(
+            r#"{"bet_type": "win-draw-win", "side": "h", "odds": 2.5, "stake": 10, "lay": true, "commission": 0.05}"#,
+        )
+        .unwrap();
+        let bets = [back.clone(), lay.clone()];
+        // Home: 20 won less 15 lost, and 5% of the net 5
+        assert_eq!(
+            get_position_profit(&bets, 1, 0).unwrap(),
+            Rational::new(475, 100).unwrap()
+        );
+        // Otherwise the two cancel out, and there's nothing to take commission from
+        assert_eq!(get_position_profit(&bets, 0, 0).unwrap(), Rational::zero());
+        assert_eq!(
+            back.get_profit(0, 0)
+                .unwrap()
+                .checked_add(lay.get_profit(0, 0).unwrap())
+                .unwrap(),
+            Rational::new(-1, 2).unwrap()
+        );
+
+        // A bookmaker's back without commission isn't netted with the exchange lay
+        let bookmaker_back =
+            bet(r#"{"bet_type": "win-draw-win", "side": "h", "odds": 3, "stake": 10}"#).unwrap();
+        assert_eq!(
+            get_position_profit(&[bookmaker_back, lay], 0, 0).unwrap(),
+            Rational::new(-1, 2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_commission_nets_selections_of_one_market() {
+        // Backed home at 3 and the draw at 3.5, 10 each: the exchange takes 5% of the 10 won
+        // on the market, not of the 20 won on home
+        let home = bet(
+            r#"{"bet_type": "win-draw-win", "side": "h", "odds": 3, "stake": 10, "commission": 0.05}"#,
+        )
+        .unwrap();
+        let draw = bet(
+            r#"{"bet_type": "win-draw-win", "side": "d", "odds": 3.5, "stake": 10, "commission": 0.05}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            get_position_profit(&[home, draw], 1, 0).unwrap(),
+            Rational::new(95, 10).unwrap()
+        );
+
+        let market = |json: &str| bet(json).unwrap();
+        let home_handicap =
+            market(r#"{"bet_type": "asian-handicap", "side": "home", "handicap": -0.5}"#);
+        let over = market(r#"{"bet_type": "over-under", "side": "over", "goals": 2.5}"#);
+        assert!(home_handicap.is_same_commission_market(&market(
+            r#"{"bet_type": "asian-handicap", "side": "away", "handicap": 0.5}"#
+        )));
+        assert!(!home_handicap.is_same_commission_market(&market(
+            r#"{"bet_type": "asian-handicap", "side": "away", "handicap": -0.5}"#
+        )));
+        assert!(over.is_same_commission_market(&market(
+            r#"{"bet_type": "over-under", "side": "under", "goals": 2.5}"#
+        )));
+        assert!(!over.is_same_commission_market(&market(
+            r#"{"bet_type": "over-under", "side": "over", "goals": 3.5}"#
+        )));
+    }
+}
@@ -0,0 +1,601 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::config::{MAX_MODEL_GOALS, MINUTES_PER_MATCH};
+use crate::grid::{print_goals_table, CellStyle, GridBounds, JsonGrid};
+use crate::market_specifiers::HomeAwayDrawSide;
+use crate::rational::Rational;
+use crate::score::Score;
+
+/// Poisson goal model: each team scores at a constant rate, given as the expected goals over a
+/// full match. Goals are independent, unless a Dixon-Coles `rho` moves probability between
+/// the four lowest scores (a negative rho makes 0-0 and 1-1 more likely). Goal timing markets
+/// don't use the correction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoissonModel {
+    home_expected_goals: f64,
+    away_expected_goals: f64,
+    rho: f64,
+}
+
+impl PoissonModel {
+    pub fn new(home_expected_goals: f64, away_expected_goals: f64) -> Result<Self> {
+        for expected_goals in [home_expected_goals, away_expected_goals] {
+            if !expected_goals.is_finite() || expected_goals < 0.0 {
+                bail!(
+                    "Invalid expected goals {}. Expected goals must be a non-negative number.",
+                    expected_goals
+                );
+            }
+        }
+        Ok(Self {
+            home_expected_goals,
+            away_expected_goals,
+            rho: 0.0,
+        })
+    }
+
+    /// The model with the Dixon-Coles correction `rho` (0 for independent goals). It must
+    /// leave every low score with a probability of at least 0.
+    pub fn with_rho(mut self, rho: f64) -> Result<Self> {
+        self.rho = rho;
+        if !rho.is_finite()
+            || [(0, 0), (0, 1), (1, 0), (1, 1)]
+                .iter()
+                .any(|(home_goals, away_goals)| {
+                    self.low_score_factor(*home_goals, *away_goals) < 0.0
+                })
+        {
+            bail!(
+                "Invalid rho {}. With these expected goals it must keep the probabilities of 0-0, 1-0, 0-1 and 1-1 positive (rho is usually between -0.2 and 0).",
+                rho
+            );
+        }
+        Ok(self)
+    }
+
+    /// The model for the goals still to come, with `minutes_remaining` of the match left
+    pub fn for_minutes_remaining(&self, minutes_remaining: f64) -> PoissonModel {
+        let fraction_remaining = minutes_remaining / MINUTES_PER_MATCH;
+        PoissonModel {
+            home_expected_goals: self.home_expected_goals * fraction_remaining,
+            away_expected_goals: self.away_expected_goals * fraction_remaining,
+            rho: self.rho,
+        }
+    }
+
+    // Dixon-Coles factor on the probability of `home_goals`-`away_goals` goals to come. The
+    // changes to the four lowest scores cancel out, so the probabilities still add up to 1.
+    fn low_score_factor(&self, home_goals: usize, away_goals: usize) -> f64 {
+        let (home, away, rho) = (self.home_expected_goals, self.away_expected_goals, self.rho);
+        match (home_goals, away_goals) {
+            (0, 0) => 1.0 - home * away * rho,
+            (0, 1) => 1.0 + home * rho,
+            (1, 0) => 1.0 + away * rho,
+            (1, 1) => 1.0 - rho,
+            _ => 1.0,
+        }
+    }
+
+    // P(0), P(1), ..., P(MAX_MODEL_GOALS) goals for a Poisson distribution
+    fn goal_probabilities(expected_goals: f64) -> Vec<f64> {
+        let mut probabilities = vec![(-expected_goals).exp()];
+        for goals in 1..=MAX_MODEL_GOALS {
+            probabilities.push(probabilities[goals - 1] * expected_goals / goals as f64);
+        }
+        probabilities
+    }
+
+    /// Probability of each final score in the grid, with the model describing the goals still
+    /// to come from `current_score` (0-0 before kick-off). Tail buckets hold all the mass
+    /// at or beyond the grid edge.
+    pub fn get_probability_grid(
+        &self,
+        bounds: &GridBounds,
+        current_score: Option<Score>,
+    ) -> ProbabilityGrid {
+        let current_score = current_score.unwrap_or(Score::new(0, 0));
+        let home_probabilities = PoissonModel::goal_probabilities(self.home_expected_goals);
+        let away_probabilities = PoissonModel::goal_probabilities(self.away_expected_goals);
+
+        // Probability of the final goals for one team landing in a row/column
+        let probability =
+            |probabilities: &Vec<f64>, goals: usize, current: usize, tail: bool| match (
+                tail,
+                goals.checked_sub(current),
+            ) {
+                (true, None) => Some(1.0),
+                (true, Some(to_come)) => {
+                    Some(1.0 - probabilities.iter().take(to_come).sum::<f64>())
+                }
+                (false, None) => None,
+                (false, Some(to_come)) => Some(probabilities.get(to_come).copied().unwrap_or(0.0)),
+            };
+
+        let grid = bounds
+            .home_goals()
+            .map(|home_goals| {
+                bounds
+                    .away_goals()
+                    .map(|away_goals| {
+                        let home = probability(
+                            &home_probabilities,
+                            home_goals,
+                            current_score.home_goals,
+                            bounds.is_home_tail(home_goals),
+                        )?;
+                        let away = probability(
+                            &away_probabilities,
+                            away_goals,
+                            current_score.away_goals,
+                            bounds.is_away_tail(away_goals),
+                        )?;
+                        Some(home * away)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut probability_grid = ProbabilityGrid {
+            grid,
+            bounds: bounds.clone(),
+        };
+        // The Dixon-Coles correction to the cells of the lowest scores still to come
+        if self.rho != 0.0 {
+            for (home_to_come, away_to_come) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                let correction = home_probabilities[home_to_come]
+                    * away_probabilities[away_to_come]
+                    * (self.low_score_factor(home_to_come, away_to_come) - 1.0);
+                let final_score = Score::new(
+                    current_score.home_goals + home_to_come,
+                    current_score.away_goals + away_to_come,
+                );
+                if let Some(cell_probability) = probability_grid.get_cell_mut(final_score) {
+                    *cell_probability += correction;
+                }
+            }
+        }
+        probability_grid
+    }
+
+    /// Expected payoff over every final score the model allows (not just those in the grid)
+    pub fn get_expected_payoff<F>(&self, current_score: Option<Score>, payoff: F) -> Result<f64>
+    where
+        F: Fn(usize, usize) -> Result<Rational>,
+    {
+        self.get_score_probabilities(current_score)
+            .into_iter()
+            .map(|(score, probability)| {
+                Ok(probability * payoff(score.home_goals, score.away_goals)?.to_f64())
+            })
+            .sum()
+    }
+
+    /// Expected value of `value(home_goals, away_goals)` over every final score the model allows
+    pub fn get_expectation<F>(&self, current_score: Option<Score>, value: F) -> f64
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        self.get_score_probabilities(current_score)
+            .into_iter()
+            .map(|(score, probability)| probability * value(score.home_goals, score.away_goals))
+            .sum()
+    }
+
+    /// Every final score the model allows from `current_score` (0-0 before kick-off), with its
+    /// probability
+    pub fn get_score_probabilities(&self, current_score: Option<Score>) -> Vec<(Score, f64)> {
+        let current_score = current_score.unwrap_or(Score::new(0, 0));
+        let home_probabilities = PoissonModel::goal_probabilities(self.home_expected_goals);
+        let away_probabilities = PoissonModel::goal_probabilities(self.away_expected_goals);
+
+        let mut score_probabilities = vec![];
+        for (home_to_come, home_probability) in home_probabilities.iter().enumerate() {
+            for (away_to_come, away_probability) in away_probabilities.iter().enumerate() {
+                let score = Score::new(
+                    current_score.home_goals + home_to_come,
+                    current_score.away_goals + away_to_come,
+                );
+                score_probabilities.push((
+                    score,
+                    home_probability
+                        * away_probability
+                        * self.low_score_factor(home_to_come, away_to_come),
+                ));
+            }
+        }
+        score_probabilities
+    }
+
+    /// Probability of an event in each cell of the grid, given the final score lands in that
+    /// cell. `probability(home_goals, away_goals)` is the probability given an exact final
+    /// score; tail buckets average it over the scores they hold, weighted by the model.
+    pub fn get_conditional_probability_grid<F>(
+        &self,
+        bounds: &GridBounds,
+        probability: F,
+    ) -> ProbabilityGrid
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let home_probabilities = PoissonModel::goal_probabilities(self.home_expected_goals);
+        let away_probabilities = PoissonModel::goal_probabilities(self.away_expected_goals);
+        let score_probability = |home_goals: usize, away_goals: usize| {
+            home_probabilities.get(home_goals).copied().unwrap_or(0.0)
+                * away_probabilities.get(away_goals).copied().unwrap_or(0.0)
+                * self.low_score_factor(home_goals, away_goals)
+        };
+
+        let grid = bounds
+            .home_goals()
+            .map(|home_goals| {
+                bounds
+                    .away_goals()
+                    .map(|away_goals| {
+                        let (mut cell_probability, mut event_probability) = (0.0, 0.0);
+                        for home in bounds.home_goals_in_row(home_goals) {
+                            for away in bounds.away_goals_in_col(away_goals) {
+                                cell_probability += score_probability(home, away);
+                                event_probability +=
+                                    score_probability(home, away) * probability(home, away);
+                            }
+                        }
+                        // Scores the model rules out (e.g. with zero expected goals) are left empty
+                        (cell_probability > 0.0).then(|| event_probability / cell_probability)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        ProbabilityGrid {
+            grid,
+            bounds: bounds.clone(),
+        }
+    }
+
+    /// Probabilities of the home team winning, a draw and the away team winning over
+    /// `minutes` (e.g. extra time), counting only the goals scored in that time
+    pub fn get_result_probabilities(&self, minutes: f64) -> (f64, f64, f64) {
+        let model = self.for_minutes_remaining(minutes);
+        let home_probabilities = PoissonModel::goal_probabilities(model.home_expected_goals);
+        let away_probabilities = PoissonModel::goal_probabilities(model.away_expected_goals);
+
+        let (mut home_win, mut draw, mut away_win) = (0.0, 0.0, 0.0);
+        for (home_goals, home_probability) in home_probabilities.iter().enumerate() {
+            for (away_goals, away_probability) in away_probabilities.iter().enumerate() {
+                let probability = home_probability
+                    * away_probability
+                    * model.low_score_factor(home_goals, away_goals);
+                match home_goals.cmp(&away_goals) {
+                    Ordering::Greater => home_win += probability,
+                    Ordering::Equal => draw += probability,
+                    Ordering::Less => away_win += probability,
+                }
+            }
+        }
+        (home_win, draw, away_win)
+    }
+
+    /// Probability of no goals at all over `minutes`
+    pub fn get_goalless_probability(&self, minutes: f64) -> f64 {
+        let model = self.for_minutes_remaining(minutes);
+        (-(model.home_expected_goals + model.away_expected_goals)).exp()
+            * model.low_score_factor(0, 0)
+    }
+
+    // Calls `visit(half_time, full_time, probability)` for every pair of half-time and full-time
+    // scores, with the goal rates split evenly over the two halves. Each half is cut off at half
+    // the full-match goal limit, which leaves out a negligible amount of probability. The
+    // Dixon-Coles correction applies to the full-time score.
+    fn for_each_half_time_full_time<F>(&self, mut visit: F)
+    where
+        F: FnMut(Score, Score, f64),
+    {
+        let half = self.for_minutes_remaining(MINUTES_PER_MATCH / 2.0);
+        let mut home_probabilities = PoissonModel::goal_probabilities(half.home_expected_goals);
+        let mut away_probabilities = PoissonModel::goal_probabilities(half.away_expected_goals);
+        home_probabilities.truncate(MAX_MODEL_GOALS / 2 + 1);
+        away_probabilities.truncate(MAX_MODEL_GOALS / 2 + 1);
+
+        for (first_home, first_home_probability) in home_probabilities.iter().enumerate() {
+            for (first_away, first_away_probability) in away_probabilities.iter().enumerate() {
+                let half_time = Score::new(first_home, first_away);
+                for (second_home, second_home_probability) in home_probabilities.iter().enumerate()
+                {
+                    for (second_away, second_away_probability) in
+                        away_probabilities.iter().enumerate()
+                    {
+                        let full_time =
+                            Score::new(first_home + second_home, first_away + second_away);
+                        visit(
+                            half_time,
+                            full_time,
+                            first_home_probability
+                                * first_away_probability
+                                * second_home_probability
+                                * second_away_probability
+                                * self.low_score_factor(full_time.home_goals, full_time.away_goals),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Probability of each full-time score in the grid together with each half-time result,
+    /// one grid per half-time result (home ahead, level, away ahead). Full-time scores that
+    /// can't follow the half-time result are `None`.
+    pub fn get_half_time_full_time_probability_grids(
+        &self,
+        bounds: &GridBounds,
+    ) -> Vec<(HomeAwayDrawSide, ProbabilityGrid)> {
+        let half_time_results = [
+            HomeAwayDrawSide::Home,
+            HomeAwayDrawSide::Draw,
+            HomeAwayDrawSide::Away,
+        ];
+        let mut grids: Vec<(HomeAwayDrawSide, ProbabilityGrid)> = half_time_results
+            .into_iter()
+            .map(|half_time_result| {
+                let grid = bounds
+                    .home_goals()
+                    .map(|home_goals| {
+                        bounds
+                            .away_goals()
+                            .map(|away_goals| {
+                                half_time_result
+                                    .can_lead_to(home_goals, away_goals)
+                                    .then_some(0.0)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let probability_grid = ProbabilityGrid {
+                    grid,
+                    bounds: bounds.clone(),
+                };
+                (half_time_result, probability_grid)
+            })
+            .collect();
+
+        self.for_each_half_time_full_time(|half_time, full_time, probability| {
+            let (_, probability_grid) = grids
+                .iter_mut()
+                .find(|(half_time_result, _)| *half_time_result == half_time.result())
+                .expect("every half-time result has a grid");
+            if let Some(cell_probability) = probability_grid.get_cell_mut(full_time) {
+                *cell_probability += probability;
+            }
+        });
+        grids
+    }
+
+    /// Expected payoff of a bet settled on both the half-time and full-time scores
+    pub fn get_half_time_full_time_expected_payoff<F>(&self, payoff: F) -> Result<f64>
+    where
+        F: Fn(Score, Score) -> Result<Rational>,
+    {
+        let mut expected_payoff = Ok(0.0);
+        self.for_each_half_time_full_time(|half_time, full_time, probability| {
+            if let Ok(sum) = expected_payoff {
+                expected_payoff =
+                    payoff(half_time, full_time).map(|payoff| sum + probability * payoff.to_f64());
+            }
+        });
+        expected_payoff
+    }
+}
+
+/// Probability of each final score in a grid. `None` for scores that can't happen any more.
+pub struct ProbabilityGrid {
+    grid: Vec<Vec<Option<f64>>>,
+    bounds: GridBounds,
+}
+
+/// A payoff grid together with the model's view of it
+#[derive(Serialize)]
+pub struct JsonModelGrid {
+    #[serde(flatten)]
+    pub payoff: JsonGrid,
+    pub probability_grid: HashMap<usize, HashMap<usize, f64>>,
+    pub expected_value: f64,
+}
+
+impl ProbabilityGrid {
+    // The cell a final score lands in, if it's in the grid. Scores beyond the edge go into the
+    // tail bucket, if there is one.
+    fn get_cell_mut(&mut self, score: Score) -> Option<&mut f64> {
+        let bounds = &self.bounds;
+        let cell = |goals: usize, min_goals: usize, max_goals: usize| match goals {
+            goals if goals < min_goals => None,
+            goals if goals > max_goals && !bounds.tail => None,
+            goals => Some(goals.min(max_goals) - min_goals),
+        };
+        let row = cell(
+            score.home_goals,
+            bounds.min_home_goals,
+            bounds.max_home_goals,
+        )?;
+        let col = cell(
+            score.away_goals,
+            bounds.min_away_goals,
+            bounds.max_away_goals,
+        )?;
+        self.grid[row][col].as_mut()
+    }
+
+    pub fn get_probability(&self, home_goals: usize, away_goals: usize) -> Option<f64> {
+        self.grid[home_goals - self.bounds.min_home_goals][away_goals - self.bounds.min_away_goals]
+    }
+
+    // Total probability of the scores shown in the grid
+    fn total_probability(&self) -> f64 {
+        self.grid.iter().flatten().flatten().sum()
+    }
+
+    pub fn print_text(&self) {
+        self.print_table("Final score probabilities (%)");
+        if !self.bounds.tail {
+            println!(
+                "Scores in the grid cover {:.2}% of the probability (use --tail to bucket the rest)",
+                self.total_probability() * 100.0
+            );
+        }
+    }
+
+    /// Prints the probabilities as percentages under `title`
+    pub fn print_table(&self, title: &str) {
+        println!("{}", title);
+        print_goals_table(&self.bounds, |home_goals, away_goals| {
+            match self.get_probability(home_goals, away_goals) {
+                Some(probability) => (format!("{:.2}", probability * 100.0), CellStyle::Neutral),
+                None => (".".to_owned(), CellStyle::Unreachable),
+            }
+        });
+    }
+
+    pub fn to_json(&self) -> HashMap<usize, HashMap<usize, f64>> {
+        let mut map_grid = HashMap::new();
+        for home_goals in self.bounds.home_goals() {
+            let mut row = HashMap::new();
+            for away_goals in self.bounds.away_goals() {
+                if let Some(probability) = self.get_probability(home_goals, away_goals) {
+                    row.insert(away_goals, probability);
+                }
+            }
+            map_grid.insert(home_goals, row);
+        }
+        map_grid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_specifiers::Period;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "Expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_probabilities_match_poisson() {
+        let model = PoissonModel::new(1.5, 1.0).unwrap();
+        let grid = model.get_probability_grid(&GridBounds::square(10), None);
+
+        assert_close(grid.get_probability(0, 0).unwrap(), (-2.5f64).exp());
+        assert_close(
+            grid.get_probability(2, 1).unwrap(),
+            (-1.5f64).exp() * 1.5 * 1.5 / 2.0 * (-1.0f64).exp(),
+        );
+    }
+
+    #[test]
+    fn test_tail_bucket_holds_residual_mass() {
+        let model = PoissonModel::new(2.7, 1.9).unwrap();
+        let mut bounds = GridBounds::square(4);
+        bounds.tail = true;
+        let grid = model.get_probability_grid(&bounds, None);
+
+        assert_close(grid.total_probability(), 1.0);
+    }
+
+    #[test]
+    fn test_in_play_probabilities_scale_with_time_remaining() {
+        let model = PoissonModel::new(1.8, 0.9)
+            .unwrap()
+            .for_minutes_remaining(30.0);
+        let grid = model.get_probability_grid(&GridBounds::square(10), Some(Score::new(1, 0)));
+
+        assert_eq!(grid.get_probability(0, 0), None);
+        // No more goals: both rates are scaled to a third of the match
+        assert_close(grid.get_probability(1, 0).unwrap(), (-0.9f64).exp());
+    }
+
+    #[test]
+    fn test_half_time_full_time_grids_split_final_score_probabilities() {
+        let model = PoissonModel::new(1.4, 1.1).unwrap();
+        let bounds = GridBounds::square(6);
+        let final_scores = model.get_probability_grid(&bounds, None);
+        let by_half_time = model.get_half_time_full_time_probability_grids(&bounds);
+
+        // A home team that never scored can't have led at half time
+        assert_eq!(by_half_time[0].1.get_probability(0, 2), None);
+        for home_goals in bounds.home_goals() {
+            for away_goals in bounds.away_goals() {
+                let total: f64 = by_half_time
+                    .iter()
+                    .filter_map(|(_, grid)| grid.get_probability(home_goals, away_goals))
+                    .sum();
+                assert_close(
+                    total,
+                    final_scores
+                        .get_probability(home_goals, away_goals)
+                        .unwrap(),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_dixon_coles_moves_probability_between_low_scores() {
+        let independent = PoissonModel::new(1.4, 1.1).unwrap();
+        let model = independent.clone().with_rho(-0.1).unwrap();
+        let mut bounds = GridBounds::square(5);
+        bounds.tail = true;
+        let grid = model.get_probability_grid(&bounds, None);
+        let independent_grid = independent.get_probability_grid(&bounds, None);
+
+        assert_close(grid.total_probability(), 1.0);
+        assert_close(
+            grid.get_probability(1, 1).unwrap(),
+            independent_grid.get_probability(1, 1).unwrap() * 1.1,
+        );
+        assert_close(
+            grid.get_probability(2, 2).unwrap(),
+            independent_grid.get_probability(2, 2).unwrap(),
+        );
+        let draws: f64 = model
+            .get_score_probabilities(None)
+            .iter()
+            .filter(|(score, _)| score.home_goals == score.away_goals)
+            .map(|(_, probability)| probability)
+            .sum();
+        assert_close(model.get_result_probabilities(90.0).1, draws);
+        assert!(draws > independent.get_result_probabilities(90.0).1);
+
+        assert!(independent.with_rho(1.5).is_err());
+    }
+
+    #[test]
+    fn test_expected_payoff() {
+        let model = PoissonModel::new(1.2, 1.2).unwrap();
+        // Symmetric teams: backing home or away on a 0 handicap is worth the same
+        let home = model
+            .get_expected_payoff(None, |home_goals, away_goals| {
+                Ok(Rational::from_integer(
+                    (home_goals as i64 - away_goals as i64).signum(),
+                ))
+            })
+            .unwrap();
+        assert_close(home, 0.0);
+    }
+
+    #[test]
+    fn test_half_scales_expected_goals_to_45_minutes() {
+        let half = PoissonModel::new(1.6, 1.2)
+            .unwrap()
+            .for_minutes_remaining(Period::FirstHalf.minutes());
+        assert_eq!(half, PoissonModel::new(0.8, 0.6).unwrap());
+        let grid = half.get_probability_grid(&GridBounds::square(10), None);
+        assert_close(grid.get_probability(0, 0).unwrap(), (-1.4f64).exp());
+    }
+}
@@ -0,0 +1,811 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::ledger::{days_from_date, parse_date};
+use crate::position::read_json_file;
+use crate::probability::PoissonModel;
+use crate::results::{HistoricalMatch, JsonSkippedRow};
+
+// The fit stops once no rating changes by more than this fraction in a sweep
+const RATING_TOLERANCE: f64 = 1e-10;
+const MAX_SWEEPS: usize = 10_000;
+const BISECTION_STEPS: usize = 100;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JsonTeamRating {
+    pub team: String,
+    pub matches: usize,
+    pub attack: f64,
+    pub defence: f64,
+}
+
+/// A ratings file, as saved by the `ratings` command
+#[derive(Serialize, Deserialize)]
+pub struct JsonRatings {
+    pub matches: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub half_life: Option<f64>,
+    pub base_goals: f64,
+    pub home_advantage: f64,
+    pub rho: f64,
+    pub teams: Vec<JsonTeamRating>,
+}
+
+/// A fixture's expected goals and result probabilities under the ratings
+#[derive(Serialize)]
+pub struct JsonFixture {
+    pub home_team: String,
+    pub away_team: String,
+    pub home_xg: f64,
+    pub away_xg: f64,
+    pub home_win: f64,
+    pub draw: f64,
+    pub away_win: f64,
+}
+
+/// The `ratings` command's output: the ratings, as saved, and the fixture asked about
+#[derive(Serialize)]
+pub struct JsonRatingsOutput {
+    #[serde(flatten)]
+    pub ratings: JsonRatings,
+    pub skipped_rows: Vec<JsonSkippedRow>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fixture: Option<JsonFixture>,
+}
+
+/// A team's attack (goals scored) and defence (goals conceded) as multiples of an average
+/// team's, so a defence below 1 is better than average
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamRating {
+    pub team: String,
+    pub matches: usize,
+    pub attack: f64,
+    pub defence: f64,
+}
+
+/// Team ratings fitted to past results by maximum likelihood. The home team's expected goals
+/// are `base_goals * home_advantage * home attack * away defence`, and the away team's
+/// `base_goals * away attack * home defence`, with goals Poisson distributed and, for a
+/// Dixon-Coles fit, the low scores corrected by `rho`. Older matches can count for less, by
+/// halving their weight every `half_life` days before `date`.
+pub struct Ratings {
+    pub matches: usize,
+    pub date: Option<String>,
+    pub half_life: Option<f64>,
+    pub base_goals: f64,
+    pub home_advantage: f64,
+    pub rho: f64,
+    pub teams: Vec<TeamRating>,
+}
+
+// A match used in the fit: the indexes of its teams, its goals and its weight
+struct FitMatch {
+    home: usize,
+    away: usize,
+    home_goals: f64,
+    away_goals: f64,
+    weight: f64,
+}
+
+impl Ratings {
+    /// Fits ratings to the matches played up to `date` (all of them without a date), weighted
+    /// by a `half_life` in days if there is one
+    pub fn fit(
+        matches: &[HistoricalMatch],
+        date: Option<String>,
+        half_life: Option<f64>,
+        dixon_coles: bool,
+    ) -> Result<Self> {
+        if half_life.is_some_and(|half_life| !(half_life.is_finite() && half_life > 0.0)) {
+            bail!("Invalid half-life. Must be a positive number of days.");
+        }
+        // Days from each match to the date the ratings are for
+        let days: Vec<Option<i64>> = matches
+            .iter()
+            .map(|historical_match| match &historical_match.date {
+                Some(date) => days_from_date(date).map(Some),
+                None if date.is_some() || half_life.is_some() => Err(anyhow!(
+                    "The match on line {} has no Date. Time decay and fitting up to a date need the date of every match.",
+                    historical_match.line
+                )),
+                None => Ok(None),
+            })
+            .collect::<Result<_>>()?;
+        let date_days = match &date {
+            Some(date) => Some(days_from_date(date)?),
+            None => days.iter().flatten().max().copied(),
+        };
+
+        let mut teams: Vec<TeamRating> = vec![];
+        let mut team_index = |team: &str| match teams.iter().position(|rating| rating.team == team)
+        {
+            Some(i) => {
+                teams[i].matches += 1;
+                i
+            }
+            None => {
+                teams.push(TeamRating {
+                    team: team.to_owned(),
+                    matches: 1,
+                    attack: 1.0,
+                    defence: 1.0,
+                });
+                teams.len() - 1
+            }
+        };
+        let mut fit_matches = vec![];
+        for (historical_match, days) in matches.iter().zip(&days) {
+            let days_before = match (days, date_days) {
+                (Some(days), Some(date_days)) => date_days - days,
+                _ => 0,
+            };
+            if days_before < 0 {
+                continue;
+            }
+            fit_matches.push(FitMatch {
+                home: team_index(&historical_match.home_team),
+                away: team_index(&historical_match.away_team),
+                home_goals: historical_match.full_time.home_goals as f64,
+                away_goals: historical_match.full_time.away_goals as f64,
+                weight: match half_life {
+                    Some(half_life) => 0.5f64.powf(days_before as f64 / half_life),
+                    None => 1.0,
+                },
+            });
+        }
+        if fit_matches.is_empty() {
+            bail!("There are no matches to fit the ratings to.");
+        }
+        // A team that never scored (or conceded) would be rated 0, which can't give a model
+        for (i, team) in teams.iter().enumerate() {
+            let (mut scored, mut conceded) = (0.0, 0.0);
+            for fit_match in &fit_matches {
+                if fit_match.home == i {
+                    scored += fit_match.weight * fit_match.home_goals;
+                    conceded += fit_match.weight * fit_match.away_goals;
+                } else if fit_match.away == i {
+                    scored += fit_match.weight * fit_match.away_goals;
+                    conceded += fit_match.weight * fit_match.home_goals;
+                }
+            }
+            for (goals, rating, verb) in [
+                (scored, "attack", "scored"),
+                (conceded, "defence", "conceded"),
+            ] {
+                if goals <= 0.0 {
+                    bail!(
+                        "'{}' hasn't {} in the matches the ratings are fitted to, so its {} can't be rated. Fit the ratings to more matches.",
+                        team.team,
+                        verb,
+                        rating
+                    );
+                }
+            }
+        }
+        if fit_matches
+            .iter()
+            .all(|fit_match| fit_match.weight * fit_match.home_goals <= 0.0)
+        {
+            bail!("No home team has scored in the matches the ratings are fitted to, so the home advantage can't be rated. Fit the ratings to more matches.");
+        }
+
+        let mut ratings = Self {
+            matches: fit_matches.len(),
+            date: date.or_else(|| {
+                matches
+                    .iter()
+                    .filter_map(|historical_match| historical_match.date.clone())
+                    .max()
+            }),
+            half_life,
+            base_goals: 1.0,
+            home_advantage: 1.0,
+            rho: 0.0,
+            teams,
+        };
+        ratings.fit_rates(&fit_matches, MAX_SWEEPS)?;
+        if dixon_coles {
+            ratings.fit_rho(&fit_matches);
+        }
+        Ok(ratings)
+    }
+
+    // Expected goals of a fitted match
+    fn rates(&self, fit_match: &FitMatch) -> (f64, f64) {
+        self.team_rates(fit_match.home, fit_match.away)
+    }
+
+    // Expected goals of the teams at these indexes
+    fn team_rates(&self, home: usize, away: usize) -> (f64, f64) {
+        let (home, away) = (&self.teams[home], &self.teams[away]);
+        (
+            self.base_goals * self.home_advantage * home.attack * away.defence,
+            self.base_goals * away.attack * home.defence,
+        )
+    }
+
+    // Maximum likelihood rates by iterative proportional fitting: each parameter in turn is
+    // set to make the expected goals it affects add up to the goals actually scored (weighted),
+    // which is where the Poisson likelihood is highest given the others. Fails if the ratings
+    // are still changing after `max_sweeps` sweeps.
+    fn fit_rates(&mut self, fit_matches: &[FitMatch], max_sweeps: usize) -> Result<()> {
+        for _ in 0..max_sweeps {
+            let mut largest_change: f64 = 0.0;
+            let mut update = |rating: &mut f64, goals: f64, expected_goals: f64| {
+                if expected_goals > 0.0 {
+                    let new_rating = *rating * goals / expected_goals;
+                    largest_change =
+                        largest_change.max((new_rating - *rating).abs() / rating.max(1e-12));
+                    *rating = new_rating;
+                }
+            };
+
+            for i in 0..self.teams.len() {
+                let (mut scored, mut expected_scored) = (0.0, 0.0);
+                for fit_match in fit_matches {
+                    let (home_rate, away_rate) = self.rates(fit_match);
+                    if fit_match.home == i {
+                        scored += fit_match.weight * fit_match.home_goals;
+                        expected_scored += fit_match.weight * home_rate;
+                    } else if fit_match.away == i {
+                        scored += fit_match.weight * fit_match.away_goals;
+                        expected_scored += fit_match.weight * away_rate;
+                    }
+                }
+                update(&mut self.teams[i].attack, scored, expected_scored);
+
+                let (mut conceded, mut expected_conceded) = (0.0, 0.0);
+                for fit_match in fit_matches {
+                    let (home_rate, away_rate) = self.rates(fit_match);
+                    if fit_match.home == i {
+                        conceded += fit_match.weight * fit_match.away_goals;
+                        expected_conceded += fit_match.weight * away_rate;
+                    } else if fit_match.away == i {
+                        conceded += fit_match.weight * fit_match.home_goals;
+                        expected_conceded += fit_match.weight * home_rate;
+                    }
+                }
+                update(&mut self.teams[i].defence, conceded, expected_conceded);
+            }
+
+            let (mut home_goals, mut expected_home_goals) = (0.0, 0.0);
+            for fit_match in fit_matches {
+                home_goals += fit_match.weight * fit_match.home_goals;
+                expected_home_goals += fit_match.weight * self.rates(fit_match).0;
+            }
+            update(&mut self.home_advantage, home_goals, expected_home_goals);
+
+            // An average team has ratings of 1, which leaves the scale to the base goals
+            let team_count = self.teams.len() as f64;
+            let mean_attack = self.teams.iter().map(|team| team.attack).sum::<f64>() / team_count;
+            let mean_defence = self.teams.iter().map(|team| team.defence).sum::<f64>() / team_count;
+            if mean_attack > 0.0 && mean_defence > 0.0 {
+                for team in self.teams.iter_mut() {
+                    team.attack /= mean_attack;
+                    team.defence /= mean_defence;
+                }
+                self.base_goals *= mean_attack * mean_defence;
+            }
+
+            let (mut goals, mut expected_goals) = (0.0, 0.0);
+            for fit_match in fit_matches {
+                let (home_rate, away_rate) = self.rates(fit_match);
+                goals += fit_match.weight * (fit_match.home_goals + fit_match.away_goals);
+                expected_goals += fit_match.weight * (home_rate + away_rate);
+            }
+            update(&mut self.base_goals, goals, expected_goals);
+
+            if largest_change < RATING_TOLERANCE {
+                return Ok(());
+            }
+        }
+        bail!(
+            "The ratings didn't settle after {} passes over the matches. Try fitting them to more matches.",
+            max_sweeps
+        )
+    }
+
+    // The Dixon-Coles rho with the highest likelihood given the fitted rates. Only 0-0, 1-0,
+    // 0-1 and 1-1 depend on it, each through a factor 1 + c * rho, so the log likelihood is
+    // concave and its slope is found by bisection, within the range where no factor is
+    // negative in a match between any two of the teams.
+    fn fit_rho(&mut self, fit_matches: &[FitMatch]) {
+        let mut terms = vec![];
+        for fit_match in fit_matches {
+            let (home_rate, away_rate) = self.rates(fit_match);
+            let c = match (fit_match.home_goals as usize, fit_match.away_goals as usize) {
+                (0, 0) => -home_rate * away_rate,
+                (0, 1) => home_rate,
+                (1, 0) => away_rate,
+                (1, 1) => -1.0,
+                _ => continue,
+            };
+            if c != 0.0 {
+                terms.push((fit_match.weight, c));
+            }
+        }
+        let (low, high) = self.get_rho_bounds();
+        let slope = |rho: f64| -> f64 {
+            terms
+                .iter()
+                .map(|(weight, c)| weight * c / (1.0 + c * rho))
+                .sum()
+        };
+        // Stay off the edges, where a factor is 0 and the log likelihood is minus infinity
+        let margin = (high - low) * 1e-9;
+        let (mut low, mut high) = (low + margin, high - margin);
+        if slope(low) <= 0.0 {
+            self.rho = low;
+            return;
+        }
+        if slope(high) >= 0.0 {
+            self.rho = high;
+            return;
+        }
+        for _ in 0..BISECTION_STEPS {
+            let middle = (low + high) / 2.0;
+            match slope(middle) > 0.0 {
+                true => low = middle,
+                false => high = middle,
+            }
+        }
+        self.rho = (low + high) / 2.0;
+    }
+
+    // The range of rho where no Dixon-Coles factor is negative in a match between any two of
+    // the teams
+    fn get_rho_bounds(&self) -> (f64, f64) {
+        let (mut low, mut high) = (-1.0f64, 1.0f64);
+        for home in 0..self.teams.len() {
+            for away in 0..self.teams.len() {
+                let (home_rate, away_rate) = self.team_rates(home, away);
+                if home_rate > 0.0 {
+                    low = low.max(-1.0 / home_rate);
+                }
+                if away_rate > 0.0 {
+                    low = low.max(-1.0 / away_rate);
+                }
+                if home_rate * away_rate > 0.0 {
+                    high = high.min(1.0 / (home_rate * away_rate));
+                }
+            }
+        }
+        (low, high)
+    }
+
+    fn get_team(&self, team: &str) -> Result<&TeamRating> {
+        self.teams
+            .iter()
+            .find(|rating| rating.team == team)
+            .ok_or_else(|| {
+                anyhow!(
+                    "No ratings for '{}'. Teams are named as in the results, e.g. '{}'.",
+                    team,
+                    self.teams
+                        .first()
+                        .map(|rating| rating.team.as_str())
+                        .unwrap_or("")
+                )
+            })
+    }
+
+    /// Expected goals of `home_team` and `away_team` in a match between them
+    pub fn get_expected_goals(&self, home_team: &str, away_team: &str) -> Result<(f64, f64)> {
+        let (home, away) = (self.get_team(home_team)?, self.get_team(away_team)?);
+        Ok((
+            self.base_goals * self.home_advantage * home.attack * away.defence,
+            self.base_goals * away.attack * home.defence,
+        ))
+    }
+
+    /// The probability model of a match between `home_team` and `away_team`
+    pub fn get_model(&self, home_team: &str, away_team: &str) -> Result<PoissonModel> {
+        let (home_xg, away_xg) = self.get_expected_goals(home_team, away_team)?;
+        PoissonModel::new(home_xg, away_xg)?.with_rho(self.rho)
+    }
+
+    /// Teams from the strongest (attack over defence) to the weakest
+    pub fn get_table(&self) -> Vec<&TeamRating> {
+        let mut table: Vec<&TeamRating> = self.teams.iter().collect();
+        table.sort_by(|a, b| (b.attack / b.defence).total_cmp(&(a.attack / a.defence)));
+        table
+    }
+
+    /// Reads a ratings file, checking it could have been written by `write`, so a file edited
+    /// by hand can't give an invalid model
+    pub fn read(path: &str) -> Result<Self> {
+        let json: JsonRatings = read_json_file(path)?;
+        Ratings::from_json(json).map_err(|e| anyhow!("{} ({})", e, path))
+    }
+
+    fn from_json(json: JsonRatings) -> Result<Self> {
+        if json.teams.is_empty() {
+            bail!("The ratings file has no teams.");
+        }
+        let is_positive = |value: f64| value.is_finite() && value > 0.0;
+        if !is_positive(json.base_goals) || !is_positive(json.home_advantage) {
+            bail!(
+                "Invalid ratings file. The base goals and home advantage must be positive numbers."
+            );
+        }
+        for (i, team) in json.teams.iter().enumerate() {
+            if !is_positive(team.attack) || !is_positive(team.defence) {
+                bail!(
+                    "Invalid ratings for '{}'. The attack and defence must be positive numbers.",
+                    team.team
+                );
+            }
+            if json.teams[..i].iter().any(|other| other.team == team.team) {
+                bail!("'{}' has more than one rating.", team.team);
+            }
+        }
+        if json
+            .half_life
+            .is_some_and(|half_life| !is_positive(half_life))
+        {
+            bail!("Invalid half-life. Must be a positive number of days.");
+        }
+        if let Some(date) = &json.date {
+            parse_date(date)?;
+        }
+        let ratings = Self {
+            matches: json.matches,
+            date: json.date,
+            half_life: json.half_life,
+            base_goals: json.base_goals,
+            home_advantage: json.home_advantage,
+            rho: json.rho,
+            teams: json
+                .teams
+                .into_iter()
+                .map(|team| TeamRating {
+                    team: team.team,
+                    matches: team.matches,
+                    attack: team.attack,
+                    defence: team.defence,
+                })
+                .collect(),
+        };
+        let (low, high) = ratings.get_rho_bounds();
+        if !(low..=high).contains(&ratings.rho) {
+            bail!(
+                "Invalid rho {}. With these ratings it must be between {:.4} and {:.4}, to keep the probabilities of 0-0, 1-0, 0-1 and 1-1 positive in every match.",
+                ratings.rho,
+                low,
+                high
+            );
+        }
+        Ok(ratings)
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(&self.to_json())?)
+            .map_err(|e| anyhow!("Couldn't write '{}': {}", path, e))
+    }
+
+    pub fn print_text(&self) {
+        let mut fitted = format!("Ratings from {} matches", self.matches);
+        if let Some(date) = &self.date {
+            fitted += &format!(" up to {}", date);
+        }
+        if let Some(half_life) = self.half_life {
+            fitted += &format!(", weighted with a half-life of {} days", half_life);
+        }
+        println!("{}.", fitted);
+        println!(
+            "Home advantage: {:.3}. Base goals (an average away team's expected goals): {:.3}.",
+            self.home_advantage, self.base_goals
+        );
+        if self.rho != 0.0 {
+            println!("Dixon-Coles rho: {:.4}", self.rho);
+        }
+        let table = self.get_table();
+        let name_width = table
+            .iter()
+            .map(|rating| rating.team.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Team".len());
+        println!();
+        println!(
+            "{:<name_width$} {:>7} {:>7} {:>7}",
+            "Team", "Matches", "Attack", "Defence"
+        );
+        for rating in table {
+            println!(
+                "{:<name_width$} {:>7} {:>7.3} {:>7.3}",
+                rating.team, rating.matches, rating.attack, rating.defence
+            );
+        }
+    }
+
+    pub fn to_json(&self) -> JsonRatings {
+        JsonRatings {
+            matches: self.matches,
+            date: self.date.clone(),
+            half_life: self.half_life,
+            base_goals: self.base_goals,
+            home_advantage: self.home_advantage,
+            rho: self.rho,
+            teams: self
+                .get_table()
+                .into_iter()
+                .map(|rating| JsonTeamRating {
+                    team: rating.team.clone(),
+                    matches: rating.matches,
+                    attack: rating.attack,
+                    defence: rating.defence,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{parse_historical_matches, CsvTable};
+
+    #[test]
+    fn test_fitted_expected_goals_match_goals() {
+        let table = CsvTable::parse(
+            "Date,HomeTeam,AwayTeam,FTHG,FTAG\n\
+             01/08/2024,A,B,3,1\n\
+             08/08/2024,B,C,1,1\n\
+             15/08/2024,C,A,0,2\n\
+             22/08/2024,B,A,2,2\n\
+             29/08/2024,C,B,1,0\n\
+             05/09/2024,A,C,4,0\n\
+             12/09/2024,B,C,0,0\n",
+        )
+        .unwrap();
+        let matches = parse_historical_matches(&table).unwrap().matches;
+        let ratings = Ratings::fit(&matches, None, None, true).unwrap();
+        assert_eq!(ratings.matches, 7);
+        assert_eq!(ratings.date.as_deref(), Some("2024-09-12"));
+        assert_eq!(ratings.get_table()[0].team, "A");
+        assert!(ratings.home_advantage > 1.0);
+        assert!(ratings.rho > -1.0 && ratings.rho < 1.0);
+
+        // At the maximum likelihood each team's expected goals, for and against, add up to
+        // its goals
+        for team in ["A", "B", "C"] {
+            let (mut scored, mut expected_scored) = (0.0, 0.0);
+            let (mut conceded, mut expected_conceded) = (0.0, 0.0);
+            for historical_match in &matches {
+                let (home_xg, away_xg) = ratings
+                    .get_expected_goals(&historical_match.home_team, &historical_match.away_team)
+                    .unwrap();
+                let (home_goals, away_goals) = (
+                    historical_match.full_time.home_goals as f64,
+                    historical_match.full_time.away_goals as f64,
+                );
+                if historical_match.home_team == team {
+                    (scored, expected_scored) = (scored + home_goals, expected_scored + home_xg);
+                    (conceded, expected_conceded) =
+                        (conceded + away_goals, expected_conceded + away_xg);
+                } else if historical_match.away_team == team {
+                    (scored, expected_scored) = (scored + away_goals, expected_scored + away_xg);
+                    (conceded, expected_conceded) =
+                        (conceded + home_goals, expected_conceded + home_xg);
+                }
+            }
+            assert!((scored - expected_scored).abs() < 1e-6);
+            assert!((conceded - expected_conceded).abs() < 1e-6);
+        }
+        assert!(ratings.get_model("A", "D").is_err());
+
+        // Later matches are left out, and older ones count for less
+        let decayed =
+            Ratings::fit(&matches, Some("2024-08-22".to_owned()), Some(7.0), false).unwrap();
+        assert_eq!(decayed.matches, 4);
+        assert_eq!(decayed.date.as_deref(), Some("2024-08-22"));
+        assert_eq!(decayed.rho, 0.0);
+    }
+
+    fn fit_csv(csv: &str, half_life: Option<f64>, dixon_coles: bool) -> Ratings {
+        let table = CsvTable::parse(csv).unwrap();
+        let matches = parse_historical_matches(&table).unwrap().matches;
+        Ratings::fit(&matches, None, half_life, dixon_coles).unwrap()
+    }
+
+    // Ratings of 1 for `teams` and a double round robin between them, where each side scores
+    // exactly its expected goals under `truth`
+    fn synthetic(truth: &Ratings) -> (Ratings, Vec<FitMatch>) {
+        let mut fit_matches = vec![];
+        for home in 0..truth.teams.len() {
+            for away in 0..truth.teams.len() {
+                if home != away {
+                    let (home_goals, away_goals) = truth.team_rates(home, away);
+                    fit_matches.push(FitMatch {
+                        home,
+                        away,
+                        home_goals,
+                        away_goals,
+                        weight: 1.0,
+                    });
+                }
+            }
+        }
+        let unfitted = Ratings {
+            matches: fit_matches.len(),
+            date: None,
+            half_life: None,
+            base_goals: 1.0,
+            home_advantage: 1.0,
+            rho: 0.0,
+            teams: truth
+                .teams
+                .iter()
+                .map(|team| TeamRating {
+                    attack: 1.0,
+                    defence: 1.0,
+                    ..team.clone()
+                })
+                .collect(),
+        };
+        (unfitted, fit_matches)
+    }
+
+    fn truth() -> Ratings {
+        // Attacks and defences average 1, as fitted ratings do
+        let teams = [
+            ("A", 1.4, 0.7),
+            ("B", 1.1, 0.9),
+            ("C", 0.9, 1.1),
+            ("D", 0.6, 1.3),
+        ];
+        Ratings {
+            matches: 0,
+            date: None,
+            half_life: None,
+            base_goals: 1.2,
+            home_advantage: 1.3,
+            rho: 0.0,
+            teams: teams
+                .iter()
+                .map(|(team, attack, defence)| TeamRating {
+                    team: team.to_string(),
+                    matches: 6,
+                    attack: *attack,
+                    defence: *defence,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_recovers_known_ratings() {
+        let truth = truth();
+        let (mut ratings, fit_matches) = synthetic(&truth);
+        ratings.fit_rates(&fit_matches, MAX_SWEEPS).unwrap();
+        assert!((ratings.base_goals - truth.base_goals).abs() < 1e-6);
+        assert!((ratings.home_advantage - truth.home_advantage).abs() < 1e-6);
+        for (fitted, team) in ratings.teams.iter().zip(&truth.teams) {
+            assert!((fitted.attack - team.attack).abs() < 1e-6);
+            assert!((fitted.defence - team.defence).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_fit_fails_at_the_sweep_limit() {
+        let (mut ratings, fit_matches) = synthetic(&truth());
+        assert!(ratings.fit_rates(&fit_matches, 2).is_err());
+        assert!(ratings.fit_rates(&fit_matches, MAX_SWEEPS).is_ok());
+    }
+
+    #[test]
+    fn test_time_decay_weighs_recent_form() {
+        // A dominated in August and B in October
+        let csv = "Date,HomeTeam,AwayTeam,FTHG,FTAG\n\
+                   01/08/2024,A,B,4,0\n\
+                   08/08/2024,B,A,0,3\n\
+                   15/08/2024,A,B,3,1\n\
+                   01/10/2024,A,B,0,2\n\
+                   08/10/2024,B,A,2,0\n";
+        let ratings = fit_csv(csv, None, false);
+        let decayed = fit_csv(csv, Some(7.0), false);
+        assert_eq!(ratings.get_table()[0].team, "A");
+        assert_eq!(decayed.get_table()[0].team, "B");
+        let attack = |ratings: &Ratings| ratings.get_team("A").unwrap().attack;
+        assert!(attack(&decayed) < attack(&ratings));
+    }
+
+    #[test]
+    fn test_rho_stays_inside_its_bounds() {
+        // Only draws of 0-0 and 1-1 push rho as low as it goes, and only 1-0 and 0-1 as high
+        for (csv, at_low_bound) in [
+            (
+                "HomeTeam,AwayTeam,FTHG,FTAG\n\
+                 A,B,1,1\nB,C,0,0\nC,A,1,1\nB,A,0,0\nC,B,1,1\nA,C,0,0\n",
+                true,
+            ),
+            (
+                "HomeTeam,AwayTeam,FTHG,FTAG\n\
+                 A,B,1,0\nB,C,0,1\nC,A,1,0\nB,A,1,0\nC,B,0,1\nA,C,0,1\n",
+                false,
+            ),
+        ] {
+            let ratings = fit_csv(csv, None, true);
+            let (low, high) = ratings.get_rho_bounds();
+            assert!(low < ratings.rho && ratings.rho < high);
+            match at_low_bound {
+                true => assert!(ratings.rho - low < 1e-6),
+                false => assert!(high - ratings.rho < 1e-6),
+            }
+            for home in ["A", "B", "C"] {
+                for away in ["A", "B", "C"] {
+                    assert!(ratings.get_model(home, away).is_ok());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reads_only_valid_ratings() {
+        let valid = || truth().to_json();
+        assert!(Ratings::from_json(valid()).is_ok());
+
+        let mut no_teams = valid();
+        no_teams.teams.clear();
+        let mut zero_base_goals = valid();
+        zero_base_goals.base_goals = 0.0;
+        let mut negative_attack = valid();
+        negative_attack.teams[0].attack = -1.0;
+        let mut duplicate_team = valid();
+        duplicate_team.teams[1].team = duplicate_team.teams[0].team.clone();
+        // The most home goals expected, the strongest attack against the weakest defence, are
+        // 1.2 * 1.3 * 1.4 * 1.3, so rho can't be below about -0.35, and the most goals expected
+        // for both teams together keep it below about 0.55
+        let mut high_rho = valid();
+        high_rho.rho = 0.6;
+        let mut low_rho = valid();
+        low_rho.rho = -0.5;
+        for invalid in [
+            no_teams,
+            zero_base_goals,
+            negative_attack,
+            duplicate_team,
+            high_rho,
+            low_rho,
+        ] {
+            assert!(Ratings::from_json(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_saved_ratings_read_back() {
+        let csv = "HomeTeam,AwayTeam,FTHG,FTAG\n\
+                   A,B,2,1\nB,C,1,1\nC,A,0,2\nB,A,1,3\nC,B,2,0\nA,C,1,0\n";
+        let ratings = fit_csv(csv, None, true);
+        let path =
+            std::env::temp_dir().join(format!("bet_grid_ratings_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        ratings.write(path).unwrap();
+        let read = Ratings::read(path);
+        fs::remove_file(path).unwrap();
+        let read = read.unwrap();
+        assert_eq!(read.teams.len(), 3);
+        assert!((read.rho - ratings.rho).abs() < 1e-12);
+        let (home_goals, away_goals) = read.get_expected_goals("A", "C").unwrap();
+        let (fitted_home_goals, fitted_away_goals) = ratings.get_expected_goals("A", "C").unwrap();
+        assert!((home_goals - fitted_home_goals).abs() < 1e-12);
+        assert!((away_goals - fitted_away_goals).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_fit_rejects_teams_that_would_be_rated_zero() {
+        // C never scores, so its attack would fit to 0, which no ratings file can hold
+        let table = CsvTable::parse(
+            "HomeTeam,AwayTeam,FTHG,FTAG\n\
+             A,B,2,1\nB,C,1,0\nC,A,0,2\nB,A,1,3\nC,B,0,0\nA,C,1,0\n",
+        )
+        .unwrap();
+        let matches = parse_historical_matches(&table).unwrap().matches;
+        let error = Ratings::fit(&matches, None, None, false).err().unwrap();
+        assert!(error.to_string().contains("'C' hasn't scored"));
+    }
+}
@@ -0,0 +1,337 @@
+use anyhow::{anyhow, bail, Result};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Neg;
+use std::str::FromStr;
+
+/// Exact fraction used for payoffs, so settlement never depends on float rounding.
+/// Always stored in lowest terms with a positive denominator. Arithmetic is checked: a result
+/// that doesn't fit (e.g. from long chains of very precise odds and stakes) is an error rather
+/// than a wrong or panicking calculation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numerator: i64,
+    denominator: i64,
+}
+
+// Digits shown for fractions that don't have an exact decimal form (e.g. 1/3)
+const MAX_DISPLAY_DECIMALS: usize = 6;
+// Decimals accepted when parsing, which keeps the denominator well within range
+const MAX_PARSE_DECIMALS: usize = 9;
+// Most decimals written out exactly, so the scaled numerator fits in an i128
+const MAX_EXACT_DISPLAY_DECIMALS: usize = 18;
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl Rational {
+    pub fn new(numerator: i64, denominator: i64) -> Result<Self> {
+        Rational::reduce(numerator as i128, denominator as i128)
+    }
+
+    /// `quarters` quarters, e.g. a quarter-goal line or a half-won payoff (two quarters)
+    pub fn from_quarters(quarters: i64) -> Self {
+        // The gcd is 1, 2 or 4, so this can't overflow
+        let divisor = gcd(quarters as i128, 4) as i64;
+        Self {
+            numerator: quarters / divisor,
+            denominator: 4 / divisor,
+        }
+    }
+
+    pub fn from_integer(value: i64) -> Self {
+        Self {
+            numerator: value,
+            denominator: 1,
+        }
+    }
+
+    pub fn zero() -> Self {
+        Rational::from_integer(0)
+    }
+
+    pub fn one() -> Self {
+        Rational::from_integer(1)
+    }
+
+    fn reduce(numerator: i128, denominator: i128) -> Result<Self> {
+        if denominator == 0 {
+            bail!("Division by zero.");
+        }
+        let divisor = gcd(numerator, denominator).max(1) * denominator.signum();
+        let (numerator, denominator) = (numerator / divisor, denominator / divisor);
+        // i64::MIN is left out so that negating never overflows
+        match (i64::try_from(numerator), i64::try_from(denominator)) {
+            (Ok(numerator), Ok(denominator)) if numerator != i64::MIN => Ok(Self {
+                numerator,
+                denominator,
+            }),
+            _ => bail!(
+                "The amounts are too large or have too many decimals to calculate exactly. Try fewer decimals in the odds, stakes and commission."
+            ),
+        }
+    }
+
+    pub fn checked_add(self, other: Rational) -> Result<Rational> {
+        Rational::reduce(
+            self.numerator as i128 * other.denominator as i128
+                + other.numerator as i128 * self.denominator as i128,
+            self.denominator as i128 * other.denominator as i128,
+        )
+    }
+
+    pub fn checked_sub(self, other: Rational) -> Result<Rational> {
+        self.checked_add(-other)
+    }
+
+    pub fn checked_mul(self, other: Rational) -> Result<Rational> {
+        Rational::reduce(
+            self.numerator as i128 * other.numerator as i128,
+            self.denominator as i128 * other.denominator as i128,
+        )
+    }
+
+    pub fn checked_div(self, other: Rational) -> Result<Rational> {
+        Rational::reduce(
+            self.numerator as i128 * other.denominator as i128,
+            self.denominator as i128 * other.numerator as i128,
+        )
+    }
+
+    /// Sum of `values`, failing if it doesn't fit
+    pub fn checked_sum<I>(values: I) -> Result<Rational>
+    where
+        I: IntoIterator<Item = Rational>,
+    {
+        values
+            .into_iter()
+            .try_fold(Rational::zero(), |sum, value| sum.checked_add(value))
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.numerator > 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.numerator < 0
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    // Number of decimals needed to show the value exactly, if it has a finite decimal form
+    fn exact_decimals(&self) -> Option<usize> {
+        let mut denominator = self.denominator;
+        let (mut twos, mut fives) = (0, 0);
+        while denominator % 2 == 0 {
+            denominator /= 2;
+            twos += 1;
+        }
+        while denominator % 5 == 0 {
+            denominator /= 5;
+            fives += 1;
+        }
+        match denominator {
+            1 => Some(usize::max(twos, fives)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.exact_decimals() {
+            Some(0) => write!(f, "{}", self.numerator),
+            Some(decimals) if decimals <= MAX_EXACT_DISPLAY_DECIMALS => {
+                // Scale to an integer number of 10^-decimals units and place the point by hand
+                let scaled =
+                    self.numerator as i128 * 10i128.pow(decimals as u32) / self.denominator as i128;
+                let sign = if scaled < 0 { "-" } else { "" };
+                let digits = format!("{:0>width$}", scaled.abs(), width = decimals + 1);
+                let (whole, fraction) = digits.split_at(digits.len() - decimals);
+                write!(f, "{}{}.{}", sign, whole, fraction)
+            }
+            _ => {
+                let rounded = format!("{:.*}", MAX_DISPLAY_DECIMALS, self.to_f64());
+                write!(f, "{}", rounded.trim_end_matches('0').trim_end_matches('.'))
+            }
+        }
+    }
+}
+
+impl FromStr for Rational {
+    type Err = anyhow::Error;
+
+    /// Parses a decimal such as "2.02", "-0.5" or "10" exactly
+    fn from_str(input: &str) -> Result<Rational> {
+        let invalid = || {
+            anyhow!(
+                "Invalid number '{}'. Expected a decimal, for example 2.02.",
+                input
+            )
+        };
+
+        let trimmed = input.trim();
+        let (sign, unsigned) = match trimmed.strip_prefix('-') {
+            Some(unsigned) => (-1, unsigned),
+            None => (1, trimmed),
+        };
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty())
+            || !all_digits(whole)
+            || !all_digits(fraction)
+            || fraction.len() > MAX_PARSE_DECIMALS
+        {
+            return Err(invalid());
+        }
+
+        let whole: i64 = match whole {
+            "" => 0,
+            whole => whole.parse().map_err(|_| invalid())?,
+        };
+        let fraction = match fraction {
+            "" => Rational::zero(),
+            fraction => Rational::new(
+                fraction.parse().map_err(|_| invalid())?,
+                10i64.pow(fraction.len() as u32),
+            )?,
+        };
+        Rational::from_integer(whole)
+            .checked_add(fraction)?
+            .checked_mul(Rational::from_integer(sign))
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Self {
+            numerator: -self.numerator,
+            denominator: self.denominator,
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> Ordering {
+        (self.numerator as i128 * other.denominator as i128)
+            .cmp(&(other.numerator as i128 * self.denominator as i128))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduces_to_lowest_terms() {
+        let half = Rational::new(-2, -4).unwrap();
+        assert_eq!((half.numerator, half.denominator), (1, 2));
+        assert_eq!(Rational::new(3, -6).unwrap(), Rational::from_quarters(-2));
+    }
+
+    #[test]
+    fn test_arithmetic_is_exact() {
+        let tenth = Rational::new(1, 10).unwrap();
+        let sum = Rational::checked_sum(vec![tenth; 10]).unwrap();
+        assert_eq!(sum, Rational::one());
+        assert_eq!(
+            Rational::one()
+                .checked_sub(Rational::from_quarters(2))
+                .unwrap(),
+            Rational::from_quarters(2)
+        );
+        assert_eq!(
+            Rational::from_quarters(2)
+                .checked_mul(Rational::new(2, 3).unwrap())
+                .unwrap(),
+            Rational::new(1, 3).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rejects_zero_denominators_and_overflow() {
+        assert!(Rational::new(1, 0).is_err());
+        let huge = Rational::from_integer(i64::MAX);
+        assert!(huge.checked_add(Rational::one()).is_err());
+        assert!(huge.checked_mul(Rational::from_integer(2)).is_err());
+        let precise = Rational::new(1, i64::MAX).unwrap();
+        assert!(precise
+            .checked_add(Rational::new(1, i64::MAX - 1).unwrap())
+            .is_err());
+        assert!(Rational::new(i64::MIN, 1).is_err());
+    }
+
+    #[test]
+    fn test_from_quarters() {
+        assert_eq!(Rational::from_quarters(6), Rational::new(3, 2).unwrap());
+        assert_eq!(Rational::from_quarters(-1), Rational::new(-1, 4).unwrap());
+        assert_eq!(Rational::from_quarters(8), Rational::from_integer(2));
+        assert_eq!(Rational::from_quarters(0), Rational::zero());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Rational::from_integer(-1).to_string(), "-1");
+        assert_eq!(Rational::new(1, 2).unwrap().to_string(), "0.5");
+        assert_eq!(Rational::new(-1, 2).unwrap().to_string(), "-0.5");
+        assert_eq!(Rational::new(-1, 40).unwrap().to_string(), "-0.025");
+        assert_eq!(Rational::new(1275, 100).unwrap().to_string(), "12.75");
+        assert_eq!(Rational::new(1, 3).unwrap().to_string(), "0.333333");
+        // Up to 18 decimals are written out exactly, beyond that they're rounded
+        assert_eq!(
+            Rational::new(1, 1 << 16).unwrap().to_string(),
+            "0.0000152587890625"
+        );
+        assert_eq!(Rational::new(1, 1 << 20).unwrap().to_string(), "0.000001");
+        assert_eq!(Rational::new(1, 1 << 62).unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn test_parses_decimals_exactly() {
+        assert_eq!(
+            Rational::from_str("2.02").unwrap(),
+            Rational::new(101, 50).unwrap()
+        );
+        assert_eq!(
+            Rational::from_str("-0.5").unwrap(),
+            Rational::new(-1, 2).unwrap()
+        );
+        assert_eq!(
+            Rational::from_str(".25").unwrap(),
+            Rational::new(1, 4).unwrap()
+        );
+        assert_eq!(
+            Rational::from_str("10").unwrap(),
+            Rational::from_integer(10)
+        );
+        for input in ["", ".", "1.2.3", "abc", "1e3", "0.1234567890"] {
+            assert!(
+                Rational::from_str(input).is_err(),
+                "Expected '{}' to be rejected",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(Rational::new(1, 3).unwrap() > Rational::new(1, 4).unwrap());
+        assert!(Rational::new(-1, 2).unwrap() < Rational::zero());
+        assert!(Rational::new(-1, 2).unwrap().is_negative());
+    }
+}
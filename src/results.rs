@@ -0,0 +1,309 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+
+use crate::ledger::parse_date;
+use crate::probability::PoissonModel;
+use crate::rational::Rational;
+use crate::score::Score;
+
+/// A CSV file with a header row. Fields may be quoted, with "" for a quote inside a quoted
+/// field. Rows with no values at all (football-data files often end with some) are dropped.
+pub struct CsvTable {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl CsvTable {
+    pub fn read(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Couldn't read the CSV file '{}': {}", path, e))?;
+        CsvTable::parse(&contents).map_err(|e| anyhow!("{} ({})", e, path))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut lines = contents.trim_start_matches('\u{feff}').lines();
+        let headers = match lines.next() {
+            Some(header) => CsvTable::parse_line(header),
+            None => bail!("The CSV file is empty. The first line should name the columns."),
+        };
+        let rows = lines
+            .map(CsvTable::parse_line)
+            .filter(|row| row.iter().any(|value| !value.is_empty()))
+            .collect();
+        Ok(Self { headers, rows })
+    }
+
+    // Splits a line into its fields, unquoting quoted fields
+    fn parse_line(line: &str) -> Vec<String> {
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = line.trim_end_matches('\r').chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => fields.push(std::mem::take(&mut field).trim().to_owned()),
+                _ => field.push(c),
+            }
+        }
+        fields.push(field.trim().to_owned());
+        fields
+    }
+}
+
+/// A played match from a results file, in the football-data.co.uk column layout: HomeTeam,
+/// AwayTeam, FTHG and FTAG (full-time goals), and optionally Date, HTHG and HTAG
+/// (half-time goals). Any other columns, such as odds, are kept by name.
+pub struct HistoricalMatch {
+    // Line of the match in the file, for errors
+    pub line: usize,
+    pub date: Option<String>,
+    pub home_team: String,
+    pub away_team: String,
+    pub full_time: Score,
+    pub half_time: Option<Score>,
+    values: HashMap<String, String>,
+}
+
+impl HistoricalMatch {
+    fn from_row(headers: &[String], row: &[String], line: usize) -> Result<Self> {
+        let values: HashMap<String, String> = headers
+            .iter()
+            .zip(row)
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(header, value)| (header.clone(), value.clone()))
+            .collect();
+        let text = |column: &str| {
+            values
+                .get(column)
+                .cloned()
+                .ok_or_else(|| anyhow!("The match on line {} has no '{}' value.", line, column))
+        };
+        let goals = |column: &str| -> Result<usize> {
+            text(column)?.parse().map_err(|_| {
+                anyhow!(
+                    "Invalid '{}' value on line {}. Goals must be a whole number.",
+                    column,
+                    line
+                )
+            })
+        };
+        let half_time = match (values.contains_key("HTHG"), values.contains_key("HTAG")) {
+            (true, true) => Some(Score::new(goals("HTHG")?, goals("HTAG")?)),
+            _ => None,
+        };
+        let date = match values.get("Date") {
+            Some(date) => {
+                Some(parse_results_date(date).map_err(|e| anyhow!("{} (line {})", e, line))?)
+            }
+            None => None,
+        };
+        Ok(Self {
+            line,
+            date,
+            home_team: text("HomeTeam")?,
+            away_team: text("AwayTeam")?,
+            full_time: Score::new(goals("FTHG")?, goals("FTAG")?),
+            half_time,
+            values,
+        })
+    }
+
+    /// The value in `column`, if the match has one
+    pub fn get(&self, column: &str) -> Option<&str> {
+        self.values.get(column).map(String::as_str)
+    }
+
+    /// The number in `column`, if the match has one
+    pub fn get_number(&self, column: &str) -> Result<Option<Rational>> {
+        match self.get(column) {
+            None => Ok(None),
+            Some(value) => Rational::from_str(value).map(Some).map_err(|_| {
+                anyhow!(
+                    "Invalid '{}' value '{}' on line {}. Expected a decimal.",
+                    column,
+                    value,
+                    self.line
+                )
+            }),
+        }
+    }
+
+    /// The model of the match from its pre-match expected goals, in the HXG and AXG columns,
+    /// with the Dixon-Coles correction `rho`
+    pub fn get_model(&self, rho: f64) -> Result<Option<PoissonModel>> {
+        match (self.get_number("HXG")?, self.get_number("AXG")?) {
+            (Some(home_xg), Some(away_xg)) => {
+                let model = PoissonModel::new(home_xg.to_f64(), away_xg.to_f64())
+                    .and_then(|model| model.with_rho(rho))
+                    .map_err(|e| anyhow!("{} (line {})", e, self.line))?;
+                Ok(Some(model))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// "Home v Away"
+    pub fn get_name(&self) -> String {
+        format!("{} v {}", self.home_team, self.away_team)
+    }
+}
+
+/// A row of a results file that was left out, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedRow {
+    pub line: usize,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+pub struct JsonSkippedRow {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl SkippedRow {
+    pub fn to_json(&self) -> JsonSkippedRow {
+        JsonSkippedRow {
+            line: self.line,
+            reason: self.reason.clone(),
+        }
+    }
+}
+
+/// Lists the skipped rows, if there are any, for text output
+pub fn print_skipped_rows(skipped: &[SkippedRow]) {
+    if !skipped.is_empty() {
+        println!("{} rows skipped:", skipped.len());
+        for skipped in skipped {
+            println!("  {}", skipped.reason);
+        }
+    }
+}
+
+/// The matches of a results file, and the rows that couldn't be read as one (a fixture with
+/// no score yet, a score that isn't a number, ...)
+pub struct HistoricalMatches {
+    pub columns: Vec<String>,
+    pub matches: Vec<HistoricalMatch>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+impl HistoricalMatches {
+    pub fn has_column(&self, column: &str) -> bool {
+        self.columns.iter().any(|other| other == column)
+    }
+}
+
+/// Reads the matches of a results file
+pub fn read_historical_matches(path: &str) -> Result<HistoricalMatches> {
+    parse_historical_matches(&CsvTable::read(path)?).map_err(|e| anyhow!("{} ({})", e, path))
+}
+
+pub fn parse_historical_matches(table: &CsvTable) -> Result<HistoricalMatches> {
+    for column in ["HomeTeam", "AwayTeam", "FTHG", "FTAG"] {
+        if !table.headers.iter().any(|header| header == column) {
+            bail!(
+                "The results file has no '{}' column. It needs HomeTeam, AwayTeam, FTHG and FTAG.",
+                column
+            );
+        }
+    }
+    let mut historical_matches = HistoricalMatches {
+        columns: table.headers.clone(),
+        matches: vec![],
+        skipped: vec![],
+    };
+    for (i, row) in table.rows.iter().enumerate() {
+        // The header is line 1
+        let line = i + 2;
+        match HistoricalMatch::from_row(&table.headers, row, line) {
+            Ok(historical_match) => historical_matches.matches.push(historical_match),
+            Err(e) => historical_matches.skipped.push(SkippedRow {
+                line,
+                reason: e.to_string(),
+            }),
+        }
+    }
+    Ok(historical_matches)
+}
+
+/// Converts a date written DD/MM/YYYY or DD/MM/YY (as in football-data files, where two
+/// digit years are from 1990 on) or YYYY-MM-DD to YYYY-MM-DD
+pub fn parse_results_date(input: &str) -> Result<String> {
+    let parts: Vec<&str> = input.split('/').collect();
+    match parts[..] {
+        [day, month, year] => {
+            let year = match year.len() {
+                2 if year < "90" => format!("20{}", year),
+                2 => format!("19{}", year),
+                _ => year.to_owned(),
+            };
+            parse_date(&format!("{}-{:0>2}-{:0>2}", year, month, day))
+                .map_err(|_| anyhow!("Invalid date '{}'. Expected DD/MM/YYYY.", input))
+        }
+        _ => parse_date(input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_football_data_rows() {
+        let table = CsvTable::parse(
+            "\u{feff}Div,Date,HomeTeam,AwayTeam,FTHG,FTAG,HTHG,HTAG,PSH\r\n\
+             E0,11/08/2023,Burnley,Man City,0,3,0,2,8.75\r\n\
+             E0,12/08/23,\"Sheffield, United\",Crystal Palace,0,1,,,2.6\r\n\
+             ,,,,,,,,\r\n",
+        )
+        .unwrap();
+        assert_eq!(table.rows.len(), 2);
+        let matches = parse_historical_matches(&table).unwrap().matches;
+        assert_eq!(matches[0].date.as_deref(), Some("2023-08-11"));
+        assert_eq!(matches[0].full_time, Score::new(0, 3));
+        assert_eq!(matches[0].half_time, Some(Score::new(0, 2)));
+        assert_eq!(
+            matches[0].get_number("PSH").unwrap(),
+            Some(Rational::new(35, 4).unwrap())
+        );
+        assert_eq!(matches[1].get_name(), "Sheffield, United v Crystal Palace");
+        assert_eq!(matches[1].date.as_deref(), Some("2023-08-12"));
+        assert_eq!(matches[1].half_time, None);
+        assert_eq!(matches[1].get_number("PSCH").unwrap(), None);
+    }
+
+    #[test]
+    fn test_skips_unreadable_rows() {
+        let table = CsvTable::parse(
+            "HomeTeam,AwayTeam,FTHG,FTAG\n\
+             Arsenal,Chelsea,2,1\n\
+             Leeds,Everton,,\n\
+             Spurs,Fulham,two,0\n",
+        )
+        .unwrap();
+        let historical_matches = parse_historical_matches(&table).unwrap();
+        assert!(historical_matches.has_column("FTHG"));
+        assert_eq!(historical_matches.matches.len(), 1);
+        assert_eq!(
+            historical_matches
+                .skipped
+                .iter()
+                .map(|skipped| skipped.line)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        assert_eq!(
+            historical_matches.skipped[0].reason,
+            "The match on line 3 has no 'FTHG' value."
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use crate::market_specifiers::HomeAwayDrawSide;
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// A scoreline, e.g. the current score of a match in play
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Score {
+    pub home_goals: usize,
+    pub away_goals: usize,
+}
+
+impl Score {
+    pub fn new(home_goals: usize, away_goals: usize) -> Self {
+        Self {
+            home_goals,
+            away_goals,
+        }
+    }
+
+    /// Whether a match currently at `self` can still finish `home_goals`-`away_goals`
+    pub fn can_reach(&self, home_goals: usize, away_goals: usize) -> bool {
+        home_goals >= self.home_goals && away_goals >= self.away_goals
+    }
+
+    /// Goals scored since the match was at `earlier`, or `None` if it can't have been
+    pub fn goals_since(&self, earlier: Score) -> Option<Score> {
+        Some(Score::new(
+            self.home_goals.checked_sub(earlier.home_goals)?,
+            self.away_goals.checked_sub(earlier.away_goals)?,
+        ))
+    }
+
+    pub fn result(&self) -> HomeAwayDrawSide {
+        if self.home_goals > self.away_goals {
+            HomeAwayDrawSide::Home
+        } else if self.away_goals > self.home_goals {
+            HomeAwayDrawSide::Away
+        } else {
+            HomeAwayDrawSide::Draw
+        }
+    }
+}
+
+impl FromStr for Score {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Score> {
+        let invalid = || {
+            anyhow!(
+                "Invalid score '{}'. Scores are home goals then away goals, for example 1-0 or 2:2.",
+                input
+            )
+        };
+
+        let (home, away) = input.trim().split_once(['-', ':']).ok_or_else(invalid)?;
+        let home_goals = home.trim().parse().map_err(|_| invalid())?;
+        let away_goals = away.trim().parse().map_err(|_| invalid())?;
+        Ok(Score::new(home_goals, away_goals))
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.home_goals, self.away_goals)
+    }
+}
@@ -0,0 +1,585 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bet_types::half_time_full_time::HalfTimeFullTime;
+use crate::bet_types::Market;
+use crate::exchange::{ExchangeBet, JsonExchangeBet};
+use crate::market_specifiers::{BackLay, HalfTimeFullTimeSide, Line, Period};
+use crate::position::{
+    parse_current_score, parse_exchange_bet, parse_market, read_json_file, CommissionMarkets,
+    JsonBetSpec,
+};
+use crate::rational::Rational;
+use crate::score::Score;
+
+/// A bet to settle: a bet file bet, optionally on one half (`period`) or voided by the
+/// bookmaker. `ht-ft` bets are allowed too, as they settle on the half-time score.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JsonSettleBetSpec {
+    #[serde(flatten)]
+    pub bet: JsonBetSpec,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub void: bool,
+}
+
+#[derive(Deserialize)]
+pub struct JsonSettleBetsFile {
+    pub bets: Vec<JsonSettleBetSpec>,
+}
+
+#[derive(Serialize)]
+pub struct JsonSettlement {
+    pub market: String,
+    pub exchange: JsonExchangeBet,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_score: Option<String>,
+    pub outcome: String,
+    pub returned: f64,
+    pub profit: f64,
+}
+
+#[derive(Serialize)]
+pub struct JsonSettlements {
+    pub score: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub half_time_score: Option<String>,
+    pub settlements: Vec<JsonSettlement>,
+    pub total_at_risk: f64,
+    pub total_returned: f64,
+    pub total_profit: f64,
+}
+
+/// How a bet settled, from the point of view of whoever placed it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    HalfWin,
+    Push,
+    HalfLoss,
+    Loss,
+    Void,
+}
+
+impl Outcome {
+    /// The outcome for an even money payoff per unit staked, e.g. 0.5 for a half-won Asian
+    /// handicap
+    pub fn from_payoff(payoff: Rational) -> Self {
+        let half = Rational::from_quarters(2);
+        if payoff >= Rational::one() {
+            Outcome::Win
+        } else if payoff >= half {
+            Outcome::HalfWin
+        } else if payoff > -half {
+            Outcome::Push
+        } else if payoff > -Rational::one() {
+            Outcome::HalfLoss
+        } else {
+            Outcome::Loss
+        }
+    }
+
+    /// The outcome for whoever placed a bet whose market has the even money payoff `payoff`:
+    /// a layer wins when the backer loses
+    pub fn from_bet_payoff(payoff: Rational, back_lay: BackLay) -> Self {
+        match back_lay {
+            BackLay::Back => Outcome::from_payoff(payoff),
+            BackLay::Lay => Outcome::from_payoff(-payoff),
+        }
+    }
+
+    /// Short label for an outcome grid cell
+    pub fn letter(&self) -> &'static str {
+        match *self {
+            Outcome::Win => "W",
+            Outcome::HalfWin => "½W",
+            Outcome::Push => "P",
+            Outcome::HalfLoss => "½L",
+            Outcome::Loss => "L",
+            Outcome::Void => "V",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Win => write!(f, "Win"),
+            Outcome::HalfWin => write!(f, "Half-win"),
+            Outcome::Push => write!(f, "Push"),
+            Outcome::HalfLoss => write!(f, "Half-loss"),
+            Outcome::Loss => write!(f, "Loss"),
+            Outcome::Void => write!(f, "Void"),
+        }
+    }
+}
+
+/// What a bet is settled on. Score markets go through the same payoff functions as their
+/// grids, on the score of their period.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettlementMarket {
+    Score(Market, Period),
+    HalfTimeFullTime(HalfTimeFullTimeSide),
+}
+
+impl SettlementMarket {
+    /// Payoff (per unit staked at even money) given the result of the match. `current_score`
+    /// is the score in the market's period when the bet was placed in play.
+    pub fn get_payoff(
+        &self,
+        full_time: Score,
+        half_time: Option<Score>,
+        current_score: Option<Score>,
+    ) -> Result<Rational> {
+        let half_time =
+            || half_time.ok_or_else(|| anyhow!("Settling '{}' needs the half-time score.", self));
+        match self {
+            SettlementMarket::Score(market, Period::FullTime) => {
+                market.get_payoff(current_score, full_time.home_goals, full_time.away_goals)
+            }
+            SettlementMarket::Score(market, Period::FirstHalf) => {
+                let half_time = half_time()?;
+                market.get_payoff(current_score, half_time.home_goals, half_time.away_goals)
+            }
+            SettlementMarket::Score(market, Period::SecondHalf) => market.get_second_half_payoff(
+                half_time()?,
+                current_score,
+                full_time.home_goals,
+                full_time.away_goals,
+            ),
+            SettlementMarket::HalfTimeFullTime(side) => {
+                Ok(HalfTimeFullTime::get_payoff(side, half_time()?, full_time))
+            }
+        }
+    }
+
+    /// Whether bets on `self` and `other` are on one exchange market, whatever selection each
+    /// backs
+    pub fn is_same_exchange_market(&self, other: &SettlementMarket) -> bool {
+        match (self, other) {
+            (
+                SettlementMarket::Score(market, period),
+                SettlementMarket::Score(other_market, other_period),
+            ) => period == other_period && market.is_same_exchange_market(other_market),
+            (SettlementMarket::HalfTimeFullTime(_), SettlementMarket::HalfTimeFullTime(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Name of the bet type, e.g. "Asian Handicap"
+    pub fn get_type_name(&self) -> &'static str {
+        match self {
+            SettlementMarket::Score(market, _) => market.get_type_name(),
+            SettlementMarket::HalfTimeFullTime(_) => "Half-time/Full-time",
+        }
+    }
+
+    /// The handicap or goals line, for markets that have one
+    pub fn get_line(&self) -> Option<Line> {
+        match self {
+            SettlementMarket::Score(market, _) => market.get_line(),
+            SettlementMarket::HalfTimeFullTime(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for SettlementMarket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettlementMarket::Score(market, Period::FullTime) => write!(f, "{}", market),
+            SettlementMarket::Score(market, period) => write!(f, "{} ({})", market, period),
+            SettlementMarket::HalfTimeFullTime(side) => {
+                write!(f, "{}: {}", self.get_type_name(), side)
+            }
+        }
+    }
+}
+
+/// A bet to settle. `current_score` is the score (in the bet's period) when it was placed in
+/// play.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettleBet {
+    pub market: SettlementMarket,
+    pub exchange_bet: ExchangeBet,
+    pub current_score: Option<Score>,
+    pub void: bool,
+}
+
+impl SettleBet {
+    pub fn from_json(spec: &JsonSettleBetSpec) -> Result<Self> {
+        let period = match &spec.period {
+            None => Period::FullTime,
+            Some(period) => Period::from_str(period).map_err(|_| anyhow!("Unsupported period '{}'. Must be one of 'full-time', 'first-half' or 'second-half' ('ft', '1h' or '2h').", period))?,
+        };
+        let market = match spec.bet.bet_type.as_str() {
+            "ht-ft" if period != Period::FullTime => {
+                bail!("Half-time/full-time bets settle on the whole match, so they can't have a 'period'.")
+            }
+            "ht-ft" => SettlementMarket::HalfTimeFullTime(
+                HalfTimeFullTimeSide::from_str(&spec.bet.side).map_err(|_| {
+                    anyhow!(
+                        "Invalid side '{}' for ht-ft bet. Should be a half-time and a full-time result, for example 'd/h'.",
+                        spec.bet.side
+                    )
+                })?,
+            ),
+            _ => SettlementMarket::Score(parse_market(&spec.bet)?, period),
+        };
+        let current_score = parse_current_score(&spec.bet)?;
+        if current_score.is_some() && matches!(market, SettlementMarket::HalfTimeFullTime(_)) {
+            bail!("Half-time/full-time bets settle on the whole match, so they can't have a 'current_score'.");
+        }
+        Ok(Self {
+            market,
+            exchange_bet: parse_exchange_bet(&spec.bet)?,
+            current_score,
+            void: spec.void,
+        })
+    }
+
+    /// Settles the bet on its own on the final score (and the half-time score, which bets on a
+    /// half and half-time/full-time bets need)
+    pub fn settle(&self, full_time: Score, half_time: Option<Score>) -> Result<Settlement> {
+        Ok(settle_bets(&[self], full_time, half_time)?.remove(0))
+    }
+
+    /// Whether the bets are netted against each other before commission: bets on any
+    /// selection of one exchange market, placed at the same score with the same commission
+    pub fn is_same_commission_market(&self, other: &SettleBet) -> bool {
+        self.market.is_same_exchange_market(&other.market)
+            && self.current_score == other.current_score
+            && self.exchange_bet.commission == other.exchange_bet.commission
+    }
+}
+
+/// Settles bets placed with one bookmaker together on the final (and half-time) score, with
+/// commission taken once from the net winnings of each market
+pub fn settle_bets(
+    bets: &[&SettleBet],
+    full_time: Score,
+    half_time: Option<Score>,
+) -> Result<Vec<Settlement>> {
+    check_half_time_score(full_time, half_time)?;
+    let mut outcomes = vec![];
+    let mut gross_profits = vec![];
+    for bet in bets {
+        if bet.void {
+            outcomes.push(Outcome::Void);
+            gross_profits.push(Rational::zero());
+            continue;
+        }
+        let payoff = bet
+            .market
+            .get_payoff(full_time, half_time, bet.current_score)?;
+        outcomes.push(Outcome::from_bet_payoff(payoff, bet.exchange_bet.back_lay));
+        gross_profits.push(bet.exchange_bet.get_gross_profit(payoff)?);
+    }
+    let profits = CommissionMarkets::group(
+        bets,
+        |bet, other| bet.is_same_commission_market(other),
+        |bet| bet.exchange_bet.commission,
+    )
+    .take_commission(&gross_profits)?;
+    bets.iter()
+        .zip(outcomes)
+        .zip(profits)
+        .map(|((bet, outcome), profit)| {
+            Ok(Settlement {
+                outcome,
+                profit,
+                returned: bet.exchange_bet.liability()?.checked_add(profit)?,
+            })
+        })
+        .collect()
+}
+
+impl fmt::Display for SettleBet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.market, self.exchange_bet)?;
+        if let Some(current_score) = self.current_score {
+            write!(f, ", placed at {}", current_score)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a bet settled, its profit, and what it returns: the amount at risk plus the profit
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settlement {
+    pub outcome: Outcome,
+    pub profit: Rational,
+    pub returned: Rational,
+}
+
+// Checks the half-time score could have led to the final score
+fn check_half_time_score(full_time: Score, half_time: Option<Score>) -> Result<()> {
+    match half_time {
+        Some(half_time) if !half_time.can_reach(full_time.home_goals, full_time.away_goals) => {
+            bail!(
+                "The half-time score {} can't lead to the final score {}.",
+                half_time,
+                full_time
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads the bets of a settle bets file
+pub fn read_settle_bets_file(path: &str) -> Result<Vec<SettleBet>> {
+    let bets_file: JsonSettleBetsFile = read_json_file(path)?;
+    if bets_file.bets.is_empty() {
+        bail!("The bets file '{}' has no 'bets'.", path);
+    }
+    bets_file.bets.iter().map(SettleBet::from_json).collect()
+}
+
+/// Settled bets, with their totals
+pub struct Settlements {
+    pub full_time: Score,
+    pub half_time: Option<Score>,
+    pub settlements: Vec<(SettleBet, Settlement)>,
+    // Totals of the amounts at risk, the amounts returned and the profits
+    totals: (Rational, Rational, Rational),
+}
+
+impl Settlements {
+    /// Settles `bets`, taken to be placed with one bookmaker, so commission is taken once
+    /// from the net winnings of each market
+    pub fn new(bets: Vec<SettleBet>, full_time: Score, half_time: Option<Score>) -> Result<Self> {
+        let settled = settle_bets(&bets.iter().collect::<Vec<_>>(), full_time, half_time)?;
+        let settlements: Vec<(SettleBet, Settlement)> = bets.into_iter().zip(settled).collect();
+        let totals = settlements.iter().try_fold(
+            (Rational::zero(), Rational::zero(), Rational::zero()),
+            |(at_risk, returned, profit), (bet, settlement)| -> Result<_> {
+                Ok((
+                    at_risk.checked_add(bet.exchange_bet.liability()?)?,
+                    returned.checked_add(settlement.returned)?,
+                    profit.checked_add(settlement.profit)?,
+                ))
+            },
+        )?;
+        Ok(Self {
+            full_time,
+            half_time,
+            settlements,
+            totals,
+        })
+    }
+
+    /// Totals of the amounts at risk, the amounts returned and the profits
+    pub fn get_totals(&self) -> (Rational, Rational, Rational) {
+        self.totals
+    }
+
+    pub fn print_text(&self) {
+        match self.half_time {
+            Some(half_time) => println!("Settled at {} (half time {}):", self.full_time, half_time),
+            None => println!("Settled at {}:", self.full_time),
+        }
+        for (bet, settlement) in &self.settlements {
+            println!(
+                "  {}: {}, returned {}, profit {}",
+                bet, settlement.outcome, settlement.returned, settlement.profit
+            );
+        }
+        let (at_risk, returned, profit) = self.get_totals();
+        println!();
+        println!(
+            "Total at risk {}, returned {}, profit {}",
+            at_risk, returned, profit
+        );
+    }
+
+    pub fn to_json(&self) -> JsonSettlements {
+        let (at_risk, returned, profit) = self.get_totals();
+        JsonSettlements {
+            score: self.full_time.to_string(),
+            half_time_score: self.half_time.map(|half_time| half_time.to_string()),
+            settlements: self
+                .settlements
+                .iter()
+                .map(|(bet, settlement)| JsonSettlement {
+                    market: bet.market.to_string(),
+                    exchange: bet.exchange_bet.to_json(),
+                    current_score: bet.current_score.map(|score| score.to_string()),
+                    outcome: settlement.outcome.to_string(),
+                    returned: settlement.returned.to_f64(),
+                    profit: settlement.profit.to_f64(),
+                })
+                .collect(),
+            total_at_risk: at_risk.to_f64(),
+            total_returned: returned.to_f64(),
+            total_profit: profit.to_f64(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::GridBounds;
+
+    fn bet(json: &str) -> SettleBet {
+        SettleBet::from_json(&serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_settles_like_the_grid() {
+        let quarter = bet(
+            r#"{"bet_type": "asian-handicap", "side": "home", "handicap": -0.75, "odds": 1.9, "stake": 100}"#,
+        );
+        assert_eq!(
+            quarter.settle(Score::new(1, 0), None).unwrap(),
+            Settlement {
+                outcome: Outcome::HalfWin,
+                profit: Rational::from_integer(45),
+                returned: Rational::from_integer(145),
+            }
+        );
+        let lay = bet(
+            r#"{"bet_type": "over-under", "side": "over", "goals": 2.25, "lay": true, "odds": 2, "stake": 10}"#,
+        );
+        assert_eq!(
+            lay.settle(Score::new(1, 1), None).unwrap().outcome,
+            Outcome::HalfWin
+        );
+        assert_eq!(
+            lay.settle(Score::new(2, 1), None).unwrap().outcome,
+            Outcome::Loss
+        );
+
+        let void = bet(r#"{"bet_type": "win-draw-win", "side": "h", "void": true}"#);
+        let settlements = Settlements::new(vec![quarter, void], Score::new(2, 2), None).unwrap();
+        assert_eq!(settlements.settlements[0].1.outcome, Outcome::Loss);
+        assert_eq!(settlements.settlements[1].1.outcome, Outcome::Void);
+        assert_eq!(
+            settlements.get_totals(),
+            (
+                Rational::from_integer(101),
+                Rational::one(),
+                Rational::from_integer(-100)
+            )
+        );
+    }
+
+    #[test]
+    fn test_commission_on_net_market_winnings() {
+        let home = bet(
+            r#"{"bet_type": "win-draw-win", "side": "h", "odds": 3, "stake": 10, "commission": 0.05}"#,
+        );
+        let draw = bet(
+            r#"{"bet_type": "win-draw-win", "side": "d", "odds": 3.5, "stake": 10, "commission": 0.05}"#,
+        );
+        let settlements = Settlements::new(vec![home, draw], Score::new(1, 0), None).unwrap();
+        // The commission on the 10 won on the market comes out of the winning bet
+        assert_eq!(
+            settlements.settlements[0].1.profit,
+            Rational::new(195, 10).unwrap()
+        );
+        assert_eq!(
+            settlements.settlements[1].1.profit,
+            Rational::from_integer(-10)
+        );
+        assert_eq!(settlements.get_totals().2, Rational::new(95, 10).unwrap());
+    }
+
+    #[test]
+    fn test_outcome_grid_matches_settlement() {
+        let lay = bet(
+            r#"{"bet_type": "asian-handicap", "side": "away", "handicap": 0.25, "lay": true, "odds": 1.8, "stake": 10}"#,
+        );
+        let market = match &lay.market {
+            SettlementMarket::Score(market, _) => market,
+            _ => unreachable!(),
+        };
+        let bounds = GridBounds::square(5);
+        let mut grid = market.get_payoff_grid(&bounds, None).unwrap();
+        grid.set_exchange_bet(&lay.exchange_bet).unwrap();
+        for (home_goals, away_goals) in bounds.scores() {
+            let settlement = lay
+                .settle(Score::new(home_goals, away_goals), None)
+                .unwrap();
+            assert_eq!(
+                grid.get_outcome(home_goals, away_goals),
+                Some(settlement.outcome)
+            );
+            assert_eq!(
+                grid.get_payoff(home_goals, away_goals),
+                Some(settlement.profit)
+            );
+        }
+        // The backer half-wins at 0-0
+        assert_eq!(grid.get_outcome(0, 0), Some(Outcome::HalfLoss));
+    }
+
+    #[test]
+    fn test_half_markets_need_the_half_time_score() {
+        let second_half = bet(
+            r#"{"bet_type": "win-draw-win", "side": "a", "period": "2h", "odds": 3, "stake": 10}"#,
+        );
+        let ht_ft = bet(r#"{"bet_type": "ht-ft", "side": "h/d"}"#);
+        assert!(second_half.settle(Score::new(1, 1), None).is_err());
+
+        let half_time = Some(Score::new(1, 0));
+        let settlement = second_half.settle(Score::new(1, 1), half_time).unwrap();
+        assert_eq!(settlement.profit, Rational::from_integer(20));
+        assert_eq!(
+            ht_ft.settle(Score::new(1, 1), half_time).unwrap().outcome,
+            Outcome::Win
+        );
+        assert!(Settlements::new(vec![ht_ft], Score::new(0, 1), half_time).is_err());
+
+        // First-half bets settle on the half-time score alone
+        let first_half = bet(
+            r#"{"bet_type": "over-under", "side": "over", "goals": 0.5, "period": "1h", "odds": 2, "stake": 10}"#,
+        );
+        assert_eq!(
+            first_half
+                .settle(Score::new(1, 1), half_time)
+                .unwrap()
+                .outcome,
+            Outcome::Win
+        );
+        assert_eq!(
+            first_half
+                .settle(Score::new(1, 1), Some(Score::new(0, 0)))
+                .unwrap()
+                .outcome,
+            Outcome::Loss
+        );
+    }
+
+    #[test]
+    fn test_in_play_handicaps_settle_from_the_current_score() {
+        // Home -0.5 at 1-0 needs the home side to win the rest of the match
+        let in_play = bet(
+            r#"{"bet_type": "asian-handicap", "side": "home", "handicap": -0.5, "odds": 2, "stake": 10, "current_score": "1-0"}"#,
+        );
+        assert_eq!(
+            in_play.settle(Score::new(1, 0), None).unwrap().outcome,
+            Outcome::Loss
+        );
+        assert_eq!(
+            in_play.settle(Score::new(2, 1), None).unwrap().outcome,
+            Outcome::Loss
+        );
+        assert_eq!(
+            in_play.settle(Score::new(3, 1), None).unwrap().outcome,
+            Outcome::Win
+        );
+        // Goals can't be taken back
+        assert!(in_play.settle(Score::new(0, 0), None).is_err());
+
+        assert!(SettleBet::from_json(
+            &serde_json::from_str(
+                r#"{"bet_type": "ht-ft", "side": "h/h", "current_score": "1-0"}"#
+            )
+            .unwrap()
+        )
+        .is_err());
+    }
+}
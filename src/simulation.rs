@@ -0,0 +1,713 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::kelly::Kelly;
+use crate::position::{get_position_profit, JsonPositionBet, PositionBet};
+use crate::rational::Rational;
+use crate::score::Score;
+
+const PERCENTILES: [f64; 9] = [1.0, 5.0, 10.0, 25.0, 50.0, 75.0, 90.0, 95.0, 99.0];
+const HISTOGRAM_BINS: usize = 20;
+const HISTOGRAM_WIDTH: usize = 40;
+
+#[derive(Serialize)]
+pub struct JsonPercentile {
+    pub percentile: f64,
+    pub value: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct JsonHistogramBin {
+    pub from: f64,
+    pub to: f64,
+    pub samples: usize,
+    pub share: f64,
+}
+
+#[derive(Serialize)]
+pub struct JsonBankrollSimulation {
+    pub staking: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kelly_fraction: Option<f64>,
+    pub bets: Vec<JsonPositionBet>,
+    pub starting_bankroll: f64,
+    pub matches: usize,
+    pub paths: usize,
+    pub mean_final_bankroll: f64,
+    pub final_bankroll_percentiles: Vec<JsonPercentile>,
+    pub probability_of_loss: f64,
+    pub probability_of_ruin: f64,
+    pub max_drawdown_percentiles: Vec<JsonPercentile>,
+}
+
+#[derive(Serialize)]
+pub struct JsonSimulation {
+    pub seed: u64,
+    pub bets: Vec<JsonPositionBet>,
+    pub samples: usize,
+    pub expected_profit: f64,
+    pub mean_profit: f64,
+    pub standard_deviation: f64,
+    pub probability_of_loss: f64,
+    pub percentiles: Vec<JsonPercentile>,
+    pub histogram: Vec<JsonHistogramBin>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bankroll: Option<JsonBankrollSimulation>,
+}
+
+// A seedable pseudo-random number generator (SplitMix64), so a simulation can be repeated
+// exactly from its seed
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A number in [0, 1), from the top 53 bits
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Final scores to draw from, by their cumulative probability
+struct ScoreSampler {
+    scores: Vec<Score>,
+    cumulative: Vec<f64>,
+}
+
+impl ScoreSampler {
+    fn new(score_probabilities: &[(Score, f64)]) -> Self {
+        let mut total = 0.0;
+        let mut cumulative = vec![];
+        for (_, probability) in score_probabilities {
+            total += probability;
+            cumulative.push(total);
+        }
+        Self {
+            scores: score_probabilities
+                .iter()
+                .map(|(score, _)| *score)
+                .collect(),
+            // The model's scores can miss a sliver of probability far out in the tail, which
+            // is shared out over the rest
+            cumulative: cumulative.into_iter().map(|sum| sum / total).collect(),
+        }
+    }
+
+    // Index of a random score
+    fn sample(&self, rng: &mut Rng) -> usize {
+        let draw = rng.next_f64();
+        self.cumulative
+            .partition_point(|cumulative| *cumulative <= draw)
+            .min(self.scores.len() - 1)
+    }
+}
+
+/// Simulated values, sorted, to read percentiles and shares from
+pub struct Distribution {
+    values: Vec<f64>,
+}
+
+impl Distribution {
+    fn new(mut values: Vec<f64>) -> Self {
+        values.sort_by(f64::total_cmp);
+        Self { values }
+    }
+
+    pub fn get_mean(&self) -> f64 {
+        self.values.iter().sum::<f64>() / self.values.len() as f64
+    }
+
+    pub fn get_standard_deviation(&self) -> f64 {
+        let mean = self.get_mean();
+        let variance = self
+            .values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / self.values.len() as f64;
+        variance.sqrt()
+    }
+
+    /// The smallest value at least `percentile`% of the values are at or below, if there are
+    /// any values
+    pub fn get_percentile(&self, percentile: f64) -> Option<f64> {
+        if self.values.is_empty() {
+            return None;
+        }
+        let rank = (percentile / 100.0 * self.values.len() as f64).ceil() as usize;
+        Some(self.values[rank.clamp(1, self.values.len()) - 1])
+    }
+
+    /// Share of the values below `threshold`
+    pub fn get_share_below(&self, threshold: f64) -> f64 {
+        self.values.partition_point(|value| *value < threshold) as f64 / self.values.len() as f64
+    }
+
+    // Bins from, to and the number of values in each: one per value if there are only a few
+    // different ones (bets settle on a handful of profits), equal widths otherwise
+    fn get_histogram(&self) -> Vec<(f64, f64, usize)> {
+        let mut distinct: Vec<(f64, f64, usize)> = vec![];
+        for value in &self.values {
+            match distinct.last_mut() {
+                Some((last, _, count)) if *last == *value => *count += 1,
+                _ => distinct.push((*value, *value, 1)),
+            }
+            if distinct.len() > HISTOGRAM_BINS {
+                break;
+            }
+        }
+        if distinct.len() <= HISTOGRAM_BINS {
+            return distinct;
+        }
+
+        let (low, high) = (self.values[0], self.values[self.values.len() - 1]);
+        let width = (high - low) / HISTOGRAM_BINS as f64;
+        let mut bins: Vec<(f64, f64, usize)> = (0..HISTOGRAM_BINS)
+            .map(|i| (low + width * i as f64, low + width * (i + 1) as f64, 0))
+            .collect();
+        for value in &self.values {
+            let i = (((value - low) / width) as usize).min(HISTOGRAM_BINS - 1);
+            bins[i].2 += 1;
+        }
+        bins
+    }
+
+    fn percentiles_to_json(&self) -> Vec<JsonPercentile> {
+        PERCENTILES
+            .iter()
+            .map(|percentile| JsonPercentile {
+                percentile: *percentile,
+                value: self.get_percentile(*percentile),
+            })
+            .collect()
+    }
+
+    fn print_percentiles(&self, title: &str) {
+        println!("{:>10} {:>10}", "Percentile", title);
+        for percentile in PERCENTILES {
+            println!(
+                "{:>10} {:>10}",
+                format!("{}%", percentile),
+                self.get_percentile(percentile)
+                    .map_or("-".to_owned(), |value| format!("{:.2}", value))
+            );
+        }
+    }
+}
+
+/// How the stakes of a bankroll path are set for each match
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StakingPlan {
+    // The stakes of the bets file every time
+    Flat,
+    // The stakes of the bets file scaled with the bankroll, a fixed share of it
+    Proportional,
+    // (Fractional) Kelly stakes for the bankroll
+    Kelly,
+}
+
+impl FromStr for StakingPlan {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<StakingPlan, ()> {
+        match input {
+            "flat" => Ok(StakingPlan::Flat),
+            "f" => Ok(StakingPlan::Flat),
+            "proportional" => Ok(StakingPlan::Proportional),
+            "p" => Ok(StakingPlan::Proportional),
+            "kelly" => Ok(StakingPlan::Kelly),
+            "k" => Ok(StakingPlan::Kelly),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for StakingPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StakingPlan::Flat => write!(f, "Flat"),
+            StakingPlan::Proportional => write!(f, "Proportional"),
+            StakingPlan::Kelly => write!(f, "Kelly"),
+        }
+    }
+}
+
+/// Bankroll paths over a run of matches with the same bets, each match's score drawn from the
+/// model. A path is ruined when what is left can't cover the stakes (and liabilities for lays)
+/// of the next match, or there is nothing left.
+pub struct BankrollSimulation {
+    pub staking: StakingPlan,
+    pub kelly_fraction: f64,
+    // The bets on the first match, with the stakes of the plan
+    pub bets: Vec<PositionBet>,
+    pub starting_bankroll: Rational,
+    pub matches: usize,
+    pub paths: usize,
+    pub final_bankrolls: Distribution,
+    pub max_drawdowns: Distribution,
+    pub ruined_paths: usize,
+}
+
+impl BankrollSimulation {
+    /// Runs `paths` of `matches` matches each, starting from `starting_bankroll`, with the
+    /// stakes of `bets` or of the `staking` plan. `kelly_fraction` scales Kelly stakes.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        bets: Vec<PositionBet>,
+        score_probabilities: &[(Score, f64)],
+        staking: StakingPlan,
+        kelly_fraction: f64,
+        starting_bankroll: Rational,
+        matches: usize,
+        paths: usize,
+        seed: u64,
+    ) -> Result<Self> {
+        let bets = match staking {
+            StakingPlan::Kelly => {
+                Kelly::new(bets, score_probabilities, starting_bankroll, kelly_fraction)?.bets
+            }
+            _ => bets,
+        };
+        let at_risk = bets
+            .iter()
+            .try_fold(Rational::zero(), |at_risk, bet| {
+                at_risk.checked_add(bet.exchange_bet.liability()?)
+            })?
+            .to_f64();
+        let starting_bankroll_f64 = starting_bankroll.to_f64();
+        if at_risk > starting_bankroll_f64 {
+            bail!(
+                "The bets risk {:.2}, more than the starting bankroll of {}.",
+                at_risk,
+                starting_bankroll
+            );
+        }
+
+        let mut rng = Rng::new(seed);
+        let sampler = ScoreSampler::new(score_probabilities);
+        let profits: Vec<f64> = sampler
+            .scores
+            .iter()
+            .map(|score| {
+                Ok(get_position_profit(&bets, score.home_goals, score.away_goals)?.to_f64())
+            })
+            .collect::<Result<_>>()?;
+        let mut final_bankrolls = vec![];
+        let mut max_drawdowns = vec![];
+        let mut ruined_paths = 0;
+        for _ in 0..paths {
+            let mut bankroll = starting_bankroll_f64;
+            let mut peak = bankroll;
+            let mut max_drawdown: f64 = 0.0;
+            for _ in 0..matches {
+                let scale = match staking {
+                    StakingPlan::Flat => 1.0,
+                    _ => bankroll / starting_bankroll_f64,
+                };
+                // Allow for rounding in the bankroll, which is kept in floating point. A bankroll
+                // with nothing left is ruined even if it would stake nothing.
+                if at_risk * scale > bankroll + 1e-9 || bankroll <= 1e-9 {
+                    ruined_paths += 1;
+                    break;
+                }
+                bankroll += profits[sampler.sample(&mut rng)] * scale;
+                peak = peak.max(bankroll);
+                max_drawdown = max_drawdown.max((peak - bankroll) / peak);
+            }
+            final_bankrolls.push(bankroll);
+            max_drawdowns.push(max_drawdown);
+        }
+
+        Ok(Self {
+            staking,
+            kelly_fraction,
+            bets,
+            starting_bankroll,
+            matches,
+            paths,
+            final_bankrolls: Distribution::new(final_bankrolls),
+            max_drawdowns: Distribution::new(max_drawdowns),
+            ruined_paths,
+        })
+    }
+
+    pub fn get_probability_of_ruin(&self) -> f64 {
+        self.ruined_paths as f64 / self.paths as f64
+    }
+
+    pub fn print_text(&self) {
+        let staking = match self.staking {
+            StakingPlan::Flat => "flat stakes".to_owned(),
+            StakingPlan::Proportional => "stakes in proportion to the bankroll".to_owned(),
+            StakingPlan::Kelly => format!("{}x Kelly stakes", self.kelly_fraction),
+        };
+        println!(
+            "{} bankroll paths of {} matches from {}, with {}:",
+            self.paths, self.matches, self.starting_bankroll, staking
+        );
+        for bet in &self.bets {
+            println!("  {}", bet);
+        }
+        println!();
+        println!(
+            "Mean final bankroll: {:.2}",
+            self.final_bankrolls.get_mean()
+        );
+        println!(
+            "Probability of finishing below the starting bankroll: {:.2}%",
+            self.final_bankrolls
+                .get_share_below(self.starting_bankroll.to_f64())
+                * 100.0
+        );
+        println!(
+            "Probability of ruin: {:.2}%",
+            self.get_probability_of_ruin() * 100.0
+        );
+        let as_percentage = |percentile: f64| {
+            self.max_drawdowns
+                .get_percentile(percentile)
+                .map_or("-".to_owned(), |value| format!("{:.2}%", value * 100.0))
+        };
+        println!(
+            "Largest drawdown from a peak: median {}, 95th percentile {}",
+            as_percentage(50.0),
+            as_percentage(95.0)
+        );
+        println!();
+        self.final_bankrolls.print_percentiles("Bankroll");
+    }
+
+    pub fn to_json(&self) -> JsonBankrollSimulation {
+        JsonBankrollSimulation {
+            staking: self.staking.to_string(),
+            kelly_fraction: match self.staking {
+                StakingPlan::Kelly => Some(self.kelly_fraction),
+                _ => None,
+            },
+            bets: self.bets.iter().map(PositionBet::to_json).collect(),
+            starting_bankroll: self.starting_bankroll.to_f64(),
+            matches: self.matches,
+            paths: self.paths,
+            mean_final_bankroll: self.final_bankrolls.get_mean(),
+            final_bankroll_percentiles: self.final_bankrolls.percentiles_to_json(),
+            probability_of_loss: self
+                .final_bankrolls
+                .get_share_below(self.starting_bankroll.to_f64()),
+            probability_of_ruin: self.get_probability_of_ruin(),
+            max_drawdown_percentiles: self.max_drawdowns.percentiles_to_json(),
+        }
+    }
+}
+
+/// The profit of bets on one match, settled on final scores drawn from the model, for the
+/// spread of outcomes around the expected profit
+pub struct Simulation {
+    pub seed: u64,
+    pub bets: Vec<PositionBet>,
+    pub expected_profit: f64,
+    pub profits: Distribution,
+    pub bankroll: Option<BankrollSimulation>,
+}
+
+impl Simulation {
+    /// Settles `bets` on `samples` final scores drawn with the given probabilities, from the
+    /// random numbers of `seed`
+    pub fn new(
+        bets: Vec<PositionBet>,
+        score_probabilities: &[(Score, f64)],
+        samples: usize,
+        seed: u64,
+    ) -> Result<Self> {
+        let mut rng = Rng::new(seed);
+        let sampler = ScoreSampler::new(score_probabilities);
+        let score_profits: Vec<f64> = sampler
+            .scores
+            .iter()
+            .map(|score| {
+                Ok(get_position_profit(&bets, score.home_goals, score.away_goals)?.to_f64())
+            })
+            .collect::<Result<_>>()?;
+        let expected_profit = score_profits
+            .iter()
+            .zip(score_probabilities)
+            .map(|(profit, (_, probability))| profit * probability)
+            .sum();
+        let profits = (0..samples)
+            .map(|_| score_profits[sampler.sample(&mut rng)])
+            .collect();
+        Ok(Self {
+            seed,
+            bets,
+            expected_profit,
+            profits: Distribution::new(profits),
+            bankroll: None,
+        })
+    }
+
+    pub fn with_bankroll(mut self, bankroll: BankrollSimulation) -> Self {
+        self.bankroll = Some(bankroll);
+        self
+    }
+
+    pub fn print_text(&self) {
+        println!(
+            "{} simulated matches (seed {}) of:",
+            self.profits.values.len(),
+            self.seed
+        );
+        for bet in &self.bets {
+            println!("  {}", bet);
+        }
+        println!();
+        println!(
+            "Expected profit: {:.4}. Simulated mean {:.4}, standard deviation {:.4}.",
+            self.expected_profit,
+            self.profits.get_mean(),
+            self.profits.get_standard_deviation()
+        );
+        println!(
+            "Probability of a loss: {:.2}%",
+            self.profits.get_share_below(0.0) * 100.0
+        );
+        println!();
+        self.profits.print_percentiles("Profit");
+
+        let histogram = self.profits.get_histogram();
+        let largest = histogram
+            .iter()
+            .map(|(_, _, count)| *count)
+            .max()
+            .unwrap_or(1);
+        let labels: Vec<String> = histogram
+            .iter()
+            .map(|(from, to, _)| match from == to {
+                true => format!("{:.2}", from),
+                false => format!("{:.2} to {:.2}", from, to),
+            })
+            .collect();
+        let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+        println!();
+        println!("Profit histogram:");
+        for (label, (_, _, count)) in labels.iter().zip(&histogram) {
+            println!(
+                "{:>label_width$} | {:<HISTOGRAM_WIDTH$} {:>6.2}%",
+                label,
+                "#".repeat(count * HISTOGRAM_WIDTH / largest),
+                *count as f64 / self.profits.values.len() as f64 * 100.0
+            );
+        }
+
+        if let Some(bankroll) = &self.bankroll {
+            println!();
+            bankroll.print_text();
+        }
+    }
+
+    pub fn to_json(&self) -> JsonSimulation {
+        let samples = self.profits.values.len();
+        JsonSimulation {
+            seed: self.seed,
+            bets: self.bets.iter().map(PositionBet::to_json).collect(),
+            samples,
+            expected_profit: self.expected_profit,
+            mean_profit: self.profits.get_mean(),
+            standard_deviation: self.profits.get_standard_deviation(),
+            probability_of_loss: self.profits.get_share_below(0.0),
+            percentiles: self.profits.percentiles_to_json(),
+            histogram: self
+                .profits
+                .get_histogram()
+                .into_iter()
+                .map(|(from, to, count)| JsonHistogramBin {
+                    from,
+                    to,
+                    samples: count,
+                    share: count as f64 / samples as f64,
+                })
+                .collect(),
+            bankroll: self.bankroll.as_ref().map(BankrollSimulation::to_json),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bet_types::Market;
+    use crate::exchange::ExchangeBet;
+    use crate::market_specifiers::{BackLay, HomeAwayDrawSide};
+    use crate::probability::PoissonModel;
+
+    fn home_win_bet(odds: Rational, stake: Rational) -> PositionBet {
+        PositionBet {
+            market: Market::WinDrawWin(HomeAwayDrawSide::Home),
+            exchange_bet: ExchangeBet::new(BackLay::Back, odds, stake, Rational::zero()).unwrap(),
+            current_score: None,
+        }
+    }
+
+    #[test]
+    fn test_simulates_profit_and_bankroll() {
+        // A coin toss between a home win and a draw
+        let score_probabilities = [(Score::new(1, 0), 0.5), (Score::new(0, 0), 0.5)];
+        let bets = vec![home_win_bet(Rational::from_integer(2), Rational::one())];
+
+        let simulation = Simulation::new(bets.clone(), &score_probabilities, 10_000, 7).unwrap();
+        assert_eq!(simulation.expected_profit, 0.0);
+        let losses = simulation.profits.get_share_below(0.0);
+        assert!((losses - 0.5).abs() < 0.02);
+        assert_eq!(simulation.profits.get_percentile(1.0), Some(-1.0));
+        assert_eq!(simulation.profits.get_percentile(99.0), Some(1.0));
+        let losing_samples = (losses * 10_000.0).round() as usize;
+        assert_eq!(
+            simulation.profits.get_histogram(),
+            vec![
+                (-1.0, -1.0, losing_samples),
+                (1.0, 1.0, 10_000 - losing_samples)
+            ]
+        );
+        // The same seed draws the same scores
+        let again = Simulation::new(bets.clone(), &score_probabilities, 10_000, 7).unwrap();
+        assert_eq!(again.profits.values, simulation.profits.values);
+
+        // Staking the whole bankroll leaves nothing to stake on the second match after losing
+        // the first
+        let bankroll = BankrollSimulation::new(
+            bets.clone(),
+            &score_probabilities,
+            StakingPlan::Flat,
+            1.0,
+            Rational::one(),
+            2,
+            1000,
+            1,
+        )
+        .unwrap();
+        assert!((bankroll.get_probability_of_ruin() - 0.5).abs() < 0.05);
+        assert_eq!(bankroll.final_bankrolls.get_percentile(1.0), Some(0.0));
+
+        // A bet with no edge has no Kelly stake, so the bankroll never moves
+        let kelly = BankrollSimulation::new(
+            bets,
+            &score_probabilities,
+            StakingPlan::Kelly,
+            1.0,
+            Rational::from_integer(100),
+            10,
+            100,
+            1,
+        )
+        .unwrap();
+        assert_eq!(kelly.final_bankrolls.get_percentile(1.0), Some(100.0));
+        assert_eq!(kelly.get_probability_of_ruin(), 0.0);
+    }
+
+    #[test]
+    fn test_sampled_scores_follow_the_grid() {
+        let score_probabilities = PoissonModel::new(1.5, 1.1)
+            .unwrap()
+            .with_rho(-0.1)
+            .unwrap()
+            .get_score_probabilities(None);
+        let total: f64 = score_probabilities
+            .iter()
+            .map(|(_, probability)| probability)
+            .sum();
+        let sampler = ScoreSampler::new(&score_probabilities);
+        let mut rng = Rng::new(42);
+        let samples = 200_000;
+        let mut counts = vec![0; score_probabilities.len()];
+        for _ in 0..samples {
+            counts[sampler.sample(&mut rng)] += 1;
+        }
+        for ((_, probability), count) in score_probabilities.iter().zip(counts) {
+            let frequency = count as f64 / samples as f64;
+            assert!((frequency - probability / total).abs() < 0.005);
+        }
+    }
+
+    #[test]
+    fn test_percentiles_at_the_ends() {
+        let distribution = Distribution::new(vec![3.0, -2.0, 5.0, 1.0]);
+        assert_eq!(distribution.get_percentile(0.0), Some(-2.0));
+        assert_eq!(distribution.get_percentile(1.0), Some(-2.0));
+        assert_eq!(distribution.get_percentile(50.0), Some(1.0));
+        assert_eq!(distribution.get_percentile(100.0), Some(5.0));
+        assert_eq!(Distribution::new(vec![]).get_percentile(50.0), None);
+    }
+
+    #[test]
+    fn test_histogram_counts_every_sample() {
+        // Too many different values for a bar each, so they go in equal ranges
+        let mut rng = Rng::new(3);
+        let values: Vec<f64> = (0..1000).map(|_| rng.next_f64() * 10.0).collect();
+        let histogram = Distribution::new(values).get_histogram();
+        assert_eq!(histogram.len(), HISTOGRAM_BINS);
+        assert_eq!(
+            histogram.iter().map(|(_, _, count)| count).sum::<usize>(),
+            1000
+        );
+
+        // A handful of profits, a bar each
+        let score_probabilities = PoissonModel::new(1.5, 1.1)
+            .unwrap()
+            .get_score_probabilities(None);
+        let simulation = Simulation::new(
+            vec![home_win_bet(
+                Rational::new(21, 10).unwrap(),
+                Rational::one(),
+            )],
+            &score_probabilities,
+            5000,
+            11,
+        )
+        .unwrap();
+        let histogram = simulation.profits.get_histogram();
+        assert_eq!(histogram.len(), 2);
+        assert_eq!(
+            histogram.iter().map(|(_, _, count)| count).sum::<usize>(),
+            5000
+        );
+    }
+
+    #[test]
+    fn test_ruin_when_staking_the_whole_bankroll() {
+        let score_probabilities = [(Score::new(1, 0), 0.5), (Score::new(0, 0), 0.5)];
+        let bankroll = |stake: i64, staking: StakingPlan| {
+            BankrollSimulation::new(
+                vec![home_win_bet(
+                    Rational::from_integer(2),
+                    Rational::from_integer(stake),
+                )],
+                &score_probabilities,
+                staking,
+                1.0,
+                Rational::from_integer(10),
+                2,
+                10_000,
+                5,
+            )
+        };
+        // Losing the first match leaves nothing for the second, whether the stakes stay the
+        // same or shrink with the bankroll
+        for staking in [StakingPlan::Flat, StakingPlan::Proportional] {
+            let simulation = bankroll(10, staking).unwrap();
+            assert!((simulation.get_probability_of_ruin() - 0.5).abs() < 0.02);
+        }
+        assert!(bankroll(11, StakingPlan::Flat).is_err());
+    }
+}